@@ -0,0 +1,38 @@
+// Runtime apis giving clients read-only views over the nft modules
+
+use rstd::prelude::*;
+use codec::Codec;
+use crate::merkle::OwnershipProof;
+use crate::order::{OrderDetails, TradeRecord, ClosedOrder};
+
+client::decl_runtime_apis! {
+    pub trait NftsApi<AccountId, Hash> where
+        AccountId: Codec,
+        Hash: Codec,
+    {
+        // holders of a nft with the number of tokens each one holds, as of the queried block
+        fn class_holders(nft_id: Hash) -> Vec<(AccountId, u64)>;
+        // root of the ownership tree of a class, tokens created outside any nft use the default hash as class
+        fn ownership_root(class: Hash) -> Hash;
+        // proof that the current owner holds the token, checked with `merkle::verify_proof`
+        fn ownership_proof(token_id: Hash) -> Option<OwnershipProof<AccountId, Hash>>;
+    }
+
+    pub trait OrderApi<AccountId, Hash, AssetId, Balance, Moment> where
+        AccountId: Codec,
+        Hash: Codec,
+        AssetId: Codec,
+        Balance: Codec,
+        Moment: Codec,
+    {
+        // reserved balance of an account in an asset next to what its open bid orders still owe, the
+        // two differ by english auction bids, sealed bid deposits, revealed sealed bids and vault buyout bids
+        fn bid_reservations(who: AccountId, asset: AssetId) -> (Balance, Balance);
+        // open ask and bid orders of an account with what is left of each
+        fn account_orders(who: AccountId) -> Vec<OrderDetails<Hash, AssetId, Balance, Moment>>;
+        // a page of at most 100 trades from seq `start` on, only the latest 10000 trades are kept
+        fn trade_records(start: u64, count: u32) -> Vec<TradeRecord<AccountId, Hash, AssetId, Balance, Moment>>;
+        // latest closed orders of an account, oldest first
+        fn closed_orders(who: AccountId) -> Vec<ClosedOrder<Hash, AssetId, Balance, Moment>>;
+    }
+}
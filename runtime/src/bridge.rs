@@ -0,0 +1,513 @@
+// Lock-and-mint bridge moving tokens to and from other chains.
+// Exporting locks a native token in the bridge account (or burns a wrapped one on its way home)
+// and emits a proof hash for relayers. Importing needs `threshold` approvals of the relayer set,
+// then either releases a token exported before or mints a wrapped token under a mirrored nft.
+
+use codec::{Encode, Decode};
+use system::{ensure_signed, ensure_root};
+use sr_primitives::traits::{Hash, Bounded, AccountIdConversion};
+use sr_primitives::ModuleId;
+use rstd::prelude::*;
+use support::{
+    decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue,
+    traits::Get,
+};
+use crate::erc721;
+use crate::nfts;
+use crate::nfts::BalanceOf;
+
+// account holding exported tokens and issuing wrapped ones
+const BRIDGE_MODULE_ID: ModuleId = ModuleId(*b"nft/brdg");
+const PROOF_DOMAIN: &[u8] = b"nft-bridge:export";
+const WRAPPED_CLASS_SYMBOL: &[u8] = b"wrapped";
+// recipients on other chains are opaque bytes, long enough for any account format
+const MAX_RECIPIENT_LEN: usize = 64;
+
+// burn reason of wrapped tokens leaving for their source chain
+pub const BRIDGE_BURN_REASON: u8 = 254;
+
+pub trait Trait: nfts::Trait {
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    // id of this chain as known by the relayers
+    type ChainId: Get<u32>;
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct ExportRecord<AccountId> {
+    owner: AccountId,
+    dest_chain: u32,
+    recipient: Vec<u8>,
+    nonce: u64,
+}
+
+// a token arriving from another chain, as relayed by the relayers
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct ImportMessage<AccountId, Hash> {
+    pub source_chain: u32,
+    pub nonce: u64, // export nonce on the source chain
+    pub class: Hash, // nft id the token belongs to on its home chain
+    pub token_id: Hash, // token id on its home chain
+    pub symbol: Vec<u8>,
+    pub recipient: AccountId,
+}
+
+// a wrapped token and the token it stands for
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct WrappedOrigin<Hash> {
+    source_chain: u32,
+    class: Hash,
+    token_id: Hash,
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        <T as system::Trait>::AccountId,
+        <T as system::Trait>::Hash
+    {
+        // threshold
+        RelayersSet(u32),
+        // owner, dest_chain, recipient, class, token_id, nonce, proof
+        TokenExported(AccountId, u32, Vec<u8>, Hash, Hash, u64, Hash),
+        // relayer, message hash, approvals
+        ImportApproved(AccountId, Hash, u32),
+        // recipient, message hash, local token_id
+        TokenImported(AccountId, Hash, Hash),
+        // recipient, message hash, token_id that expired while exported and was reaped instead
+        ImportedTokenExpired(AccountId, Hash, Hash),
+    }
+);
+
+decl_storage! {
+    trait Store for Module<T: Trait> as BridgeStorage {
+        Relayers get(relayers): Vec<T::AccountId>;
+        // approvals needed to execute an import
+        Threshold get(threshold): u32;
+        // native token id => export locking it
+        Exports get(get_export): map T::Hash => Option<ExportRecord<T::AccountId>>;
+        // local wrapped token id => the token it stands for
+        WrappedTokens get(get_wrapped): map T::Hash => Option<WrappedOrigin<T::Hash>>;
+        // (source chain, source nft id) => mirrored local nft id
+        MirroredClasses get(get_mirrored_class): map (u32, T::Hash) => Option<T::Hash>;
+        // message hash => relayers approving it
+        ImportApprovals get(get_import_approvals): map T::Hash => Vec<T::AccountId>;
+        ProcessedImports get(is_import_processed): map T::Hash => bool;
+        Nonce: u64;
+    }
+}
+
+decl_module! {
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        fn deposit_event() = default;
+
+        const ChainId: u32 = T::ChainId::get();
+
+        pub fn set_relayers(origin, relayers: Vec<T::AccountId>, threshold: u32) -> Result {
+            ensure_root(origin)?;
+            Self::_set_relayers(relayers, threshold)
+        }
+
+        pub fn export_token(origin, token_id: T::Hash, dest_chain: u32, recipient: Vec<u8>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_export_token(sender, token_id, dest_chain, recipient)
+        }
+
+        // executes the import once `threshold` relayers approved the same message
+        pub fn import_approve(origin, message: ImportMessage<T::AccountId, T::Hash>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_import_approve(sender, message)
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    pub fn account_id() -> T::AccountId {
+        BRIDGE_MODULE_ID.into_account()
+    }
+
+    // hash relayers check before relaying an export to `dest_chain`
+    pub fn export_proof(dest_chain: u32, recipient: &Vec<u8>, class: &T::Hash, token_id: &T::Hash, nonce: u64) -> T::Hash {
+        (PROOF_DOMAIN, T::ChainId::get(), dest_chain, recipient, class, token_id, nonce)
+            .using_encoded(<T as system::Trait>::Hashing::hash)
+    }
+
+    fn _set_relayers(relayers: Vec<T::AccountId>, threshold: u32) -> Result {
+        ensure!(threshold > 0, "threshold can not be zero");
+        ensure!(threshold as usize <= relayers.len(), "threshold exceeds the relayer count");
+        <Relayers<T>>::put(relayers);
+        Threshold::put(threshold);
+        // the account mints wrapped classes and tokens once relayers approved an import, those pay no deposit
+        <nfts::Module<T>>::_exempt_from_deposits(&Self::account_id());
+        Self::deposit_event(RawEvent::RelayersSet(threshold));
+        Ok(())
+    }
+
+    fn _export_token(owner: T::AccountId, token_id: T::Hash, dest_chain: u32, recipient: Vec<u8>) -> Result {
+        ensure!(dest_chain != T::ChainId::get(), "can not export to this chain");
+        ensure!(recipient.len() > 0 && recipient.len() <= MAX_RECIPIENT_LEN, "invalid recipient");
+        let token = match <nfts::Module<T>>::get_token(&token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        let nonce = Nonce::get();
+        // wrapped tokens go home as their origin token, native ones as themselves
+        let (class, home_token_id) = match Self::get_wrapped(&token_id){
+            Some(origin) => {
+                ensure!(origin.source_chain == dest_chain, "wrapped token can only go back to its source chain");
+                ensure!(<erc721::Module<T>>::owner_of(&token_id) == Some(owner.clone()), "not the owner of this token");
+                <nfts::Module<T>>::_destroy_token(owner.clone(), token_id, BRIDGE_BURN_REASON)?;
+                <WrappedTokens<T>>::remove(token_id);
+                (origin.class, origin.token_id)
+            },
+            None => {
+                <nfts::Module<T>>::_escrow_token(owner.clone(), Self::account_id(), token_id)?;
+                <Exports<T>>::insert(token_id, ExportRecord{
+                    owner: owner.clone(),
+                    dest_chain,
+                    recipient: recipient.clone(),
+                    nonce,
+                });
+                (token.nft_id, token_id)
+            },
+        };
+        Nonce::put(nonce + 1);
+        let proof = Self::export_proof(dest_chain, &recipient, &class, &home_token_id, nonce);
+        Self::deposit_event(RawEvent::TokenExported(owner, dest_chain, recipient, class, home_token_id, nonce, proof));
+        Ok(())
+    }
+
+    fn _import_approve(relayer: T::AccountId, message: ImportMessage<T::AccountId, T::Hash>) -> Result {
+        let relayers = Self::relayers();
+        ensure!(relayers.contains(&relayer), "not a relayer");
+        ensure!(message.source_chain != T::ChainId::get(), "can not import from this chain");
+        let message_hash = message.using_encoded(<T as system::Trait>::Hashing::hash);
+        ensure!(!Self::is_import_processed(&message_hash), "message already imported");
+        // approvals of relayers removed since then do not count
+        let mut approvals = Self::get_import_approvals(&message_hash);
+        approvals.retain(|x| relayers.contains(x));
+        ensure!(!approvals.contains(&relayer), "message already approved by this relayer");
+        approvals.push(relayer.clone());
+        let count = approvals.len() as u32;
+
+        if count >= Self::threshold() {
+            let (token_id, delivered) = Self::_execute_import(&message)?;
+            <ImportApprovals<T>>::remove(message_hash);
+            <ProcessedImports<T>>::insert(message_hash, true);
+            Self::deposit_event(RawEvent::ImportApproved(relayer, message_hash, count));
+            if delivered {
+                Self::deposit_event(RawEvent::TokenImported(message.recipient, message_hash, token_id));
+            } else {
+                Self::deposit_event(RawEvent::ImportedTokenExpired(message.recipient, message_hash, token_id));
+            };
+        } else {
+            <ImportApprovals<T>>::insert(message_hash, approvals);
+            Self::deposit_event(RawEvent::ImportApproved(relayer, message_hash, count));
+        };
+        Ok(())
+    }
+
+    // release a token coming back home, or mint a wrapped one, returns the local token id and
+    // whether it was delivered
+    fn _execute_import(message: &ImportMessage<T::AccountId, T::Hash>) -> rstd::result::Result<(T::Hash, bool), &'static str> {
+        let pot = Self::account_id();
+        if let Some(record) = Self::get_export(&message.token_id) {
+            ensure!(record.dest_chain == message.source_chain, "token was exported to another chain");
+            // a token that expired while exported is reaped, the message is processed all the same
+            let delivered = <nfts::Module<T>>::_release_escrow(pot, message.recipient.clone(), message.token_id)?;
+            <Exports<T>>::remove(message.token_id);
+            return Ok((message.token_id, delivered))
+        };
+
+        let class_key = (message.source_chain, message.class);
+        let class = match Self::get_mirrored_class(&class_key){
+            Some(t) => t,
+            None => {
+                let t = <nfts::Module<T>>::_nonfungible_create(pot.clone(), &WRAPPED_CLASS_SYMBOL.to_vec(), <BalanceOf<T>>::max_value())?;
+                <MirroredClasses<T>>::insert(class_key, t);
+                t
+            },
+        };
+        let token_id = <nfts::Module<T>>::_issue_token_to(pot, message.recipient.clone(), class, message.symbol.clone(), None)?;
+        <WrappedTokens<T>>::insert(token_id, WrappedOrigin{
+            source_chain: message.source_chain,
+            class: message.class,
+            token_id: message.token_id,
+        });
+        Ok((token_id, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        use primitives::{H256};
+        use support::{impl_outer_origin, parameter_types};
+        use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
+        use sr_primitives::weights::Weight;
+        use sr_primitives::Perbill;
+
+        impl_outer_origin! {
+            pub enum Origin for Test {}
+        }
+
+        // For testing the module, we construct most of a mock runtime. This means
+        // first constructing a configuration type (`Test`) which `impl`s each of the
+        // configuration traits of modules we want to use.
+        #[derive(Clone, Eq, PartialEq, Debug)]
+        pub struct Test;
+        parameter_types! {
+            pub const BlockHashCount: u64 = 250;
+            pub const MaximumBlockWeight: Weight = 1024;
+            pub const MaximumBlockLength: u32 = 2 * 1024;
+            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+        }
+        impl system::Trait for Test {
+            type Origin = Origin;
+            type Call = ();
+            type Index = u64;
+            type BlockNumber = u64;
+            type Hash = H256;
+            type Hashing = BlakeTwo256;
+            type AccountId = u64;
+            type Lookup = IdentityLookup<Self::AccountId>;
+            type Header = Header;
+            type Event = ();
+            type BlockHashCount = BlockHashCount;
+            type MaximumBlockWeight = MaximumBlockWeight;
+            type MaximumBlockLength = MaximumBlockLength;
+            type AvailableBlockRatio = AvailableBlockRatio;
+            type Version = ();
+        }
+        type Balance = u64;
+        parameter_types! {
+            pub const TransferFee: Balance = 0;
+            pub const CreationFee: Balance = 0;
+        }
+        impl balances::Trait for Test {
+            type Balance = Balance;
+            type OnFreeBalanceZero = ();
+            type OnNewAccount = ();
+            type Event = ();
+            type TransferPayment = ();
+            type DustRemoval = ();
+            type ExistentialDeposit = ();
+            type TransferFee = TransferFee;
+            type CreationFee = CreationFee;
+        }
+        impl erc721::Trait for Test{
+            type Event = ();
+            type Hooks = nfts::Module<Test>;
+        }
+        parameter_types! {
+            pub const ClassDeposit: Balance = 10;
+            pub const TokenDeposit: Balance = 2;
+            pub const DepositPerByte: Balance = 1;
+        }
+        impl nfts::Trait for Test{
+            type Event = ();
+            type Currency = balances::Module<Self>;
+            type ClassDeposit = ClassDeposit;
+            type TokenDeposit = TokenDeposit;
+            type DepositPerByte = DepositPerByte;
+        }
+        parameter_types! {
+            pub const MinimumPeriod: u64 = 5;
+        }
+        impl timestamp::Trait for Test {
+                type Moment = u64;
+                type OnTimestampSet = ();
+                type MinimumPeriod = MinimumPeriod;
+        }
+        parameter_types! {
+            pub const ChainId: u32 = 1;
+        }
+        impl Trait for Test {
+            type Event = ();
+            type ChainId = ChainId;
+        }
+
+        // This function basically just builds a genesis storage key/value store according to
+        // our desired mockup.
+        fn new_test_ext() -> runtime_io::TestExternalities {
+            let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+            balances::GenesisConfig::<Test>{
+                balances: vec![(0, 1000), (1, 1000), (2, 1000), (3, 1000)],
+                vesting: vec![],
+            }.assimilate_storage(&mut t).unwrap();
+            t.into()
+        }
+
+        pub type NftsModule = nfts::Module<Test>;
+        pub type Erc721Module = erc721::Module<Test>;
+        pub type BridgeModule = Module<Test>;
+        type BalancesModule = balances::Module<Test>;
+        type Timestamp = timestamp::Module<Test>;
+
+        const FOREIGN_CHAIN: u32 = 7;
+
+        // stands in for the off-chain relayers, every relayer approves what it is given
+        struct MockRelayer {
+            relayers: Vec<u64>,
+        }
+        impl MockRelayer {
+            fn setup(relayers: Vec<u64>, threshold: u32) -> Self {
+                BridgeModule::set_relayers(Origin::ROOT, relayers.clone(), threshold).unwrap();
+                MockRelayer{ relayers }
+            }
+            fn relay(&self, message: &ImportMessage<u64, H256>) -> Result {
+                for relayer in self.relayers.iter() {
+                    BridgeModule::import_approve(Origin::signed(*relayer), message.clone())?;
+                    if BridgeModule::is_import_processed(message.using_encoded(BlakeTwo256::hash)) {
+                        break;
+                    };
+                };
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn bridge_export_import_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let pot = BridgeModule::account_id();
+                let res = BridgeModule::set_relayers(Origin::signed(bob), vec![10, 11, 12], 2);
+                assert_eq!(res.is_err(), true);
+                let res = BridgeModule::set_relayers(Origin::ROOT, vec![10, 11], 3);
+                assert_eq!(res, Err("threshold exceeds the relayer count"));
+                MockRelayer::setup(vec![10, 11, 12], 2);
+
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+
+                // export locks the native token in the bridge account
+                let res = BridgeModule::export_token(Origin::signed(alice), tk0, FOREIGN_CHAIN, vec![9; 32]);
+                assert_eq!(res.is_err(), true);
+                let res = BridgeModule::export_token(Origin::signed(bob), tk0, 1, vec![9; 32]);
+                assert_eq!(res, Err("can not export to this chain"));
+                let res = BridgeModule::export_token(Origin::signed(bob), tk0, FOREIGN_CHAIN, vec![9; 32]);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(pot));
+                assert_eq!(NftsModule::get_token_reserve(tk0), true);
+                assert_eq!(BridgeModule::get_export(tk0).unwrap().nonce, 0);
+                assert_eq!(BridgeModule::export_proof(FOREIGN_CHAIN, &vec![9; 32], &nft_id, &tk0, 0),
+                    (PROOF_DOMAIN, 1u32, FOREIGN_CHAIN, vec![9u8; 32], nft_id, tk0, 0u64).using_encoded(BlakeTwo256::hash));
+
+                // the token comes back to alice once two relayers approve
+                let message = ImportMessage{
+                    source_chain: FOREIGN_CHAIN,
+                    nonce: 0,
+                    class: nft_id,
+                    token_id: tk0,
+                    symbol: "token_0".as_bytes().to_vec(),
+                    recipient: alice,
+                };
+                let res = BridgeModule::import_approve(Origin::signed(bob), message.clone());
+                assert_eq!(res, Err("not a relayer"));
+                let res = BridgeModule::import_approve(Origin::signed(10), message.clone());
+                assert_eq!(res, Ok(()));
+                let res = BridgeModule::import_approve(Origin::signed(10), message.clone());
+                assert_eq!(res, Err("message already approved by this relayer"));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(pot));
+                // the approval of a removed relayer no longer counts
+                MockRelayer::setup(vec![11, 12], 2);
+                let res = BridgeModule::import_approve(Origin::signed(11), message.clone());
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(pot));
+                let res = BridgeModule::import_approve(Origin::signed(12), message.clone());
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(BridgeModule::get_export(tk0), None);
+                let res = BridgeModule::import_approve(Origin::signed(11), message.clone());
+                assert_eq!(res, Err("message already imported"));
+            });
+        }
+
+        #[test]
+        fn bridge_wrapped_token_test() {
+            new_test_ext().execute_with(|| {
+                let alice = 1;
+                let jack = 2;
+                let pot = BridgeModule::account_id();
+                let relayer = MockRelayer::setup(vec![10, 11, 12], 2);
+                // the bridge account holds no funds, mirrored nfts and wrapped tokens need no deposit
+                assert_eq!(NftsModule::is_deposit_exempt(pot), true);
+                assert_eq!(BalancesModule::free_balance(pot), 0);
+
+                let foreign_class = H256::repeat_byte(1);
+                let foreign_token = H256::repeat_byte(2);
+                let message = ImportMessage{
+                    source_chain: FOREIGN_CHAIN,
+                    nonce: 3,
+                    class: foreign_class,
+                    token_id: foreign_token,
+                    symbol: "sword".as_bytes().to_vec(),
+                    recipient: alice,
+                };
+                assert_eq!(relayer.relay(&message), Ok(()));
+                let class = BridgeModule::get_mirrored_class((FOREIGN_CHAIN, foreign_class)).unwrap();
+                assert_eq!(NftsModule::get_nft(class).unwrap().issuer, pot);
+                let wrapped = *NftsModule::get_tokens_nft_vec(class).get(0).unwrap();
+                assert_eq!(Erc721Module::owner_of(wrapped), Some(alice));
+                assert_eq!(NftsModule::get_token(wrapped).unwrap().symbol, "sword".as_bytes().to_vec());
+                assert_eq!(BalancesModule::reserved_balance(pot), 0);
+
+                // a second token of the same foreign nft reuses the mirrored nft
+                let message = ImportMessage{
+                    nonce: 4,
+                    token_id: H256::repeat_byte(3),
+                    recipient: jack,
+                    ..message
+                };
+                assert_eq!(relayer.relay(&message), Ok(()));
+                assert_eq!(NftsModule::get_tokens_nft_vec(class).len(), 2);
+
+                // wrapped tokens only go back to their source chain, where they are released
+                let res = BridgeModule::export_token(Origin::signed(alice), wrapped, 8, vec![9; 32]);
+                assert_eq!(res, Err("wrapped token can only go back to its source chain"));
+                let res = BridgeModule::export_token(Origin::signed(jack), wrapped, FOREIGN_CHAIN, vec![9; 32]);
+                assert_eq!(res, Err("not the owner of this token"));
+                let res = BridgeModule::export_token(Origin::signed(alice), wrapped, FOREIGN_CHAIN, vec![9; 32]);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(wrapped), None);
+                assert_eq!(BridgeModule::get_wrapped(wrapped), None);
+                assert_eq!(BalancesModule::reserved_balance(pot), 0);
+            });
+        }
+
+        #[test]
+        fn bridge_expired_import_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let relayer = MockRelayer::setup(vec![10, 11, 12], 2);
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec(), 200).unwrap();
+                let tk0 = Erc721Module::token_by_index(0);
+                let res = BridgeModule::export_token(Origin::signed(bob), tk0, FOREIGN_CHAIN, vec![9; 32]);
+                assert_eq!(res, Ok(()));
+
+                // the token expired while exported, the import reaps it and the message is done
+                Timestamp::set_timestamp(200);
+                let message = ImportMessage{
+                    source_chain: FOREIGN_CHAIN,
+                    nonce: 0,
+                    class: nft_id,
+                    token_id: tk0,
+                    symbol: "token_0".as_bytes().to_vec(),
+                    recipient: alice,
+                };
+                assert_eq!(relayer.relay(&message), Ok(()));
+                assert_eq!(BridgeModule::is_import_processed(message.using_encoded(BlakeTwo256::hash)), true);
+                assert_eq!(Erc721Module::owner_of(tk0), None);
+                assert_eq!(NftsModule::get_token(tk0), None);
+                assert_eq!(BridgeModule::get_export(tk0), None);
+            });
+        }
+}
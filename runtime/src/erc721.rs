@@ -8,7 +8,10 @@ use rstd::prelude::*;
 use support::{
     decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue,
 };
+use rstd::result;
+use crate::merkle;
 use sr_primitives::traits::{ CheckedAdd, CheckedSub};
+use sr_primitives::weights::{SimpleDispatchInfo, Weight};
 // use sr_primitives::RuntimeDebug;
 
 // use rstd::{result, cmp};
@@ -17,8 +20,39 @@ use sr_primitives::traits::{ CheckedAdd, CheckedSub};
 pub trait Trait: system::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
     // type Index: Parameter + Member + Default + Copy + SimpleArithmetic;
+    // lets the modules built on top of erc721 keep their own token state in sync
+    type Hooks: TokenHooks<Self::AccountId, Self::Hash>;
 }
 
+pub trait TokenHooks<AccountId, Hash> {
+    // called before a plain token is minted by `create_token`, an error blocks the mint
+    fn on_mint(who: &AccountId, token_id: &Hash) -> Result;
+    // called before a token is burnt, `reason` is a code chosen by whoever burns it
+    fn on_burn(who: &AccountId, token_id: &Hash, reason: u8) -> Result;
+    // called before a token changes hands, an error blocks the transfer
+    fn ensure_transferable(token_id: &Hash) -> Result;
+    // called once a token changed hands
+    fn on_transfer(from: &AccountId, to: &AccountId, token_id: &Hash);
+}
+
+impl<AccountId, Hash> TokenHooks<AccountId, Hash> for () {
+    fn on_mint(_who: &AccountId, _token_id: &Hash) -> Result {
+        Ok(())
+    }
+    fn on_burn(_who: &AccountId, _token_id: &Hash, _reason: u8) -> Result {
+        Ok(())
+    }
+    fn ensure_transferable(_token_id: &Hash) -> Result {
+        Ok(())
+    }
+    fn on_transfer(_from: &AccountId, _to: &AccountId, _token_id: &Hash) {}
+}
+
+// prefix of every token id preimage, keeps token ids apart from other hashes built on chain
+const TOKEN_ID_DOMAIN: &[u8] = b"erc721:token";
+// weight of a call minting, moving or burning one token, the leaf and all TREE_DEPTH nodes above it are rewritten
+pub const OWNERSHIP_UPDATE_WEIGHT: Weight = 50_000;
+
 decl_event!(
     pub enum Event<T>
     where
@@ -53,8 +87,17 @@ decl_storage! {
         OwnedTokensIndex get(get_owned_index): map T::Hash => T::Index;
         // Start ERC721 : Enumerable : Storage & Getters //
 
-        // Not a part of the ERC721 specification, but used in random token generation
-        Nonce: u64;
+        // Not a part of the ERC721 specification, every token id is allocated as (class, serial)
+        // class => next serial, tokens created without a class use the default hash as class
+        ClassSerialCounter get(class_serial_counter): map T::Hash => u64;
+        // (class, serial) => token id
+        TokenBySerial get(token_by_serial): map (T::Hash, u64) => Option<T::Hash>;
+        // token id => (class, serial)
+        TokenSerialOf get(token_serial_of): map T::Hash => Option<(T::Hash, u64)>;
+        // (class, level, index) => node of the ownership tree of the class, absent nodes are empty subtrees
+        OwnershipNodes get(ownership_node): map (T::Hash, u32, u64) => Option<T::Hash>;
+        // merkle::zero_hashes, computed once on the first ownership update
+        ZeroHashes get(zero_hashes): Vec<T::Hash>;
         // test debug 
         // TestDebugs get(get_test_debug): Option<TestDebug<T>>;
     }
@@ -89,6 +132,7 @@ decl_module! {
         }
 
         // transfer_from will transfer to addresses even without a balance
+        #[weight = SimpleDispatchInfo::FixedNormal(OWNERSHIP_UPDATE_WEIGHT)]
         fn transfer_from(origin, from: T::AccountId, to: T::AccountId, token_id: T::Hash) -> Result {
             let sender = ensure_signed(origin)?;
             ensure!(Self::_is_approved_or_owner(sender, token_id), "You do not own this token");
@@ -109,18 +153,23 @@ decl_module! {
         //     Ok(())
         // }
 
+        #[weight = SimpleDispatchInfo::FixedNormal(OWNERSHIP_UPDATE_WEIGHT)]
         fn create_token(origin) -> Result {
             let sender = ensure_signed(origin)?;
-            let nonce = Nonce::get();
-            // let random_hash = (<system::Module<T>>::random_seed(), &sender, nonce).using_encoded(<T as system::Trait>::Hashing::hash);
-            let random_hash = (&sender, nonce).using_encoded(<T as system::Trait>::Hashing::hash);
-            
-            Self::_mint(sender, random_hash)?;
-            Nonce::mutate(|n| *n += 1);
+            let class = T::Hash::default();
+            let token_id = Self::_token_id_of(class, Self::class_serial_counter(class));
+            // plain tokens pay the storage deposit of the modules on top like every other token
+            T::Hooks::on_mint(&sender, &token_id)?;
+            if let Err(e) = Self::_mint_next(sender.clone(), class) {
+                // hand the deposit back
+                let _ = T::Hooks::on_burn(&sender, &token_id, 0);
+                return Err(e)
+            };
 
             // Ok(random_hash)
             Ok(())
         }
+        #[weight = SimpleDispatchInfo::FixedNormal(OWNERSHIP_UPDATE_WEIGHT)]
         fn burn_token(origin, token_id: T::Hash) -> Result{
             let sender = ensure_signed(origin)?;
             let owner = Self::owner_of(&token_id);
@@ -128,6 +177,7 @@ decl_module! {
                 return Err("token not found")
             };
             ensure!(owner.unwrap() == sender, "not own this token, cannot burn it");
+            T::Hooks::on_burn(&sender, &token_id, 0)?;
             Self::_burn(token_id)
         }
 
@@ -170,7 +220,104 @@ impl<T: Trait> Module<T> {
         return approved_as_owner || approved_as_user || approved_as_delegate
     }
 
-    pub fn _mint(to: T::AccountId, token_id: T::Hash) -> Result {
+    pub fn _token_id_of(class: T::Hash, serial: u64) -> T::Hash {
+        (TOKEN_ID_DOMAIN, class, serial).using_encoded(<T as system::Trait>::Hashing::hash)
+    }
+
+    // mint the next serial of `class` to `to`, the only way token ids are handed out
+    pub fn _mint_next(to: T::AccountId, class: T::Hash) -> result::Result<T::Hash, &'static str> {
+        let serial = Self::class_serial_counter(class);
+        let next_serial = match serial.checked_add(1) {
+            Some(c) => c,
+            None => return Err("Overflow allocating a new token serial"),
+        };
+        ensure!(serial >> merkle::TREE_DEPTH == 0, "no serial left under this class");
+        let token_id = Self::_token_id_of(class, serial);
+        ensure!(!<TokenSerialOf<T>>::exists(token_id), "token id collision");
+        <TokenSerialOf<T>>::insert(token_id, (class, serial));
+        if let Err(e) = Self::_mint(to, token_id) {
+            <TokenSerialOf<T>>::remove(token_id);
+            return Err(e)
+        };
+
+        <ClassSerialCounter<T>>::insert(class, next_serial);
+        <TokenBySerial<T>>::insert((class, serial), token_id);
+        Ok(token_id)
+    }
+
+    // roots of empty subtrees, from storage once an ownership update cached them
+    fn _zero_hashes() -> Vec<T::Hash> {
+        let zeros = Self::zero_hashes();
+        if zeros.len() == merkle::TREE_DEPTH as usize + 1 {
+            return zeros
+        };
+        merkle::zero_hashes::<T::Hashing>()
+    }
+
+    pub fn ownership_root(class: T::Hash) -> T::Hash {
+        match Self::ownership_node((class, merkle::TREE_DEPTH, 0)) {
+            Some(t) => t,
+            None => Self::_zero_hashes()[merkle::TREE_DEPTH as usize],
+        }
+    }
+
+    // inclusion proof of the current owner of a token in the ownership tree of its class
+    pub fn ownership_proof(token_id: T::Hash) -> Option<merkle::OwnershipProof<T::AccountId, T::Hash>> {
+        let owner = Self::owner_of(token_id)?;
+        let (class, serial) = Self::token_serial_of(token_id)?;
+        let zeros = Self::_zero_hashes();
+        let mut siblings = Vec::with_capacity(merkle::TREE_DEPTH as usize);
+        let mut idx = serial;
+        for level in 0..merkle::TREE_DEPTH {
+            let sibling = Self::ownership_node((class, level, idx ^ 1)).unwrap_or(zeros[level as usize]);
+            siblings.push(sibling);
+            idx >>= 1;
+        };
+        Some(merkle::OwnershipProof{
+            class,
+            serial,
+            token_id,
+            owner,
+            root: Self::ownership_root(class),
+            siblings,
+        })
+    }
+
+    // rewrite the leaf of a token and every node above it, `None` empties the leaf
+    fn _update_ownership_leaf(token_id: T::Hash, owner: Option<&T::AccountId>) {
+        let (class, serial) = match Self::token_serial_of(token_id) {
+            Some(c) => c,
+            None => return,
+        };
+        let mut zeros = Self::zero_hashes();
+        if zeros.len() != merkle::TREE_DEPTH as usize + 1 {
+            zeros = merkle::zero_hashes::<T::Hashing>();
+            <ZeroHashes<T>>::put(&zeros);
+        };
+        let mut node = match owner {
+            Some(who) => merkle::leaf_hash::<T::Hashing, T::AccountId>(&class, &token_id, who),
+            None => zeros[0],
+        };
+        let mut idx = serial;
+        for level in 0..=merkle::TREE_DEPTH {
+            if node == zeros[level as usize] {
+                <OwnershipNodes<T>>::remove((class, level, idx));
+            } else {
+                <OwnershipNodes<T>>::insert((class, level, idx), node);
+            };
+            if level == merkle::TREE_DEPTH {
+                break;
+            };
+            let sibling = Self::ownership_node((class, level, idx ^ 1)).unwrap_or(zeros[level as usize]);
+            node = match idx & 1 {
+                0 => merkle::node_hash::<T::Hashing>(&node, &sibling),
+                _ => merkle::node_hash::<T::Hashing>(&sibling, &node),
+            };
+            idx >>= 1;
+        };
+    }
+
+    fn _mint(to: T::AccountId, token_id: T::Hash) -> Result {
         ensure!(!Self::_exists(token_id), "Token already exists");
         let balance_of = Self::balance_of(&to);
 
@@ -185,6 +332,7 @@ impl<T: Trait> Module<T> {
 
         <TokenOwner<T>>::insert(token_id, &to);
         <OwnedTokensCount<T>>::insert(&to, new_balance_of);
+        Self::_update_ownership_leaf(token_id, Some(&to));
 
         Self::deposit_event(RawEvent::Transfer(None, Some(to), token_id));
 
@@ -213,6 +361,11 @@ impl<T: Trait> Module<T> {
 
         <OwnedTokensCount<T>>::insert(&owner, new_balance_of);
         <TokenOwner<T>>::remove(token_id);
+        Self::_update_ownership_leaf(token_id, None);
+        // the serial is never handed out again, only the lookup goes away
+        if let Some(serial) = <TokenSerialOf<T>>::take(token_id) {
+            <TokenBySerial<T>>::remove(serial);
+        };
 
         Self::deposit_event(RawEvent::Transfer(Some(owner), None, token_id));
 
@@ -226,6 +379,7 @@ impl<T: Trait> Module<T> {
         };
 
         ensure!(owner == from, "'from' account does not own this token");
+        T::Hooks::ensure_transferable(&token_id)?;
 
         let balance_of_from = Self::balance_of(&from);
         let balance_of_to = Self::balance_of(&to);
@@ -248,6 +402,8 @@ impl<T: Trait> Module<T> {
         <OwnedTokensCount<T>>::insert(&from, new_balance_of_from);
         <OwnedTokensCount<T>>::insert(&to, new_balance_of_to);
         <TokenOwner<T>>::insert(&token_id, &to);
+        Self::_update_ownership_leaf(token_id, Some(&to));
+        T::Hooks::on_transfer(&from, &to, &token_id);
 
         Self::deposit_event(RawEvent::Transfer(Some(from), Some(to), token_id));
         
@@ -386,6 +542,7 @@ mod tests {
         }
         impl Trait for Test {
             type Event = ();
+            type Hooks = ();
         }
 
 
@@ -437,6 +594,80 @@ mod tests {
             });
         }
 
+        #[test]
+        fn token_serial_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let class = H256::repeat_byte(1);
+                Erc721Module::create_token(Origin::signed(bob));
+                let tk_0 = Erc721Module::_mint_next(alice, class).unwrap();
+                let tk_1 = Erc721Module::_mint_next(alice, class).unwrap();
+
+                // serials count per class, ids are derived from (class, serial)
+                assert_eq!(Erc721Module::class_serial_counter(H256::default()), 1);
+                assert_eq!(Erc721Module::class_serial_counter(class), 2);
+                assert_eq!(Erc721Module::token_by_serial((H256::default(), 0)), Some(Erc721Module::token_by_index(0)));
+                assert_eq!(Erc721Module::token_by_serial((class, 0)), Some(tk_0));
+                assert_eq!(Erc721Module::token_by_serial((class, 1)), Some(tk_1));
+                assert_eq!(Erc721Module::token_serial_of(tk_1), Some((class, 1)));
+                assert_eq!(Erc721Module::_token_id_of(class, 1), tk_1);
+                assert!(Erc721Module::token_by_index(0) != tk_0);
+
+                // a burnt serial is not handed out again
+                let res = Erc721Module::burn_token(Origin::signed(alice), tk_1);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::token_by_serial((class, 1)), None);
+                assert_eq!(Erc721Module::token_serial_of(tk_1), None);
+                let tk_2 = Erc721Module::_mint_next(alice, class).unwrap();
+                assert_eq!(Erc721Module::token_serial_of(tk_2), Some((class, 2)));
+            });
+        }
+
+        #[test]
+        fn ownership_proof_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let class = H256::repeat_byte(1);
+                let empty_root = Erc721Module::ownership_root(class);
+                assert_eq!(Erc721Module::zero_hashes().len(), 0);
+                let tk_0 = Erc721Module::_mint_next(bob, class).unwrap();
+                // the empty subtree roots are kept once the tree is first written
+                assert_eq!(Erc721Module::zero_hashes(), merkle::zero_hashes::<BlakeTwo256>());
+                let tk_1 = Erc721Module::_mint_next(bob, class).unwrap();
+                let tk_2 = Erc721Module::_mint_next(alice, class).unwrap();
+                let root = Erc721Module::ownership_root(class);
+                assert!(root != empty_root);
+
+                for &tk in [tk_0, tk_1, tk_2].iter() {
+                    let p = Erc721Module::ownership_proof(tk).unwrap();
+                    assert_eq!(p.root, root);
+                    assert!(merkle::verify_proof::<BlakeTwo256, u64>(&p.root, &class, p.serial, &tk, &p.owner, &p.siblings));
+                };
+                let p = Erc721Module::ownership_proof(tk_1).unwrap();
+                assert_eq!(merkle::verify_proof::<BlakeTwo256, u64>(&p.root, &class, p.serial, &tk_1, &alice, &p.siblings), false);
+
+                // a transfer moves the root, the old proof no longer matches it
+                let res = Erc721Module::transfer_from(Origin::signed(bob), bob, alice, tk_1);
+                assert_eq!(res, Ok(()));
+                let new_root = Erc721Module::ownership_root(class);
+                assert!(new_root != root);
+                assert_eq!(merkle::verify_proof::<BlakeTwo256, u64>(&new_root, &class, p.serial, &tk_1, &bob, &p.siblings), false);
+                let p = Erc721Module::ownership_proof(tk_1).unwrap();
+                assert_eq!(p.owner, alice);
+                assert!(merkle::verify_proof::<BlakeTwo256, u64>(&new_root, &class, p.serial, &tk_1, &alice, &p.siblings));
+
+                // burning every token empties the tree again
+                for &(who, tk) in [(bob, tk_0), (alice, tk_1), (alice, tk_2)].iter() {
+                    let res = Erc721Module::burn_token(Origin::signed(who), tk);
+                    assert_eq!(res, Ok(()));
+                };
+                assert_eq!(Erc721Module::ownership_proof(tk_0), None);
+                assert_eq!(Erc721Module::ownership_root(class), empty_root);
+            });
+        }
+
         #[test]
         fn burn_token_test() {
             new_test_ext().execute_with(|| {
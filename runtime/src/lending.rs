@@ -0,0 +1,523 @@
+// Peer to peer loans of generic assets backed by a token.
+// The borrower lists a token with the terms it wants, the token is held by the lending account
+// while the loan is open. Repaying before the deadline releases it, after the deadline the lender
+// may claim it instead.
+
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use sr_primitives::traits::{Hash, Zero, CheckedAdd, AccountIdConversion};
+use sr_primitives::ModuleId;
+use rstd::prelude::*;
+use support::{
+    decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue,
+};
+use crate::nfts;
+
+// account holding the collateral of open loans
+const LENDING_MODULE_ID: ModuleId = ModuleId(*b"nft/lend");
+
+pub trait Trait: nfts::Trait + timestamp::Trait + generic_asset::Trait {
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub enum LoanStatus<AccountId, Moment> {
+    // waiting for a lender
+    Requested,
+    // funded by `lender`, to be repaid before `due`
+    Active{ lender: AccountId, due: Moment },
+}
+
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct Loan<T> where T: Trait {
+    loan_id: T::Hash,
+    borrower: T::AccountId,
+    token_id: T::Hash, // collateral
+    asset: T::AssetId,
+    amount: T::Balance, // lent to the borrower
+    interest: T::Balance, // paid on top of the amount when repaying
+    duration: T::Moment, // counted from the moment the loan is funded
+    status: LoanStatus<T::AccountId, T::Moment>,
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        <T as system::Trait>::AccountId,
+        <T as system::Trait>::Hash,
+        <T as generic_asset::Trait>::Balance,
+        <T as timestamp::Trait>::Moment
+    {
+        // borrower, loan_id, token_id
+        LoanRequested(AccountId, Hash, Hash),
+        // borrower, loan_id
+        LoanCanceled(AccountId, Hash),
+        // lender, loan_id, due
+        LoanFunded(AccountId, Hash, Moment),
+        // borrower, loan_id, amount repaid with interest
+        LoanRepaid(AccountId, Hash, Balance),
+        // lender, loan_id, token_id claimed
+        LoanDefaulted(AccountId, Hash, Hash),
+        // lender, loan_id, token_id of a collateral expired before it was claimed, the token is reaped
+        CollateralExpired(AccountId, Hash, Hash),
+    }
+);
+
+decl_storage! {
+    trait Store for Module<T: Trait> as LendingStorage {
+        // loan id -> loan
+        Loans get(get_loan): map T::Hash => Option<Loan<T>>;
+        // token id -> loan the token backs
+        TokenLoan get(get_token_loan): map T::Hash => Option<T::Hash>;
+
+        Nonce: u64;
+    }
+}
+
+decl_module! {
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        fn deposit_event() = default;
+
+        // put a token up as collateral for `amount` of `asset`
+        pub fn loan_request(origin, token_id: T::Hash, asset: T::AssetId, amount: T::Balance, interest: T::Balance, duration: T::Moment) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_loan_request(sender, token_id, asset, amount, interest, duration)
+        }
+
+        pub fn loan_cancel(origin, loan_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_loan_cancel(sender, loan_id)
+        }
+
+        pub fn loan_fund(origin, loan_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_loan_fund(sender, loan_id)
+        }
+
+        pub fn loan_repay(origin, loan_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_loan_repay(sender, loan_id)
+        }
+
+        // take the collateral of a loan not repaid in time
+        pub fn loan_claim(origin, loan_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_loan_claim(sender, loan_id)
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    pub fn account_id() -> T::AccountId {
+        LENDING_MODULE_ID.into_account()
+    }
+
+    fn _loan_request(borrower: T::AccountId, token_id: T::Hash, asset: T::AssetId, amount: T::Balance, interest: T::Balance, duration: T::Moment) -> Result {
+        ensure!(!amount.is_zero(), "loan amount can not be zero");
+        ensure!(!duration.is_zero(), "loan duration can not be zero");
+        // the borrower picks the terms, neither the repayment nor the deadline may wrap around
+        ensure!(amount.checked_add(&interest).is_some(), "loan repayment overflows");
+        ensure!(<timestamp::Module<T>>::get().checked_add(&duration).is_some(), "loan duration overflows");
+        ensure!(Self::get_token_loan(&token_id).is_none(), "token already backs a loan");
+        // escrow the collateral
+        <nfts::Module<T>>::_escrow_token(borrower.clone(), Self::account_id(), token_id)?;
+
+        let nonce = Nonce::get();
+        let loan_id = (b"loan", &borrower, token_id, nonce).using_encoded(<T as system::Trait>::Hashing::hash);
+        Nonce::mutate(|n| *n += 1);
+        let loan = Loan{
+            loan_id,
+            borrower: borrower.clone(),
+            token_id,
+            asset,
+            amount,
+            interest,
+            duration,
+            status: LoanStatus::Requested,
+        };
+        <Loans<T>>::insert(loan_id, loan);
+        <TokenLoan<T>>::insert(token_id, loan_id);
+        Self::deposit_event(RawEvent::LoanRequested(borrower, loan_id, token_id));
+        Ok(())
+    }
+
+    fn _loan_cancel(sender: T::AccountId, loan_id: T::Hash) -> Result {
+        let loan = match Self::get_loan(&loan_id){
+            Some(t) => t,
+            None => return Err("loan not found"),
+        };
+        ensure!(loan.borrower == sender, "not the borrower of this loan");
+        ensure!(loan.status == LoanStatus::Requested, "loan already funded");
+        <nfts::Module<T>>::_release_escrow(Self::account_id(), sender.clone(), loan.token_id)?;
+        Self::_remove_loan(&loan);
+        Self::deposit_event(RawEvent::LoanCanceled(sender, loan_id));
+        Ok(())
+    }
+
+    fn _loan_fund(lender: T::AccountId, loan_id: T::Hash) -> Result {
+        let mut loan = match Self::get_loan(&loan_id){
+            Some(t) => t,
+            None => return Err("loan not found"),
+        };
+        ensure!(loan.status == LoanStatus::Requested, "loan already funded");
+        ensure!(loan.borrower != lender, "borrower can not fund its own loan");
+        let due = match <timestamp::Module<T>>::get().checked_add(&loan.duration){
+            Some(t) => t,
+            None => return Err("loan duration overflows"),
+        };
+        // the collateral has to outlive the loan, an expired token is worth nothing to the lender
+        if let Some(token) = <nfts::Module<T>>::get_token(&loan.token_id){
+            if let Some(expiry) = token.expiry {
+                ensure!(expiry > due, "collateral expires before the loan is due");
+            };
+        };
+        <generic_asset::Module<T>>::make_transfer(&loan.asset, &lender, &loan.borrower, loan.amount)?;
+
+        loan.status = LoanStatus::Active{ lender: lender.clone(), due };
+        <Loans<T>>::insert(loan_id, loan);
+        Self::deposit_event(RawEvent::LoanFunded(lender, loan_id, due));
+        Ok(())
+    }
+
+    fn _loan_repay(sender: T::AccountId, loan_id: T::Hash) -> Result {
+        let loan = match Self::get_loan(&loan_id){
+            Some(t) => t,
+            None => return Err("loan not found"),
+        };
+        ensure!(loan.borrower == sender, "not the borrower of this loan");
+        let lender = match loan.status {
+            LoanStatus::Active{ ref lender, due } => {
+                ensure!(<timestamp::Module<T>>::get() < due, "loan is past due");
+                lender.clone()
+            },
+            LoanStatus::Requested => return Err("loan not funded"),
+        };
+        let total = match loan.amount.checked_add(&loan.interest){
+            Some(t) => t,
+            None => return Err("loan repayment overflows"),
+        };
+        <generic_asset::Module<T>>::make_transfer(&loan.asset, &sender, &lender, total)?;
+        <nfts::Module<T>>::_release_escrow(Self::account_id(), sender.clone(), loan.token_id)?;
+        Self::_remove_loan(&loan);
+        Self::deposit_event(RawEvent::LoanRepaid(sender, loan_id, total));
+        Ok(())
+    }
+
+    fn _loan_claim(sender: T::AccountId, loan_id: T::Hash) -> Result {
+        let loan = match Self::get_loan(&loan_id){
+            Some(t) => t,
+            None => return Err("loan not found"),
+        };
+        match loan.status {
+            LoanStatus::Active{ ref lender, due } => {
+                ensure!(*lender == sender, "not the lender of this loan");
+                ensure!(<timestamp::Module<T>>::get() >= due, "loan not past due yet");
+            },
+            LoanStatus::Requested => return Err("loan not funded"),
+        };
+        // the collateral goes to the lender, unless it expired in the meantime and can only be reaped
+        let delivered = <nfts::Module<T>>::_release_escrow(Self::account_id(), sender.clone(), loan.token_id)?;
+        Self::_remove_loan(&loan);
+        if delivered {
+            Self::deposit_event(RawEvent::LoanDefaulted(sender, loan_id, loan.token_id));
+        } else {
+            Self::deposit_event(RawEvent::CollateralExpired(sender, loan_id, loan.token_id));
+        };
+        Ok(())
+    }
+
+    fn _remove_loan(loan: &Loan<T>) {
+        <Loans<T>>::remove(loan.loan_id);
+        <TokenLoan<T>>::remove(loan.token_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        use primitives::{H256};
+        use support::{impl_outer_origin, parameter_types};
+        use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
+        use sr_primitives::weights::Weight;
+        use sr_primitives::Perbill;
+        use generic_asset::{AssetOptions, PermissionsV1};
+        use crate::erc721;
+
+        impl_outer_origin! {
+            pub enum Origin for Test {}
+        }
+
+        // For testing the module, we construct most of a mock runtime. This means
+        // first constructing a configuration type (`Test`) which `impl`s each of the
+        // configuration traits of modules we want to use.
+        #[derive(Clone, Eq, PartialEq, Debug)]
+        pub struct Test;
+        parameter_types! {
+            pub const BlockHashCount: u64 = 250;
+            pub const MaximumBlockWeight: Weight = 1024;
+            pub const MaximumBlockLength: u32 = 2 * 1024;
+            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+        }
+        impl system::Trait for Test {
+            type Origin = Origin;
+            type Call = ();
+            type Index = u64;
+            type BlockNumber = u64;
+            type Hash = H256;
+            type Hashing = BlakeTwo256;
+            type AccountId = u64;
+            type Lookup = IdentityLookup<Self::AccountId>;
+            type Header = Header;
+            type Event = ();
+            type BlockHashCount = BlockHashCount;
+            type MaximumBlockWeight = MaximumBlockWeight;
+            type MaximumBlockLength = MaximumBlockLength;
+            type AvailableBlockRatio = AvailableBlockRatio;
+            type Version = ();
+        }
+        type Balance = u64;
+        parameter_types! {
+            pub const TransferFee: Balance = 0;
+            pub const CreationFee: Balance = 0;
+        }
+        impl balances::Trait for Test {
+            type Balance = Balance;
+            type OnFreeBalanceZero = ();
+            type OnNewAccount = ();
+            type Event = ();
+            type TransferPayment = ();
+            type DustRemoval = ();
+            type ExistentialDeposit = ();
+            type TransferFee = TransferFee;
+            type CreationFee = CreationFee;
+        }
+        impl erc721::Trait for Test{
+            type Event = ();
+            type Hooks = nfts::Module<Test>;
+        }
+        parameter_types! {
+            pub const ClassDeposit: Balance = 10;
+            pub const TokenDeposit: Balance = 2;
+            pub const DepositPerByte: Balance = 1;
+        }
+        impl nfts::Trait for Test{
+            type Event = ();
+            type Currency = balances::Module<Self>;
+            type ClassDeposit = ClassDeposit;
+            type TokenDeposit = TokenDeposit;
+            type DepositPerByte = DepositPerByte;
+        }
+        impl generic_asset::Trait for Test {
+                type Event = ();
+                type Balance = u64;
+                type AssetId = u32;
+        }
+        parameter_types! {
+            pub const MinimumPeriod: u64 = 5;
+        }
+        impl timestamp::Trait for Test {
+                type Moment = u64;
+                type OnTimestampSet = ();
+                type MinimumPeriod = MinimumPeriod;
+        }
+        impl Trait for Test {
+            type Event = ();
+        }
+
+        // This function basically just builds a genesis storage key/value store according to
+        // our desired mockup.
+        fn new_test_ext() -> runtime_io::TestExternalities {
+            let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+            balances::GenesisConfig::<Test>{
+                balances: vec![(0, 1000), (1, 1000), (2, 1000), (3, 1000)],
+                vesting: vec![],
+            }.assimilate_storage(&mut t).unwrap();
+            t.into()
+        }
+
+        pub type NftsModule = nfts::Module<Test>;
+        pub type AssetsModule = generic_asset::Module<Test>;
+        pub type Erc721Module = erc721::Module<Test>;
+        pub type LendingModule = Module<Test>;
+        type Timestamp = timestamp::Module<Test>;
+
+        // bob owns a token, alice and bob hold 5000 of a fresh asset, returns (token_id, asset)
+        fn setup() -> (H256, u32) {
+            let bob = 0;
+            let alice = 1;
+            let asset_issuer = 100;
+            let asset = AssetsModule::next_asset_id();
+            let options = AssetOptions{
+                initial_issuance: 1000000,
+                permissions: PermissionsV1::default(),
+            };
+            AssetsModule::create_asset(None, Some(asset_issuer), options).unwrap();
+            AssetsModule::transfer(Origin::signed(asset_issuer), asset, bob, 5000).unwrap();
+            AssetsModule::transfer(Origin::signed(asset_issuer), asset, alice, 5000).unwrap();
+
+            NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+            let nft_id = NftsModule::get_nft_by_index(0);
+            NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+            (Erc721Module::token_by_index(0), asset)
+        }
+
+        #[test]
+        fn loan_repay_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                Timestamp::set_timestamp(100);
+                let (token_id, asset) = setup();
+                let res = LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, 200);
+                assert_eq!(res, Ok(()));
+                let loan_id = LendingModule::get_token_loan(token_id).unwrap();
+                // the collateral is escrowed
+                assert_eq!(Erc721Module::owner_of(token_id), Some(LendingModule::account_id()));
+                assert_eq!(NftsModule::get_token_reserve(token_id), true);
+                let res = Erc721Module::transfer_from(Origin::signed(bob), bob, jack, token_id);
+                assert_eq!(res, Err("You do not own this token"));
+                let res = LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, 200);
+                assert_eq!(res, Err("token already backs a loan"));
+                // terms that would wrap around are rejected
+                let res = LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, u64::max_value(), 200);
+                assert_eq!(res, Err("loan repayment overflows"));
+                let res = LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, u64::max_value());
+                assert_eq!(res, Err("loan duration overflows"));
+
+                let res = LendingModule::loan_repay(Origin::signed(bob), loan_id);
+                assert_eq!(res, Err("loan not funded"));
+                let res = LendingModule::loan_fund(Origin::signed(bob), loan_id);
+                assert_eq!(res, Err("borrower can not fund its own loan"));
+                let res = LendingModule::loan_fund(Origin::signed(alice), loan_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &bob), 6000);
+                assert_eq!(AssetsModule::free_balance(&asset, &alice), 4000);
+                let res = LendingModule::loan_cancel(Origin::signed(bob), loan_id);
+                assert_eq!(res, Err("loan already funded"));
+
+                // due at 300, the lender can not claim before
+                Timestamp::set_timestamp(299);
+                let res = LendingModule::loan_claim(Origin::signed(alice), loan_id);
+                assert_eq!(res, Err("loan not past due yet"));
+                let res = LendingModule::loan_repay(Origin::signed(bob), loan_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &bob), 4950);
+                assert_eq!(AssetsModule::free_balance(&asset, &alice), 5050);
+                assert_eq!(NftsModule::get_token_reserve(token_id), false);
+                assert_eq!(Erc721Module::owner_of(token_id), Some(bob));
+                assert_eq!(LendingModule::get_loan(loan_id).is_none(), true);
+                assert_eq!(LendingModule::get_token_loan(token_id), None);
+            });
+        }
+
+        #[test]
+        fn loan_default_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                Timestamp::set_timestamp(100);
+                let (token_id, asset) = setup();
+                LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, 200).unwrap();
+                let loan_id = LendingModule::get_token_loan(token_id).unwrap();
+                LendingModule::loan_fund(Origin::signed(alice), loan_id).unwrap();
+
+                Timestamp::set_timestamp(300);
+                let res = LendingModule::loan_repay(Origin::signed(bob), loan_id);
+                assert_eq!(res, Err("loan is past due"));
+                let res = LendingModule::loan_claim(Origin::signed(jack), loan_id);
+                assert_eq!(res, Err("not the lender of this loan"));
+                let res = LendingModule::loan_claim(Origin::signed(alice), loan_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(token_id), Some(alice));
+                assert_eq!(NftsModule::get_token_reserve(token_id), false);
+                assert_eq!(AssetsModule::free_balance(&asset, &bob), 6000);
+                assert_eq!(LendingModule::get_loan(loan_id).is_none(), true);
+            });
+        }
+
+        #[test]
+        fn loan_cancel_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let (token_id, asset) = setup();
+                LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, 200).unwrap();
+                let loan_id = LendingModule::get_token_loan(token_id).unwrap();
+                let res = LendingModule::loan_cancel(Origin::signed(alice), loan_id);
+                assert_eq!(res, Err("not the borrower of this loan"));
+                let res = LendingModule::loan_cancel(Origin::signed(bob), loan_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(token_id), false);
+                assert_eq!(Erc721Module::owner_of(token_id), Some(bob));
+                let res = LendingModule::loan_fund(Origin::signed(alice), loan_id);
+                assert_eq!(res, Err("loan not found"));
+            });
+        }
+
+        #[test]
+        fn loan_escrow_bypass_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                Timestamp::set_timestamp(100);
+                let (token_id, asset) = setup();
+                LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, 200).unwrap();
+                let loan_id = LendingModule::get_token_loan(token_id).unwrap();
+                LendingModule::loan_fund(Origin::signed(alice), loan_id).unwrap();
+
+                // the borrower can neither lift the reservation nor move the collateral
+                let res = NftsModule::token_unreserve(Origin::signed(bob), token_id);
+                assert_eq!(res, Err("not authrized as not the issuer of this token"));
+                let res = NftsModule::_reserve_safe_transfer(bob, jack, token_id);
+                assert_eq!(res, Err("token reserved, transfer now is forbidden"));
+                let res = Erc721Module::transfer_from(Origin::signed(bob), bob, jack, token_id);
+                assert_eq!(res, Err("You do not own this token"));
+                let res = NftsModule::burn_token(Origin::signed(bob), token_id, 0);
+                assert_eq!(res, Err("not authrized to burn this token"));
+
+                Timestamp::set_timestamp(300);
+                let res = LendingModule::loan_claim(Origin::signed(alice), loan_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(token_id), Some(alice));
+            });
+        }
+
+        #[test]
+        fn loan_expired_collateral_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type BalancesModule = balances::Module<Test>;
+                Timestamp::set_timestamp(100);
+                let (_, asset) = setup();
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec(), 350).unwrap();
+                let token_id = Erc721Module::token_by_index(1);
+
+                // due at 400 while the token expires at 350
+                LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, 300).unwrap();
+                let loan_id = LendingModule::get_token_loan(token_id).unwrap();
+                let res = LendingModule::loan_fund(Origin::signed(alice), loan_id);
+                assert_eq!(res, Err("collateral expires before the loan is due"));
+                LendingModule::loan_cancel(Origin::signed(bob), loan_id).unwrap();
+
+                // due at 300, the lender claims too late to get a valid token
+                LendingModule::loan_request(Origin::signed(bob), token_id, asset, 1000, 50, 200).unwrap();
+                let loan_id = LendingModule::get_token_loan(token_id).unwrap();
+                LendingModule::loan_fund(Origin::signed(alice), loan_id).unwrap();
+                Timestamp::set_timestamp(400);
+                let reserved = BalancesModule::reserved_balance(bob);
+                let res = LendingModule::loan_claim(Origin::signed(alice), loan_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(token_id), None);
+                assert_eq!(NftsModule::get_token(token_id), None);
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved - 2);
+                assert_eq!(LendingModule::get_loan(loan_id).is_none(), true);
+                assert_eq!(LendingModule::get_token_loan(token_id), None);
+            });
+        }
+}
@@ -70,6 +70,12 @@ pub type DigestItem = generic::DigestItem<Hash>;
 mod erc721;
 mod nfts;
 mod order;
+mod vault;
+mod lending;
+mod nft_staking;
+mod bridge;
+pub mod api;
+pub mod merkle;
 
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
@@ -102,7 +108,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("substrate-nfts"),
 	impl_name: create_runtime_str!("substrate-nfts"),
 	authoring_version: 3,
-	spec_version: 4,
+	spec_version: 5,
 	impl_version: 4,
 	apis: RUNTIME_API_VERSIONS,
 };
@@ -251,16 +257,59 @@ impl sudo::Trait for Runtime {
 
 impl erc721::Trait for Runtime {
 	type Event = Event;
+	type Hooks = Order;
+}
 
+parameter_types! {
+	pub const ClassDeposit: Balance = 100 * 1_000;
+	pub const TokenDeposit: Balance = 10 * 1_000;
+	pub const DepositPerByte: Balance = 1_000;
 }
 
 impl nfts::Trait for Runtime {
 	type Event = Event;
 	type Currency = Balances;
+	type ClassDeposit = ClassDeposit;
+	type TokenDeposit = TokenDeposit;
+	type DepositPerByte = DepositPerByte;
 }
+parameter_types! {
+	// bids in the last 10 minutes of an english auction extend it by 10 minutes
+	pub const AuctionExtension: u64 = 10 * 60 * 1000;
+}
+
 impl order::Trait for Runtime {
 	type Event = Event;
+	type AuctionExtension = AuctionExtension;
+	type FeeOrigin = system::EnsureRoot<AccountId>;
+}
+
+parameter_types! {
+	// buyout auctions of vaulted tokens run for 3 days
+	pub const BuyoutPeriod: u64 = 3 * 24 * 60 * 60 * 1000;
+}
+
+impl vault::Trait for Runtime {
+	type Event = Event;
+	type BuyoutPeriod = BuyoutPeriod;
+}
+
+impl lending::Trait for Runtime {
+	type Event = Event;
+}
+
+impl nft_staking::Trait for Runtime {
+	type Event = Event;
+}
+
+parameter_types! {
+	// id of this chain for the bridge relayers
+	pub const BridgeChainId: u32 = 0;
+}
 
+impl bridge::Trait for Runtime {
+	type Event = Event;
+	type ChainId = BridgeChainId;
 }
 type SubmitTransaction = TransactionSubmitter<AuraId, Runtime, UncheckedExtrinsic>;
 
@@ -283,7 +332,8 @@ impl system::offchain::CreateTransaction<Runtime, UncheckedExtrinsic> for Runtim
 			system::CheckEra::<Runtime>::from(generic::Era::mortal(period, current_block)),
 			system::CheckNonce::<Runtime>::from(index),
 			system::CheckWeight::<Runtime>::new(),
-			transaction_payment::ChargeTransactionPayment::<Runtime>::from(tip)
+			transaction_payment::ChargeTransactionPayment::<Runtime>::from(tip),
+			order::CheckTokenGate::<Runtime>::new(),
 		);
 		let raw_payload = SignedPayload::new(call, extra).ok()?;
 		let signature = F::sign(public, &raw_payload)?;
@@ -314,6 +364,10 @@ construct_runtime!(
 		ERC721: erc721::{Module, Storage, Call, Event<T>},
 		NFTs: nfts::{Module, Storage, Call, Event<T>},
 		Order: order::{Module, Storage, Call, Event<T>},
+		Vault: vault::{Module, Storage, Call, Event<T>},
+		Lending: lending::{Module, Storage, Call, Event<T>},
+		NftStaking: nft_staking::{Module, Storage, Call, Event<T>},
+		Bridge: bridge::{Module, Storage, Call, Event<T>},
 	}
 );
 
@@ -334,7 +388,8 @@ pub type SignedExtra = (
 	system::CheckEra<Runtime>,
 	system::CheckNonce<Runtime>,
 	system::CheckWeight<Runtime>,
-	transaction_payment::ChargeTransactionPayment<Runtime>
+	transaction_payment::ChargeTransactionPayment<Runtime>,
+	order::CheckTokenGate<Runtime>,
 );
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
@@ -422,4 +477,36 @@ impl_runtime_apis! {
 			Grandpa::grandpa_authorities()
 		}
 	}
+
+	impl api::NftsApi<Block, AccountId, Hash> for Runtime {
+		fn class_holders(nft_id: Hash) -> Vec<(AccountId, u64)> {
+			NFTs::class_holders(nft_id)
+		}
+
+		fn ownership_root(class: Hash) -> Hash {
+			ERC721::ownership_root(class)
+		}
+
+		fn ownership_proof(token_id: Hash) -> Option<merkle::OwnershipProof<AccountId, Hash>> {
+			ERC721::ownership_proof(token_id)
+		}
+	}
+
+	impl api::OrderApi<Block, AccountId, Hash, u64, Balance, u64> for Runtime {
+		fn bid_reservations(who: AccountId, asset: u64) -> (Balance, Balance) {
+			(GAssets::reserved_balance(&asset, &who), Order::bid_obligations(&who, asset))
+		}
+
+		fn account_orders(who: AccountId) -> Vec<order::OrderDetails<Hash, u64, Balance, u64>> {
+			Order::account_orders(&who)
+		}
+
+		fn trade_records(start: u64, count: u32) -> Vec<order::TradeRecord<AccountId, Hash, u64, Balance, u64>> {
+			Order::trade_records(start, count)
+		}
+
+		fn closed_orders(who: AccountId) -> Vec<order::ClosedOrder<Hash, u64, Balance, u64>> {
+			Order::get_closed_orders(who)
+		}
+	}
 }
@@ -0,0 +1,72 @@
+// Sparse merkle tree committing to the owner of every token of a class.
+// Leaf `serial` of the tree of a class holds the hash of (class, token_id, owner),
+// empty leaves are the default hash. Everything here is no_std so partner apps
+// can verify proofs with the runtime crate alone.
+
+use codec::{Encode, Decode};
+use rstd::prelude::*;
+use sr_primitives::traits::Hash as HashT;
+
+// depth of the tree of every class, a class holds up to 2^TREE_DEPTH serials
+pub const TREE_DEPTH: u32 = 32;
+
+const LEAF_DOMAIN: &[u8] = b"erc721:leaf";
+const NODE_DOMAIN: &[u8] = b"erc721:node";
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct OwnershipProof<AccountId, Hash> {
+    pub class: Hash,
+    pub serial: u64, // index of the leaf
+    pub token_id: Hash,
+    pub owner: AccountId,
+    pub root: Hash,
+    pub siblings: Vec<Hash>, // from the leaf level up, TREE_DEPTH entries
+}
+
+pub fn leaf_hash<H: HashT, AccountId: Encode>(class: &H::Output, token_id: &H::Output, owner: &AccountId) -> H::Output {
+    (LEAF_DOMAIN, class, token_id, owner).using_encoded(H::hash)
+}
+
+pub fn node_hash<H: HashT>(left: &H::Output, right: &H::Output) -> H::Output {
+    (NODE_DOMAIN, left, right).using_encoded(H::hash)
+}
+
+// root of an empty subtree for every level, from the leaves (default hash) up to the root
+pub fn zero_hashes<H: HashT>() -> Vec<H::Output> {
+    let mut zeros = Vec::with_capacity(TREE_DEPTH as usize + 1);
+    zeros.push(H::Output::default());
+    for level in 0..TREE_DEPTH as usize {
+        let z = node_hash::<H>(&zeros[level], &zeros[level]);
+        zeros.push(z);
+    };
+    zeros
+}
+
+pub fn root_from_path<H: HashT>(leaf: H::Output, index: u64, siblings: &[H::Output]) -> H::Output {
+    let mut node = leaf;
+    let mut idx = index;
+    for sibling in siblings.iter() {
+        node = match idx & 1 {
+            0 => node_hash::<H>(&node, sibling),
+            _ => node_hash::<H>(sibling, &node),
+        };
+        idx >>= 1;
+    };
+    node
+}
+
+// check that `owner` held `token_id` as leaf `serial` of `class` under `root`
+pub fn verify_proof<H: HashT, AccountId: Encode>(
+    root: &H::Output,
+    class: &H::Output,
+    serial: u64,
+    token_id: &H::Output,
+    owner: &AccountId,
+    siblings: &[H::Output],
+) -> bool {
+    if siblings.len() != TREE_DEPTH as usize || serial >> TREE_DEPTH != 0 {
+        return false
+    };
+    let leaf = leaf_hash::<H, AccountId>(class, token_id, owner);
+    root_from_path::<H>(leaf, serial, siblings) == *root
+}
@@ -0,0 +1,571 @@
+// Staking of tokens into a reward pool of their nft.
+// The issuer of a nft sets up the pool and funds it with a generic asset, owners stake tokens
+// (held by the staking account while staked) and earn `reward_per_block` per staked token every block.
+
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use sr_primitives::traits::{SaturatedConversion, Saturating, Zero, AccountIdConversion};
+use sr_primitives::ModuleId;
+use rstd::prelude::*;
+use support::{
+    decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap,
+};
+use crate::nfts;
+
+// account holding the reward funds of every pool and the staked tokens
+const STAKING_MODULE_ID: ModuleId = ModuleId(*b"nft/stak");
+
+pub trait Trait: nfts::Trait + generic_asset::Trait {
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct StakePool<AssetId, Balance, BlockNumber> {
+    reward_asset: AssetId,
+    reward_per_block: Balance, // paid for every staked token
+    funds: Balance, // rewards left to pay, held by the staking account
+    staked: u32, // tokens staked into the pool
+    acc_reward: Balance, // reward accrued by one token staked since the pool was set up
+    debt_total: Balance, // sum of `reward_debt` over the staked tokens
+    last_update: BlockNumber, // `acc_reward` counts up to this block
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct StakeInfo<AccountId, Hash, Balance> {
+    owner: AccountId,
+    nft_id: Hash,
+    reward_debt: Balance, // `acc_reward` of the pool at the last claim
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        <T as system::Trait>::AccountId,
+        <T as system::Trait>::Hash,
+        <T as generic_asset::Trait>::Balance
+    {
+        // issuer, nft_id
+        PoolSet(AccountId, Hash),
+        // issuer, nft_id, amount
+        PoolFunded(AccountId, Hash, Balance),
+        // issuer, nft_id, amount
+        PoolWithdrawn(AccountId, Hash, Balance),
+        // owner, token_id
+        TokenStaked(AccountId, Hash),
+        // owner, token_id, reward
+        RewardClaimed(AccountId, Hash, Balance),
+        // owner, token_id
+        TokenUnstaked(AccountId, Hash),
+    }
+);
+
+decl_storage! {
+    trait Store for Module<T: Trait> as NftStakingStorage {
+        // nft id -> reward pool of the nft
+        Pools get(get_pool): map T::Hash => Option<StakePool<T::AssetId, T::Balance, T::BlockNumber>>;
+        // token id -> stake of the token
+        Stakes get(get_stake): map T::Hash => Option<StakeInfo<T::AccountId, T::Hash, T::Balance>>;
+        // owner -> tokens staked by the account
+        StakedTokens get(get_staked_tokens): map T::AccountId => Vec<T::Hash>;
+    }
+}
+
+decl_module! {
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        fn deposit_event() = default;
+
+        // create or update the pool of a nft, rate changes apply from the current block on
+        pub fn pool_set(origin, nft_id: T::Hash, reward_asset: T::AssetId, reward_per_block: T::Balance) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_pool_set(sender, nft_id, reward_asset, reward_per_block)
+        }
+
+        pub fn pool_fund(origin, nft_id: T::Hash, amount: T::Balance) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_pool_fund(sender, nft_id, amount)
+        }
+
+        // rewards accrued by the staked tokens and not claimed yet stay in the pool
+        pub fn pool_withdraw(origin, nft_id: T::Hash, amount: T::Balance) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_pool_withdraw(sender, nft_id, amount)
+        }
+
+        pub fn stake(origin, token_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_stake(sender, token_id)
+        }
+
+        pub fn claim(origin, token_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_claim(&sender, token_id)
+        }
+
+        // claim what is left and release the token, anyone may unstake an expired token to reap it
+        pub fn unstake(origin, token_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_unstake(sender, token_id)
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    pub fn account_id() -> T::AccountId {
+        STAKING_MODULE_ID.into_account()
+    }
+
+    fn _ensure_issuer(sender: &T::AccountId, nft_id: T::Hash) -> Result {
+        let nft = match <nfts::Module<T>>::get_nft(&nft_id){
+            Some(t) => t,
+            None => return Err("nft not found"),
+        };
+        ensure!(nft.issuer == *sender, "not authorized as not the issuer of this nft");
+        Ok(())
+    }
+
+    // bring the accrued reward per token up to the current block at the current rate
+    fn _accrue(pool: &mut StakePool<T::AssetId, T::Balance, T::BlockNumber>) {
+        let now = <system::Module<T>>::block_number();
+        let blocks = now.saturating_sub(pool.last_update);
+        let blocks: T::Balance = blocks.saturated_into::<u32>().into();
+        pool.acc_reward = pool.acc_reward.saturating_add(pool.reward_per_block.saturating_mul(blocks));
+        pool.last_update = now;
+    }
+
+    fn _pool_set(issuer: T::AccountId, nft_id: T::Hash, reward_asset: T::AssetId, reward_per_block: T::Balance) -> Result {
+        Self::_ensure_issuer(&issuer, nft_id)?;
+        let pool = match Self::get_pool(&nft_id){
+            Some(mut pool) => {
+                ensure!(pool.reward_asset == reward_asset || pool.funds.is_zero(), "withdraw pool funds before changing the reward asset");
+                // rewards accrued so far keep the old rate
+                Self::_accrue(&mut pool);
+                pool.reward_asset = reward_asset;
+                pool.reward_per_block = reward_per_block;
+                pool
+            },
+            None => StakePool{
+                reward_asset,
+                reward_per_block,
+                funds: Zero::zero(),
+                staked: 0,
+                acc_reward: Zero::zero(),
+                debt_total: Zero::zero(),
+                last_update: <system::Module<T>>::block_number(),
+            },
+        };
+        <Pools<T>>::insert(nft_id, pool);
+        Self::deposit_event(RawEvent::PoolSet(issuer, nft_id));
+        Ok(())
+    }
+
+    fn _pool_fund(issuer: T::AccountId, nft_id: T::Hash, amount: T::Balance) -> Result {
+        Self::_ensure_issuer(&issuer, nft_id)?;
+        let mut pool = match Self::get_pool(&nft_id){
+            Some(t) => t,
+            None => return Err("pool not found"),
+        };
+        <generic_asset::Module<T>>::make_transfer(&pool.reward_asset, &issuer, &Self::account_id(), amount)?;
+        pool.funds = pool.funds.saturating_add(amount);
+        <Pools<T>>::insert(nft_id, pool);
+        Self::deposit_event(RawEvent::PoolFunded(issuer, nft_id, amount));
+        Ok(())
+    }
+
+    fn _pool_withdraw(issuer: T::AccountId, nft_id: T::Hash, amount: T::Balance) -> Result {
+        Self::_ensure_issuer(&issuer, nft_id)?;
+        let mut pool = match Self::get_pool(&nft_id){
+            Some(t) => t,
+            None => return Err("pool not found"),
+        };
+        ensure!(amount <= pool.funds, "not enough funds in the pool");
+        Self::_accrue(&mut pool);
+        let staked: T::Balance = pool.staked.into();
+        let owed = pool.acc_reward.saturating_mul(staked).saturating_sub(pool.debt_total);
+        ensure!(amount <= pool.funds.saturating_sub(owed), "pool funds owed to stakers can not be withdrawn");
+        <generic_asset::Module<T>>::make_transfer(&pool.reward_asset, &Self::account_id(), &issuer, amount)?;
+        pool.funds -= amount;
+        <Pools<T>>::insert(nft_id, pool);
+        Self::deposit_event(RawEvent::PoolWithdrawn(issuer, nft_id, amount));
+        Ok(())
+    }
+
+    fn _stake(owner: T::AccountId, token_id: T::Hash) -> Result {
+        let token = match <nfts::Module<T>>::get_token(&token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        let mut pool = match Self::get_pool(&token.nft_id){
+            Some(t) => t,
+            None => return Err("no staking pool for this nft"),
+        };
+        <nfts::Module<T>>::_escrow_token(owner.clone(), Self::account_id(), token_id)?;
+        Self::_accrue(&mut pool);
+        pool.staked = pool.staked.saturating_add(1);
+        let reward_debt = pool.acc_reward;
+        pool.debt_total = pool.debt_total.saturating_add(reward_debt);
+        <Pools<T>>::insert(token.nft_id, pool);
+        <Stakes<T>>::insert(token_id, StakeInfo{
+            owner: owner.clone(),
+            nft_id: token.nft_id,
+            reward_debt,
+        });
+        <StakedTokens<T>>::mutate(&owner, |x| x.push(token_id));
+        Self::deposit_event(RawEvent::TokenStaked(owner, token_id));
+        Ok(())
+    }
+
+    // rewards accrued by a staked token up to now, as far as the pool can pay them, an expired
+    // token earns nothing more and forfeits what it did not claim
+    pub fn pending_reward(token_id: T::Hash) -> T::Balance {
+        let stake = match Self::get_stake(&token_id){
+            Some(t) => t,
+            None => return Zero::zero(),
+        };
+        if <nfts::Module<T>>::_is_expired(&token_id) {
+            return Zero::zero()
+        };
+        let mut pool = match Self::get_pool(&stake.nft_id){
+            Some(t) => t,
+            None => return Zero::zero(),
+        };
+        Self::_accrue(&mut pool);
+        let accrued = pool.acc_reward.saturating_sub(stake.reward_debt);
+        if accrued > pool.funds { pool.funds } else { accrued }
+    }
+
+    // pay the pending reward, whatever the pool could not pay is forfeited
+    fn _claim(owner: &T::AccountId, token_id: T::Hash) -> Result {
+        let mut stake = match Self::get_stake(&token_id){
+            Some(t) => t,
+            None => return Err("token not staked"),
+        };
+        ensure!(stake.owner == *owner, "not the owner of this stake");
+        ensure!(!<nfts::Module<T>>::_is_expired(&token_id), "token expired, unstake it instead");
+        let mut pool = match Self::get_pool(&stake.nft_id){
+            Some(t) => t,
+            None => return Err("pool not found"),
+        };
+        let reward = Self::pending_reward(token_id);
+        if !reward.is_zero() {
+            <generic_asset::Module<T>>::make_transfer(&pool.reward_asset, &Self::account_id(), owner, reward)?;
+            pool.funds -= reward;
+        };
+        Self::_accrue(&mut pool);
+        pool.debt_total = pool.debt_total.saturating_sub(stake.reward_debt).saturating_add(pool.acc_reward);
+        stake.reward_debt = pool.acc_reward;
+        <Pools<T>>::insert(stake.nft_id, pool);
+        <Stakes<T>>::insert(token_id, stake);
+        Self::deposit_event(RawEvent::RewardClaimed(owner.clone(), token_id, reward));
+        Ok(())
+    }
+
+    fn _unstake(sender: T::AccountId, token_id: T::Hash) -> Result {
+        let owner = match Self::get_stake(&token_id){
+            Some(t) => t.owner,
+            None => return Err("token not staked"),
+        };
+        // an expired token forfeits its rewards, it can not move anymore and is reaped instead
+        if !<nfts::Module<T>>::_is_expired(&token_id) {
+            Self::_claim(&sender, token_id)?;
+        };
+        <nfts::Module<T>>::_release_escrow(Self::account_id(), owner.clone(), token_id)?;
+        let stake = match <Stakes<T>>::take(&token_id){
+            Some(t) => t,
+            None => return Err("token not staked"),
+        };
+        <Pools<T>>::mutate(&stake.nft_id, |x| {
+            if let Some(pool) = x {
+                pool.staked = pool.staked.saturating_sub(1);
+                pool.debt_total = pool.debt_total.saturating_sub(stake.reward_debt);
+            };
+        });
+        <StakedTokens<T>>::mutate(&owner, |x| x.retain(|id| *id != token_id));
+        Self::deposit_event(RawEvent::TokenUnstaked(owner, token_id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        use primitives::{H256};
+        use support::{impl_outer_origin, parameter_types};
+        use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
+        use sr_primitives::weights::Weight;
+        use sr_primitives::Perbill;
+        use generic_asset::{AssetOptions, PermissionsV1};
+        use crate::erc721;
+
+        impl_outer_origin! {
+            pub enum Origin for Test {}
+        }
+
+        // For testing the module, we construct most of a mock runtime. This means
+        // first constructing a configuration type (`Test`) which `impl`s each of the
+        // configuration traits of modules we want to use.
+        #[derive(Clone, Eq, PartialEq, Debug)]
+        pub struct Test;
+        parameter_types! {
+            pub const BlockHashCount: u64 = 250;
+            pub const MaximumBlockWeight: Weight = 1024;
+            pub const MaximumBlockLength: u32 = 2 * 1024;
+            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+        }
+        impl system::Trait for Test {
+            type Origin = Origin;
+            type Call = ();
+            type Index = u64;
+            type BlockNumber = u64;
+            type Hash = H256;
+            type Hashing = BlakeTwo256;
+            type AccountId = u64;
+            type Lookup = IdentityLookup<Self::AccountId>;
+            type Header = Header;
+            type Event = ();
+            type BlockHashCount = BlockHashCount;
+            type MaximumBlockWeight = MaximumBlockWeight;
+            type MaximumBlockLength = MaximumBlockLength;
+            type AvailableBlockRatio = AvailableBlockRatio;
+            type Version = ();
+        }
+        type Balance = u64;
+        parameter_types! {
+            pub const TransferFee: Balance = 0;
+            pub const CreationFee: Balance = 0;
+        }
+        impl balances::Trait for Test {
+            type Balance = Balance;
+            type OnFreeBalanceZero = ();
+            type OnNewAccount = ();
+            type Event = ();
+            type TransferPayment = ();
+            type DustRemoval = ();
+            type ExistentialDeposit = ();
+            type TransferFee = TransferFee;
+            type CreationFee = CreationFee;
+        }
+        impl erc721::Trait for Test{
+            type Event = ();
+            type Hooks = nfts::Module<Test>;
+        }
+        parameter_types! {
+            pub const ClassDeposit: Balance = 10;
+            pub const TokenDeposit: Balance = 2;
+            pub const DepositPerByte: Balance = 1;
+        }
+        impl nfts::Trait for Test{
+            type Event = ();
+            type Currency = balances::Module<Self>;
+            type ClassDeposit = ClassDeposit;
+            type TokenDeposit = TokenDeposit;
+            type DepositPerByte = DepositPerByte;
+        }
+        impl generic_asset::Trait for Test {
+                type Event = ();
+                type Balance = u64;
+                type AssetId = u32;
+        }
+        parameter_types! {
+            pub const MinimumPeriod: u64 = 5;
+        }
+        impl timestamp::Trait for Test {
+                type Moment = u64;
+                type OnTimestampSet = ();
+                type MinimumPeriod = MinimumPeriod;
+        }
+        impl Trait for Test {
+            type Event = ();
+        }
+
+        // This function basically just builds a genesis storage key/value store according to
+        // our desired mockup.
+        fn new_test_ext() -> runtime_io::TestExternalities {
+            let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+            balances::GenesisConfig::<Test>{
+                balances: vec![(0, 1000), (1, 1000), (2, 1000), (3, 1000)],
+                vesting: vec![],
+            }.assimilate_storage(&mut t).unwrap();
+            t.into()
+        }
+
+        pub type NftsModule = nfts::Module<Test>;
+        pub type AssetsModule = generic_asset::Module<Test>;
+        pub type Erc721Module = erc721::Module<Test>;
+        pub type StakingModule = Module<Test>;
+        type System = system::Module<Test>;
+        type Timestamp = timestamp::Module<Test>;
+
+        #[test]
+        fn staking_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let asset_issuer = 100;
+                let asset = AssetsModule::next_asset_id();
+                let options = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                AssetsModule::create_asset(None, Some(asset_issuer), options).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset, bob, 100).unwrap();
+
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                NftsModule::_reserve_safe_transfer(bob, alice, tk1).unwrap();
+
+                let res = StakingModule::stake(Origin::signed(alice), tk1);
+                assert_eq!(res, Err("no staking pool for this nft"));
+                let res = StakingModule::pool_set(Origin::signed(alice), nft_id, asset, 2);
+                assert_eq!(res, Err("not authorized as not the issuer of this nft"));
+                let res = StakingModule::pool_set(Origin::signed(bob), nft_id, asset, 2);
+                assert_eq!(res, Ok(()));
+                let res = StakingModule::pool_fund(Origin::signed(bob), nft_id, 30);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &StakingModule::account_id()), 30);
+
+                System::set_block_number(1);
+                let res = StakingModule::stake(Origin::signed(bob), tk1);
+                assert_eq!(res, Err("You do not own this token"));
+                assert_eq!(StakingModule::stake(Origin::signed(alice), tk1), Ok(()));
+                assert_eq!(StakingModule::stake(Origin::signed(bob), tk0), Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(tk1), true);
+                // the staked token sits in the staking account, out of reach of its owner
+                assert_eq!(Erc721Module::owner_of(tk1), Some(StakingModule::account_id()));
+                let res = NftsModule::token_unreserve(Origin::signed(alice), tk1);
+                assert_eq!(res, Err("not authrized as not the issuer of this token"));
+                let res = Erc721Module::transfer_from(Origin::signed(alice), alice, bob, tk1);
+                assert_eq!(res, Err("You do not own this token"));
+                assert_eq!(StakingModule::get_staked_tokens(alice), vec![tk1]);
+                assert_eq!(StakingModule::get_pool(nft_id).unwrap().staked, 2);
+
+                // 2 per block per token
+                System::set_block_number(6);
+                assert_eq!(StakingModule::pending_reward(tk1), 10);
+                let res = StakingModule::claim(Origin::signed(bob), tk1);
+                assert_eq!(res, Err("not the owner of this stake"));
+                assert_eq!(StakingModule::claim(Origin::signed(alice), tk1), Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &alice), 10);
+                assert_eq!(StakingModule::pending_reward(tk1), 0);
+
+                // the pool pays what it has left, the rest is forfeited
+                System::set_block_number(16);
+                assert_eq!(StakingModule::pending_reward(tk0), 20);
+                assert_eq!(StakingModule::unstake(Origin::signed(bob), tk0), Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &bob), 70 + 20);
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(StakingModule::get_stake(tk0), None);
+                assert_eq!(StakingModule::pending_reward(tk1), 0);
+                assert_eq!(StakingModule::unstake(Origin::signed(alice), tk1), Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &alice), 10);
+                assert_eq!(StakingModule::get_pool(nft_id).unwrap().staked, 0);
+                assert_eq!(StakingModule::get_pool(nft_id).unwrap().funds, 0);
+                assert_eq!(Erc721Module::owner_of(tk0), Some(bob));
+                assert_eq!(Erc721Module::owner_of(tk1), Some(alice));
+            });
+        }
+
+        #[test]
+        fn pool_rate_change_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let asset_issuer = 100;
+                let asset = AssetsModule::next_asset_id();
+                let options = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                AssetsModule::create_asset(None, Some(asset_issuer), options).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset, bob, 1000).unwrap();
+
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                NftsModule::_reserve_safe_transfer(bob, alice, tk0).unwrap();
+                StakingModule::pool_set(Origin::signed(bob), nft_id, asset, 2).unwrap();
+                StakingModule::pool_fund(Origin::signed(bob), nft_id, 500).unwrap();
+
+                System::set_block_number(1);
+                assert_eq!(StakingModule::stake(Origin::signed(alice), tk0), Ok(()));
+                // 5 blocks at 2, then the rate goes up to 10 without touching what already accrued
+                System::set_block_number(6);
+                assert_eq!(StakingModule::pool_set(Origin::signed(bob), nft_id, asset, 10), Ok(()));
+                assert_eq!(StakingModule::pending_reward(tk0), 10);
+                System::set_block_number(8);
+                assert_eq!(StakingModule::pending_reward(tk0), 10 + 20);
+                // and back down, the blocks at 10 are kept
+                assert_eq!(StakingModule::pool_set(Origin::signed(bob), nft_id, asset, 0), Ok(()));
+                System::set_block_number(20);
+                assert_eq!(StakingModule::pending_reward(tk0), 30);
+                assert_eq!(StakingModule::unstake(Origin::signed(alice), tk0), Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &alice), 30);
+                assert_eq!(StakingModule::get_pool(nft_id).unwrap().funds, 470);
+            });
+        }
+
+        #[test]
+        fn staking_expired_token_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let asset_issuer = 100;
+                let asset = AssetsModule::next_asset_id();
+                let options = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                AssetsModule::create_asset(None, Some(asset_issuer), options).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset, bob, 1000).unwrap();
+
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec(), 100);
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                NftsModule::_reserve_safe_transfer(bob, alice, tk0).unwrap();
+                NftsModule::_reserve_safe_transfer(bob, alice, tk1).unwrap();
+                StakingModule::pool_set(Origin::signed(bob), nft_id, asset, 2).unwrap();
+                StakingModule::pool_fund(Origin::signed(bob), nft_id, 100).unwrap();
+
+                System::set_block_number(1);
+                assert_eq!(StakingModule::stake(Origin::signed(alice), tk0), Ok(()));
+                assert_eq!(StakingModule::stake(Origin::signed(alice), tk1), Ok(()));
+
+                // 10 accrued by each token, the issuer can only take out the rest
+                System::set_block_number(6);
+                let res = StakingModule::pool_withdraw(Origin::signed(bob), nft_id, 81);
+                assert_eq!(res, Err("pool funds owed to stakers can not be withdrawn"));
+                assert_eq!(StakingModule::pool_withdraw(Origin::signed(bob), nft_id, 70), Ok(()));
+                assert_eq!(StakingModule::get_pool(nft_id).unwrap().funds, 30);
+
+                // an expired token stops earning and forfeits what it did not claim
+                Timestamp::set_timestamp(100);
+                assert_eq!(StakingModule::pending_reward(tk1), 0);
+                let res = StakingModule::claim(Origin::signed(alice), tk1);
+                assert_eq!(res, Err("token expired, unstake it instead"));
+                let res = StakingModule::unstake(Origin::signed(bob), tk0);
+                assert_eq!(res, Err("not the owner of this stake"));
+                // anyone reaps it, which frees its share of the pool
+                assert_eq!(StakingModule::unstake(Origin::signed(bob), tk1), Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk1), None);
+                assert_eq!(StakingModule::get_stake(tk1), None);
+                assert_eq!(StakingModule::get_staked_tokens(alice), vec![tk0]);
+                assert_eq!(AssetsModule::free_balance(&asset, &alice), 0);
+                let res = StakingModule::pool_withdraw(Origin::signed(bob), nft_id, 21);
+                assert_eq!(res, Err("pool funds owed to stakers can not be withdrawn"));
+                assert_eq!(StakingModule::pool_withdraw(Origin::signed(bob), nft_id, 20), Ok(()));
+
+                assert_eq!(StakingModule::claim(Origin::signed(alice), tk0), Ok(()));
+                assert_eq!(AssetsModule::free_balance(&asset, &alice), 10);
+                assert_eq!(StakingModule::get_pool(nft_id).unwrap().funds, 0);
+                assert_eq!(StakingModule::get_pool(nft_id).unwrap().debt_total, 12);
+            });
+        }
+}
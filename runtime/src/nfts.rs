@@ -5,25 +5,32 @@ use codec::{Encode, Decode};
 use system::ensure_signed;
 use sr_primitives::traits::{Hash};
 use rstd::prelude::*;
-// use rstd::collections::btree_map::BTreeMap;
+use rstd::collections::btree_map::BTreeMap;
+use rstd::result;
 
 use support::{
     decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue,
     traits::{
-        LockableCurrency, Currency,
+        LockableCurrency, Currency, ReservableCurrency, Get, LockIdentifier,
     }
 
 };
-use sr_primitives::traits::{CheckedAdd, CheckedSub};
+use sr_primitives::traits::{CheckedAdd, CheckedSub, SaturatedConversion, Saturating, Zero};
+use sr_primitives::weights::SimpleDispatchInfo;
 use crate::erc721;
 
 // #[cfg(feature = "std")]
 // use std::fmt;
 
-pub trait Trait: erc721::Trait {
+pub trait Trait: erc721::Trait + timestamp::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
-    type Currency: LockableCurrency<Self::AccountId, Moment = Self::BlockNumber>;
-    
+    type Currency: LockableCurrency<Self::AccountId, Moment = Self::BlockNumber> + ReservableCurrency<Self::AccountId>;
+    // deposit reserved from the issuer when a nft class is created
+    type ClassDeposit: Get<BalanceOf<Self>>;
+    // deposit reserved from the issuer for every token issued under a class
+    type TokenDeposit: Get<BalanceOf<Self>>;
+    // deposit reserved per byte of token attribute data kept on chain
+    type DepositPerByte: Get<BalanceOf<Self>>;
 }
 
 pub type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
@@ -44,9 +51,9 @@ pub struct Permission<AccountId>
     // where AccountId: Member,
     where AccountId: core::fmt::Debug
 {
-    perm_type: PermissionType,
+    pub perm_type: PermissionType,
     // target: TargetType::Asset,
-    account: AccountId,
+    pub account: AccountId,
 }
 impl<AccountId> core::fmt::Display for Permission<AccountId> 
     // where AccountId: Member,
@@ -62,9 +69,10 @@ impl<AccountId> core::fmt::Display for Permission<AccountId>
 pub struct NonfungibleOption<AccountId, Balance> 
     where AccountId: core::fmt::Debug
 {
-    permissions: Vec<Permission<AccountId>>,
-    max_supply: Balance,
-    description:Vec<u8>,
+    pub permissions: Vec<Permission<AccountId>>,
+    pub max_supply: Balance,
+    pub description:Vec<u8>,
+    pub issuer_burnable: bool,// issuer may burn tokens held by others
 }
 
 #[derive(Encode, Decode, Clone, PartialEq,Debug)]
@@ -107,6 +115,84 @@ impl<T:Trait> NftMeta<T>  {
     }
 }
 
+// upper bound of the recipients of one airdrop
+const MAX_AIRDROP_RECIPIENTS: usize = 10_000;
+// upper bound of the tokens issued by one airdrop call, keeps a full batch within half a block
+const MAX_AIRDROP_BATCH: u32 = 8;
+
+// reason code of a burn reaping an expired token
+pub const EXPIRED_BURN_REASON: u8 = 255;
+// lock of a token held in the account of a module by `_escrow_token`
+pub const ESCROW_LOCK_ID: LockIdentifier = *b"nft/escr";
+
+// upper bound of every metadata field, in bytes
+const MAX_METADATA_FIELD_LEN: usize = 256;
+// upper bound of the number of extra key/value pairs in metadata
+const MAX_METADATA_EXTRA_ENTRIES: usize = 16;
+
+#[derive(Encode, Decode, Clone, Default, PartialEq, Debug)]
+pub struct NftMetadata {
+    name: Vec<u8>,
+    description: Vec<u8>,
+    image_uri: Vec<u8>,
+    external_url: Vec<u8>,
+    extra: Vec<(Vec<u8>, Vec<u8>)>,// arbitrary key/values
+}
+
+impl NftMetadata {
+    fn validate(&self) -> Result {
+        ensure!(self.name.len() <= MAX_METADATA_FIELD_LEN, "metadata name too long");
+        ensure!(self.description.len() <= MAX_METADATA_FIELD_LEN, "metadata description too long");
+        ensure!(self.image_uri.len() <= MAX_METADATA_FIELD_LEN, "metadata image uri too long");
+        ensure!(self.external_url.len() <= MAX_METADATA_FIELD_LEN, "metadata external url too long");
+        ensure!(self.extra.len() <= MAX_METADATA_EXTRA_ENTRIES, "too many extra metadata entries");
+        for (k, v) in self.extra.iter(){
+            ensure!(k.len() <= MAX_METADATA_FIELD_LEN && v.len() <= MAX_METADATA_FIELD_LEN, "metadata extra entry too long");
+        };
+        Ok(())
+    }
+}
+
+// upper bound of the number of attribute keys in the schema of a nft
+const MAX_ATTR_SCHEMA_KEYS: usize = 32;
+// upper bound of the length of an attribute key in a schema
+const MAX_ATTR_KEY_LEN: usize = 64;
+
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Debug)]
+pub enum AttrValKind{
+    String,
+    Uint64,
+}
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Debug)]
+pub enum AttrMutability{
+    OwnerWritable,// token owner or nft issuer
+    IssuerOnly,
+}
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct AttrSchema{
+    pub val_kind: AttrValKind,
+    pub required: bool,// tokens missing a required attribute can not be listed, once set it can not be removed
+    pub mutability: AttrMutability,
+}
+
+// who receives the tokens of an airdrop
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub enum AirdropTarget<AccountId, Hash> {
+    // one token for every listed account
+    Accounts(Vec<AccountId>),
+    // one token for every token held under the given nft
+    Holders(Hash),
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct AirdropJob<AccountId, Hash> {
+    issuer: AccountId,
+    nft_id: Hash, // nft the new tokens are issued under
+    symbol: Vec<u8>,
+    total: u32, // recipients snapshotted when the airdrop was created
+    cursor: u32, // recipients before the cursor are served
+}
+
 #[derive(Encode, Decode, Clone, Default, PartialEq,Debug)]
 pub struct Token<T> where
     T: Trait
@@ -114,6 +200,7 @@ pub struct Token<T> where
     token_id: T::Hash,
     symbol: Vec<u8>, // symbol of this token
     pub nft_id: T::Hash, // nft_id
+    pub expiry: Option<T::Moment>, // token is no longer valid from this moment on
     // attributes: BTreeMap<Vec<u8>, Vec<u8>>,
     // attributes: BTreeMap<u8, u64>,
 }
@@ -129,8 +216,22 @@ decl_event!(
     {
         NonfungibleCreate(AccountId, Hash), 
         NonfungibleUpdate(AccountId, Hash), 
-        TokenDestroy(AccountId, Hash), 
+        NonfungibleDestroy(AccountId, Hash), 
+        NftMetadataSet(AccountId, Hash), 
+        NftMetadataClear(AccountId, Hash), 
+        NftMetadataLock(AccountId, Hash), 
+        AttrSchemaSet(AccountId, Hash), 
+        AttrSchemaRemove(AccountId, Hash), 
+        // burner, token_id, reason code
+        TokenDestroy(AccountId, Hash, u8), 
         TokenIssue(AccountId, Hash, Hash),
+        // issuer, airdrop_id
+        AirdropCreate(AccountId, Hash),
+        // issuer, airdrop_id, recipients served so far
+        AirdropProgress(AccountId, Hash, u32),
+        AirdropFinish(AccountId, Hash),
+        // issuer, airdrop_id, recipients served before the cancel
+        AirdropCancel(AccountId, Hash, u32),
     }
 );
 
@@ -156,12 +257,46 @@ decl_storage! {
         TotalNFTSupply get(total_nft_supply): T::Index;
         //global index -> nft_id
         AllNFTsIndex get(get_nft_by_index): map  T::Index => T::Hash;
+        // map nft id -> global index
+        AllNFTsIndexOf: map T::Hash => T::Index;
         // map (account, nft under account index) -> nft id
         OwnedNFTs get(nft_of_owner_by_index): map (T::AccountId, T::Index) => T::Hash;
         // map nft id -> nft under account index
-        // OwnedNFTsIndex: map T::Hash => T::Index;
+        OwnedNFTsIndex: map T::Hash => T::Index;
+        // map holder -> nft ids the account holds tokens of
+        HeldNFTs get(get_held_nfts): map T::AccountId => Vec<T::Hash>;
+        // map (holder, nft id) -> count of tokens held under the nft
+        HeldTokenCount get(held_token_count): map (T::AccountId, T::Hash) => u64;
         // reserve token, token id -> bool
         ReserveTokens get(get_token_reserve): map T::Hash => bool;
+        // token id -> module holding the reservation, only that module can lift it
+        TokenLocks get(get_token_lock): map T::Hash => Option<LockIdentifier>;
+        // nft id -> (depositor, amount) reserved when the nft was created
+        NftDeposits get(get_nft_deposit): map T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+        // token id -> (depositor, amount) reserved when the token was issued
+        TokenDeposits get(get_token_deposit): map T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+        // accounts of modules creating nfts and issuing tokens for free, their calls are gated by the module
+        DepositExempt get(is_deposit_exempt): map T::AccountId => bool;
+        // nft id -> class level metadata
+        NftMetadatas get(get_nft_metadata): map T::Hash => Option<NftMetadata>;
+        // nft id -> (depositor, amount) reserved for storing the metadata
+        NftMetadataDeposits get(get_nft_metadata_deposit): map T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+        // nft id -> metadata can no longer change, irreversible once set
+        NftMetadataLocked get(is_nft_metadata_locked): map T::Hash => bool;
+        // (nft id, attribute key) -> schema of the attribute, enforced on tokens under the nft
+        AttrSchemas get(get_attr_schema): map (T::Hash, Vec<u8>) => Option<AttrSchema>;
+        // nft id -> attribute keys with a schema
+        AttrSchemaKeys get(get_attr_schema_keys): map T::Hash => Vec<Vec<u8>>;
+        // (nft id, attribute key, value type) -> number of tokens under the nft holding such a value
+        AttrUsage get(get_attr_usage): map (T::Hash, Vec<u8>, AttrValKind) => u64;
+
+        // airdrop id -> airdrop still in progress
+        AirdropJobs get(get_airdrop): map T::Hash => Option<AirdropJob<T::AccountId, T::Hash>>;
+        // (airdrop id, recipient index) -> recipient not served yet
+        AirdropRecipients get(get_airdrop_recipient): map (T::Hash, u32) => Option<T::AccountId>;
+        // airdrop id -> (creator, amount) reserved for keeping the recipients on chain
+        AirdropDeposits get(get_airdrop_deposit): map T::Hash => Option<(T::AccountId, BalanceOf<T>)>;
+        AirdropNonce: u64;
 
         Nonce: u64;
     }
@@ -171,12 +306,16 @@ decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         fn deposit_event() = default;
 
+        const ClassDeposit: BalanceOf<T> = T::ClassDeposit::get();
+        const TokenDeposit: BalanceOf<T> = T::TokenDeposit::get();
+        const DepositPerByte: BalanceOf<T> = T::DepositPerByte::get();
+
         // Public Functions //
         pub fn nonfungible_create(origin, symbol : Vec<u8>, max_supply: BalanceOf<T>) -> Result {
             let sender = ensure_signed(origin)?;
             
-            Self::_nonfungible_create(sender, &symbol, max_supply)
-            // Ok(())
+            Self::_nonfungible_create(sender, &symbol, max_supply)?;
+            Ok(())
             
         }
 
@@ -186,18 +325,86 @@ decl_module! {
             Self::_nonfungible_update(sender, new_issuer, new_option, nft_id)
             // Ok(())
         }
+
+        pub fn set_nft_metadata(origin, nft_id: T::Hash, metadata: NftMetadata) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_set_nft_metadata(sender, nft_id, metadata)
+        }
+
+        pub fn clear_nft_metadata(origin, nft_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_clear_nft_metadata(sender, nft_id)
+        }
+
+        // freeze the metadata of a nft forever, there is no way back
+        pub fn lock_nft_metadata(origin, nft_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_lock_nft_metadata(sender, nft_id)
+        }
+
+        pub fn set_attr_schema(origin, nft_id: T::Hash, key: Vec<u8>, schema: AttrSchema) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_set_attr_schema(sender, nft_id, key, schema)
+        }
+
+        pub fn remove_attr_schema(origin, nft_id: T::Hash, key: Vec<u8>) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_remove_attr_schema(sender, nft_id, key)
+        }
+
+        // destroy an nft without any token left under it and release its deposit
+        pub fn nonfungible_destroy(origin, nft_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_nonfungible_destroy(sender, nft_id)
+        }
         
         // Not part of ERC721, but allows you to play with the runtime
+        #[weight = SimpleDispatchInfo::FixedNormal(erc721::OWNERSHIP_UPDATE_WEIGHT)]
         pub fn issue_token(origin, nft_id: T::Hash, symbol: Vec<u8>) -> Result {
             let sender = ensure_signed(origin)?;
-            Self::_issue_token(sender, nft_id, symbol)
+            Self::_issue_token(sender, nft_id, symbol, None)
             // Ok(())
         }
+        // issue a token that can no longer be transferred or traded once `expiry` is reached
+        #[weight = SimpleDispatchInfo::FixedNormal(erc721::OWNERSHIP_UPDATE_WEIGHT)]
+        pub fn issue_token_with_expiry(origin, nft_id: T::Hash, symbol: Vec<u8>, expiry: T::Moment) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_issue_token(sender, nft_id, symbol, Some(expiry))
+        }
+        // anyone may burn an expired token
+        #[weight = SimpleDispatchInfo::FixedNormal(erc721::OWNERSHIP_UPDATE_WEIGHT)]
+        pub fn reap_expired_token(origin, token_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_reap_expired_token(sender, token_id)
+        }
+        // snapshot the recipients and issue the first batch, the rest goes through airdrop_continue
+        #[weight = SimpleDispatchInfo::FixedNormal(MAX_AIRDROP_BATCH * erc721::OWNERSHIP_UPDATE_WEIGHT)]
+        pub fn airdrop(origin, nft_id: T::Hash, symbol: Vec<u8>, target: AirdropTarget<T::AccountId, T::Hash>, batch: u32) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_airdrop(sender, nft_id, symbol, target, batch)
+        }
+        #[weight = SimpleDispatchInfo::FixedNormal(MAX_AIRDROP_BATCH * erc721::OWNERSHIP_UPDATE_WEIGHT)]
+        pub fn airdrop_continue(origin, airdrop_id: T::Hash, batch: u32) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_airdrop_continue(sender, airdrop_id, batch)
+        }
+        // drop an airdrop not finished yet, tokens issued so far stay with their recipients
+        pub fn airdrop_cancel(origin, airdrop_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_airdrop_cancel(sender, airdrop_id)
+        }
+        #[weight = SimpleDispatchInfo::FixedNormal(erc721::OWNERSHIP_UPDATE_WEIGHT)]
         fn destroy_token(origin, token_id: T::Hash) -> Result{
             let sender = ensure_signed(origin)?;
-            Self::_destroy_token(sender, token_id)
+            Self::_destroy_token(sender, token_id, 0)
             // Ok(())
         }
+        // burn by the token owner, or by the nft issuer if the nft option permits it
+        #[weight = SimpleDispatchInfo::FixedNormal(erc721::OWNERSHIP_UPDATE_WEIGHT)]
+        fn burn_token(origin, token_id: T::Hash, reason: u8) -> Result{
+            let sender = ensure_signed(origin)?;
+            Self::_destroy_token(sender, token_id, reason)
+        }
         fn token_reserve(origin, token_id: T::Hash) -> Result{
             let sender = ensure_signed(origin)?;
             Self::_token_reserve(sender, token_id)
@@ -221,18 +428,28 @@ impl<T: Trait> Module<T> //where <T as system::Trait>::Origin: core::convert::Fr
         return owner == nft.issuer
     }
 
-    fn _nonfungible_create(issuer:T::AccountId , symbol : &Vec<u8>, max_supply: BalanceOf<T>) -> Result {
+    pub fn _nonfungible_create(issuer:T::AccountId , symbol : &Vec<u8>, max_supply: BalanceOf<T>) -> result::Result<T::Hash, &'static str> {
         let option = NonfungibleOption{
             permissions: Vec::<Permission<T::AccountId>>::new(),
             // max_supply : <BalanceOf<T>>::max_value(),
             max_supply : max_supply,
             description : Vec::<u8>::new(),
+            issuer_burnable : false,
         };
         let total_nft_count =  Self::total_nft_supply();
-        // let nonce = Nonce::get();
-        // Nonce::mutate(|n| *n += 1);
-        let nft_id =  ( &issuer, total_nft_count).using_encoded(<T as system::Trait>::Hashing::hash);
+        // total supply goes down when an nft is destroyed, so ids come from the nonce instead
+        let nonce = Nonce::get();
+        let nft_id =  ( &issuer, nonce).using_encoded(<T as system::Trait>::Hashing::hash);
         ensure!(!Self::_exists(nft_id), "Nft id conflicts.");
+        let new_total_nft_count = match total_nft_count.checked_add(&1.into()){
+            Some(c) => c,
+            None => return Err("Overflow adding a new nft"),
+        };
+        ensure!(Self::nft_counter_owner(&issuer).checked_add(&1.into()).is_some(), "Overflow adding a new nft to account");
+        // the deposit is the last thing that can fail, nothing is written before it
+        let deposit = Self::_deposit_of(&issuer, T::ClassDeposit::get());
+        Self::_reserve_deposit(&issuer, deposit)?;
+        Nonce::mutate(|n| *n += 1);
         let new_nft = NftMeta{
             total_supply : 0.into(),
             issuer : issuer.clone(),
@@ -242,56 +459,340 @@ impl<T: Trait> Module<T> //where <T as system::Trait>::Origin: core::convert::Fr
         };
 
         <NFTs<T>>::insert(&nft_id, new_nft);
+        <NftDeposits<T>>::insert(&nft_id, (issuer.clone(), deposit));
         // <TokenUnderNftCounter<T>>::insert(&nft_id, 0); // come across error
         
-        let new_total_nft_count = match total_nft_count.checked_add(&1.into()){
-            Some(c) => c,
-            None => return Err("Overflow adding a new nft"),
-        };
         <TotalNFTSupply<T>>::put(new_total_nft_count);
 
         <AllNFTsIndex<T>>::insert(&total_nft_count, nft_id);
+        <AllNFTsIndexOf<T>>::insert(&nft_id, total_nft_count);
 
-        let owned_nft_count = Self::nft_counter_owner(&issuer);
-        let new_owned_nft_count = match owned_nft_count.checked_add(&1.into()){
-            Some(c) => c,
-            None => return Err("Overflow adding a new nft to account"),
-        };
-        <OwnedNFTsCounter<T>>::insert(&issuer, new_owned_nft_count);
-        // OwnedNFTsIndex<T>::insert(nft_id, owned_nft_count);
-        <OwnedNFTsVec<T>>::mutate(&issuer, |x| x.push(nft_id));
-
-        <OwnedNFTs<T>>::insert((issuer.clone(), owned_nft_count), nft_id);
+        Self::_add_nft_to_owner_enumeration(issuer.clone(), nft_id)?;
         
         Self::deposit_event(RawEvent::NonfungibleCreate(issuer, nft_id));
         
-        Ok(())
+        Ok(nft_id)
     }
     fn _nonfungible_update(issuer:T::AccountId ,  new_issuer: Option<T::AccountId>, new_option: Option<NonfungibleOption<T::AccountId, BalanceOf<T>>>, nft_id: T::Hash) -> Result {
-        let mut nft = Self::get_nft(&nft_id).unwrap();
-        let mut will_update : u32 = 0;
-        ensure!(Self::_nft_owner_check(issuer.clone(),nft_id), "not authorized as not the issuer of this nft");
-        if new_issuer.is_some(){
-            let new_issuer = new_issuer.unwrap();
-            // set new issuer
-            nft.issuer = new_issuer;
-            will_update += 2;
+        let mut nft = match Self::get_nft(&nft_id){
+            Some(t) => t,
+            None => return Err("nft not found"),
+        };
+        ensure!(nft.issuer == issuer, "not authorized as not the issuer of this nft");
+        ensure!(new_issuer.is_some() || new_option.is_some(), "neither of issuer or option will be updated");
+        // every check runs against the current issuer before anything is written
+        let move_issuer = match new_issuer {
+            Some(ref t) => *t != nft.issuer,
+            None => false,
         };
-        if new_option.is_some(){
-            let new_option = new_option.unwrap();
-            // set new option
-            nft.option = new_option.clone();
+        if move_issuer {
+            let owned_nft_count = Self::nft_counter_owner(new_issuer.as_ref().unwrap());
+            ensure!(owned_nft_count.checked_add(&1.into()).is_some(), "Overflow adding a new nft to account");
+        };
+        // holders got their tokens under the burn rule of the class, it may only get stricter once tokens exist
+        if let Some(ref t) = new_option {
+            if t.issuer_burnable && !nft.option.issuer_burnable {
+                ensure!(nft.total_supply.is_zero(), "issuer_burnable can only be turned on before any token is issued");
+            };
+        };
+
+        // Writing to storage begins here
+        if let Some(new_option) = new_option {
+            // set new option, permissions are reset by the issuer in charge when the update was sent
+            nft.option = new_option;
             nft.reset_permission(issuer.clone())?;
-            will_update += 1;
         };
-        ensure!(will_update > 0, "neither of issuer or option will be updated");
+        if let Some(new_issuer) = new_issuer {
+            // move the nft between issuer indexes
+            if move_issuer {
+                Self::_remove_nft_from_owner_enumeration(nft.issuer.clone(), nft_id)?;
+                Self::_add_nft_to_owner_enumeration(new_issuer.clone(), nft_id)?;
+            };
+            // set new issuer
+            nft.issuer = new_issuer;
+        };
         <NFTs<T>>::insert(nft_id, nft);
         Self::deposit_event(RawEvent::NonfungibleUpdate(issuer, nft_id));
         
         Ok(())
     }
 
+    fn _nonfungible_destroy(issuer: T::AccountId, nft_id: T::Hash) -> Result {
+        let nft = match Self::get_nft(&nft_id){
+            Some(t) => t,
+            None => return Err("nft not found"),
+        };
+        ensure!(nft.issuer == issuer, "not authorized as not the issuer of this nft");
+        ensure!(nft.total_supply.is_zero(), "nft still has tokens issued under it");
+
+        Self::_remove_nft_from_all_nfts_enumeration(nft_id)?;
+        Self::_remove_nft_from_owner_enumeration(issuer.clone(), nft_id)?;
+        for perm in nft.option.permissions.iter(){
+            <NftPermissions<T>>::remove((nft_id, perm.account.clone()));
+        };
+        <NFTs<T>>::remove(&nft_id);
+        <TokensUnderNFTVec<T>>::remove(&nft_id);
+        <TokenUnderNftCounter<T>>::remove(&nft_id);
+        if let Some((depositor, deposit)) = <NftDeposits<T>>::take(&nft_id){
+            Self::_unreserve_deposit(&depositor, deposit);
+        };
+        <NftMetadatas<T>>::remove(&nft_id);
+        <NftMetadataLocked<T>>::remove(&nft_id);
+        for key in <AttrSchemaKeys<T>>::take(&nft_id).into_iter(){
+            <AttrSchemas<T>>::remove((nft_id, key));
+        };
+        if let Some((depositor, deposit)) = <NftMetadataDeposits<T>>::take(&nft_id){
+            Self::_unreserve_deposit(&depositor, deposit);
+        };
+
+        Self::deposit_event(RawEvent::NonfungibleDestroy(issuer, nft_id));
+        Ok(())
+    }
+    fn _ensure_metadata_editable(issuer: T::AccountId, nft_id: T::Hash) -> Result {
+        ensure!(Self::_exists(nft_id), "nft not found");
+        ensure!(Self::_nft_owner_check(issuer, nft_id), "not authorized as not the issuer of this nft");
+        ensure!(!Self::is_nft_metadata_locked(nft_id), "nft metadata is locked");
+        Ok(())
+    }
+    fn _set_nft_metadata(issuer: T::AccountId, nft_id: T::Hash, metadata: NftMetadata) -> Result {
+        Self::_ensure_metadata_editable(issuer.clone(), nft_id)?;
+        metadata.validate()?;
+
+        let deposit = Self::_byte_deposit(metadata.encode().len());
+        Self::_reserve_deposit(&issuer, deposit)?;
+        if let Some((depositor, old_deposit)) = <NftMetadataDeposits<T>>::take(&nft_id){
+            Self::_unreserve_deposit(&depositor, old_deposit);
+        };
+        <NftMetadataDeposits<T>>::insert(&nft_id, (issuer.clone(), deposit));
+        <NftMetadatas<T>>::insert(&nft_id, metadata);
+
+        Self::deposit_event(RawEvent::NftMetadataSet(issuer, nft_id));
+        Ok(())
+    }
+    fn _clear_nft_metadata(issuer: T::AccountId, nft_id: T::Hash) -> Result {
+        Self::_ensure_metadata_editable(issuer.clone(), nft_id)?;
+        ensure!(<NftMetadatas<T>>::exists(&nft_id), "nft metadata not found");
+
+        <NftMetadatas<T>>::remove(&nft_id);
+        if let Some((depositor, deposit)) = <NftMetadataDeposits<T>>::take(&nft_id){
+            Self::_unreserve_deposit(&depositor, deposit);
+        };
+
+        Self::deposit_event(RawEvent::NftMetadataClear(issuer, nft_id));
+        Ok(())
+    }
+    fn _lock_nft_metadata(issuer: T::AccountId, nft_id: T::Hash) -> Result {
+        Self::_ensure_metadata_editable(issuer.clone(), nft_id)?;
+        <NftMetadataLocked<T>>::insert(&nft_id, true);
+
+        Self::deposit_event(RawEvent::NftMetadataLock(issuer, nft_id));
+        Ok(())
+    }
+    fn _set_attr_schema(issuer: T::AccountId, nft_id: T::Hash, key: Vec<u8>, schema: AttrSchema) -> Result {
+        ensure!(Self::_exists(nft_id), "nft not found");
+        ensure!(Self::_nft_owner_check(issuer.clone(), nft_id), "not authorized as not the issuer of this nft");
+        ensure!(key.len() > 0 && key.len() <= MAX_ATTR_KEY_LEN, "invalid attribute key length");
+        // values already held by tokens have to keep fitting the schema
+        for kind in [AttrValKind::String, AttrValKind::Uint64].iter(){
+            if *kind != schema.val_kind {
+                ensure!(Self::get_attr_usage((nft_id, key.clone(), *kind)) == 0, "attribute key in use with another value type");
+            };
+        };
+        // a key only becomes required once every token holds it, relaxing it leaves no token behind
+        let was_required = Self::get_attr_schema((nft_id, key.clone())).map(|old| old.required).unwrap_or(false);
+        if schema.required && !was_required {
+            let nft = match Self::get_nft(&nft_id){
+                Some(t) => t,
+                None => return Err("nft not found"),
+            };
+            let holders = Self::get_attr_usage((nft_id, key.clone(), schema.val_kind));
+            ensure!(holders == nft.total_supply.saturated_into::<u64>(), "attribute missing on some tokens, it can not be made required");
+        };
+        let mut keys = Self::get_attr_schema_keys(&nft_id);
+        if !keys.contains(&key){
+            ensure!(keys.len() < MAX_ATTR_SCHEMA_KEYS, "too many attribute keys in schema");
+            keys.push(key.clone());
+            <AttrSchemaKeys<T>>::insert(&nft_id, keys);
+        };
+        <AttrSchemas<T>>::insert((nft_id, key), schema);
+
+        Self::deposit_event(RawEvent::AttrSchemaSet(issuer, nft_id));
+        Ok(())
+    }
+    fn _remove_attr_schema(issuer: T::AccountId, nft_id: T::Hash, key: Vec<u8>) -> Result {
+        ensure!(Self::_exists(nft_id), "nft not found");
+        ensure!(Self::_nft_owner_check(issuer.clone(), nft_id), "not authorized as not the issuer of this nft");
+        ensure!(<AttrSchemas<T>>::exists((nft_id, key.clone())), "attribute key not in the schema of this nft");
+        // the values left behind could neither be written nor removed anymore
+        ensure!(!Self::_attr_in_use(nft_id, &key), "attribute key in use, remove its values first");
+        <AttrSchemas<T>>::remove((nft_id, key.clone()));
+        <AttrSchemaKeys<T>>::mutate(&nft_id, |x| x.retain(|k| *k != key));
+
+        Self::deposit_event(RawEvent::AttrSchemaRemove(issuer, nft_id));
+        Ok(())
+    }
+    fn _attr_in_use(nft_id: T::Hash, key: &Vec<u8>) -> bool {
+        Self::get_attr_usage((nft_id, key.clone(), AttrValKind::String)) > 0
+            || Self::get_attr_usage((nft_id, key.clone(), AttrValKind::Uint64)) > 0
+    }
+    // keep count of the attribute values held under a nft, schema changes are checked against it
+    pub fn _attr_usage_increase(nft_id: T::Hash, key: Vec<u8>, kind: AttrValKind) {
+        <AttrUsage<T>>::mutate((nft_id, key, kind), |n| *n = n.saturating_add(1));
+    }
+    pub fn _attr_usage_decrease(nft_id: T::Hash, key: Vec<u8>, kind: AttrValKind) {
+        let count = Self::get_attr_usage((nft_id, key.clone(), kind));
+        if count <= 1 {
+            <AttrUsage<T>>::remove((nft_id, key, kind));
+        } else {
+            <AttrUsage<T>>::insert((nft_id, key, kind), count - 1);
+        };
+    }
+    // check `sender` may write (`kind` is Some) or remove (`kind` is None) attribute `key` of a token
+    pub fn _ensure_attr_writable(sender: &T::AccountId, token_id: T::Hash, key: &Vec<u8>, kind: Option<AttrValKind>) -> Result {
+        let owner = match <erc721::Module<T>>::owner_of(&token_id){
+            Some(c) => c,
+            None => return Err("token not found"),
+        };
+        let token = match Self::get_token(&token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        let nft = match Self::get_nft(&token.nft_id){
+            Some(t) => t,
+            None => return Err("nft not found"),
+        };
+        // a reserved token may be bound to an open order, its attributes must not move under the buyer,
+        // reservations of modules are locked so the owner can not lift the freeze on its own
+        ensure!(!Self::get_token_reserve(&token_id), "token reserved, attributes are frozen");
+        if Self::get_attr_schema_keys(&token.nft_id).is_empty(){
+            // without a schema every attribute is issuer only, owner writable keys are declared in the schema
+            ensure!(nft.issuer == *sender, "attribute can only be written by the nft issuer");
+            return Ok(())
+        };
+        let schema = match Self::get_attr_schema((token.nft_id, key.clone())){
+            Some(t) => t,
+            None => return Err("attribute key not in the schema of this nft"),
+        };
+        match schema.mutability {
+            AttrMutability::IssuerOnly => {
+                ensure!(nft.issuer == *sender, "attribute can only be written by the nft issuer");
+            },
+            AttrMutability::OwnerWritable => {
+                ensure!(owner == *sender || nft.issuer == *sender, "sender is neither the token owner nor the nft issuer");
+            },
+        };
+        match kind {
+            Some(k) => ensure!(k == schema.val_kind, "attribute value type mismatches the schema"),
+            None => ensure!(!schema.required, "required attribute can not be removed"),
+        };
+        Ok(())
+    }
+    fn _remove_nft_from_all_nfts_enumeration(nft_id: T::Hash) -> Result {
+        let total_nft_count = Self::total_nft_supply();
+        let last_nft_index = match total_nft_count.checked_sub(&1.into()){
+            Some(c) => c,
+            None => return Err("Underflow removing nft from total supply"),
+        };
+        let nft_index = <AllNFTsIndexOf<T>>::get(nft_id);
+
+        if nft_index != last_nft_index {
+            let last_nft_id = Self::get_nft_by_index(last_nft_index);
+            <AllNFTsIndex<T>>::insert(nft_index, last_nft_id);
+            <AllNFTsIndexOf<T>>::insert(last_nft_id, nft_index);
+        }
+        <AllNFTsIndex<T>>::remove(last_nft_index);
+        <AllNFTsIndexOf<T>>::remove(nft_id);
+        <TotalNFTSupply<T>>::put(last_nft_index);
+        Ok(())
+    }
+    fn _add_nft_to_owner_enumeration(owner: T::AccountId, nft_id: T::Hash) -> Result {
+        let owned_nft_count = Self::nft_counter_owner(&owner);
+        let new_owned_nft_count = match owned_nft_count.checked_add(&1.into()){
+            Some(c) => c,
+            None => return Err("Overflow adding a new nft to account"),
+        };
+        <OwnedNFTsCounter<T>>::insert(&owner, new_owned_nft_count);
+        <OwnedNFTsIndex<T>>::insert(nft_id, owned_nft_count);
+        <OwnedNFTsVec<T>>::mutate(&owner, |x| x.push(nft_id));
+        <OwnedNFTs<T>>::insert((owner, owned_nft_count), nft_id);
+        Ok(())
+    }
+    fn _remove_nft_from_owner_enumeration(owner: T::AccountId, nft_id: T::Hash) -> Result {
+        let owned_nft_count = Self::nft_counter_owner(&owner);
+        let last_nft_index = match owned_nft_count.checked_sub(&1.into()){
+            Some(c) => c,
+            None => return Err("Underflow removing nft from account"),
+        };
+        let nft_index = <OwnedNFTsIndex<T>>::get(nft_id);
+
+        if nft_index != last_nft_index {
+            let last_nft_id = Self::nft_of_owner_by_index((owner.clone(), last_nft_index));
+            <OwnedNFTs<T>>::insert((owner.clone(), nft_index), last_nft_id);
+            <OwnedNFTsIndex<T>>::insert(last_nft_id, nft_index);
+        }
+        <OwnedNFTs<T>>::remove((owner.clone(), last_nft_index));
+        <OwnedNFTsIndex<T>>::remove(nft_id);
+        <OwnedNFTsCounter<T>>::insert(&owner, last_nft_index);
+        <OwnedNFTsVec<T>>::mutate(&owner, |x| x.retain(|id| *id != nft_id));
+        Ok(())
+    }
+    fn _holding_increase(holder: &T::AccountId, nft_id: T::Hash) {
+        let count = Self::held_token_count((holder.clone(), nft_id));
+        if count == 0 {
+            <HeldNFTs<T>>::mutate(holder, |x| x.push(nft_id));
+        };
+        <HeldTokenCount<T>>::insert((holder.clone(), nft_id), count.saturating_add(1));
+    }
+    fn _holding_decrease(holder: &T::AccountId, nft_id: T::Hash) {
+        let count = Self::held_token_count((holder.clone(), nft_id));
+        if count <= 1 {
+            <HeldTokenCount<T>>::remove((holder.clone(), nft_id));
+            <HeldNFTs<T>>::mutate(holder, |x| x.retain(|id| *id != nft_id));
+        } else {
+            <HeldTokenCount<T>>::insert((holder.clone(), nft_id), count - 1);
+        };
+    }
+    // keep the holder index in sync when a token changes hands, plain erc721 tokens are ignored
+    pub fn _on_transfer(from: &T::AccountId, to: &T::AccountId, token_id: &T::Hash) {
+        if let Some(token) = Self::get_token(token_id){
+            Self::_holding_decrease(from, token.nft_id);
+            Self::_holding_increase(to, token.nft_id);
+        };
+    }
+    // deposit for `len` bytes of data stored on chain
+    pub fn _byte_deposit(len: usize) -> BalanceOf<T> {
+        let len = len as u32;
+        T::DepositPerByte::get().saturating_mul(len.into())
+    }
+    // the class or token deposit `who` pays, nothing for exempt accounts
+    fn _deposit_of(who: &T::AccountId, amount: BalanceOf<T>) -> BalanceOf<T> {
+        if Self::is_deposit_exempt(who) {
+            return Zero::zero()
+        };
+        amount
+    }
+    pub fn _exempt_from_deposits(who: &T::AccountId) {
+        <DepositExempt<T>>::insert(who, true);
+    }
+    pub fn _reserve_deposit(who: &T::AccountId, amount: BalanceOf<T>) -> Result {
+        if amount.is_zero(){
+            return Ok(())
+        };
+        T::Currency::reserve(who, amount)
+    }
+    pub fn _unreserve_deposit(who: &T::AccountId, amount: BalanceOf<T>) {
+        if amount.is_zero(){
+            return
+        };
+        // deposits are only ever reserved by this module, nothing can be left behind
+        let _ = T::Currency::unreserve(who, amount);
+    }
+
     pub fn _token_unreserve(issuer: T::AccountId, token_id: T::Hash )->Result{
+        ensure!(Self::get_token_lock(&token_id).is_none(), "token locked by a module, unreserve now is forbidden");
+        Self::_release_reservation(issuer, token_id)
+    }
+    fn _release_reservation(issuer: T::AccountId, token_id: T::Hash )->Result{
         let owner = match  <erc721::Module<T>>::owner_of(&token_id){
             Some(c) => c,
             None => return Err("null token under this token_id")
@@ -301,6 +802,18 @@ impl<T: Trait> Module<T> //where <T as system::Trait>::Origin: core::convert::Fr
         <ReserveTokens<T>>::insert(token_id, false);
         Ok(())
     }
+    // reserve a token on behalf of a module, the owner can not lift it through `token_unreserve`
+    pub fn _token_lock(owner: T::AccountId, token_id: T::Hash, lock: LockIdentifier) -> Result {
+        Self::_token_reserve(owner, token_id)?;
+        <TokenLocks<T>>::insert(token_id, lock);
+        Ok(())
+    }
+    pub fn _token_unlock(owner: T::AccountId, token_id: T::Hash, lock: LockIdentifier) -> Result {
+        ensure!(Self::get_token_lock(&token_id) == Some(lock), "token not locked by this module");
+        Self::_release_reservation(owner, token_id)?;
+        <TokenLocks<T>>::remove(token_id);
+        Ok(())
+    }
     pub fn _token_reserve(issuer: T::AccountId, token_id: T::Hash )->Result {
         let owner = match  <erc721::Module<T>>::owner_of(&token_id){
             Some(c) => c,
@@ -308,6 +821,7 @@ impl<T: Trait> Module<T> //where <T as system::Trait>::Origin: core::convert::Fr
         };
         ensure!(owner == issuer , "not authrized as not the issuer of this token");
         ensure!(Self::get_token_reserve(token_id) == false, "token already reserved");
+        ensure!(!Self::_is_expired(&token_id), "token expired, reserve now is forbidden");
         <ReserveTokens<T>>::insert(token_id, true);
         Ok(())
     }
@@ -324,39 +838,62 @@ impl<T: Trait> Module<T> //where <T as system::Trait>::Origin: core::convert::Fr
         <NFTs<T>>::insert(nft_id, nft);
         Ok(())
     }
-    fn _destroy_token(issuer: T::AccountId, token_id: T::Hash )->Result {
+    pub fn _destroy_token(sender: T::AccountId, token_id: T::Hash, reason: u8)->Result {
         let owner = match  <erc721::Module<T>>::owner_of(&token_id){
             Some(c) => c,
             None => return Err("null token under this token_id")
         };
-        ensure!(owner == issuer , "not authrized as not the issuer of this token");
         let token = match Self::get_token(&token_id){
             Some(c) => c,
             None => return Err("null token under this token_id")
         };
+        if owner != sender {
+            let nft = match Self::get_nft(&token.nft_id){
+                Some(t) => t,
+                None => return Err("nft not found"),
+            };
+            ensure!(nft.issuer == sender && nft.option.issuer_burnable, "not authrized to burn this token");
+        };
+        <T as erc721::Trait>::Hooks::on_burn(&sender, &token_id, reason)?;
+        // call erc721 module functions
+        <erc721::Module<T>>::_burn(token_id)
+    }
+    // charge the token deposit of a plain erc721 token about to be minted
+    pub fn _on_mint(who: &T::AccountId, token_id: &T::Hash) -> Result {
+        let deposit = T::TokenDeposit::get();
+        Self::_reserve_deposit(who, deposit)?;
+        <TokenDeposits<T>>::insert(token_id, (who.clone(), deposit));
+        Ok(())
+    }
+    // drop everything kept for a token about to be burnt, plain erc721 tokens only get their deposit back
+    pub fn _on_burn(who: &T::AccountId, token_id: &T::Hash, reason: u8) -> Result {
+        let token = match Self::get_token(token_id){
+            Some(c) => c,
+            None => {
+                if let Some((depositor, deposit)) = <TokenDeposits<T>>::take(token_id){
+                    Self::_unreserve_deposit(&depositor, deposit);
+                };
+                return Ok(())
+            },
+        };
+        // a module holding the token has to release it first, a reservation of the owner is dropped
+        ensure!(Self::get_token_lock(token_id).is_none(), "token locked by a module, burn now is forbidden");
         let nft_id = token.nft_id;
-        match Self::_supply_decrease(nft_id){
-            Err(e) => return Err(e),
-            Ok(()) => {},
+        Self::_supply_decrease(nft_id)?;
+        if let Some(holder) = <erc721::Module<T>>::owner_of(token_id){
+            Self::_holding_decrease(&holder, nft_id);
         };
         // remove from reserve tokens
-        <ReserveTokens<T>>::remove(&token_id);
-        <Tokens<T>>::remove(&token_id);
-        let mut owner_vec = Self::get_tokens_nft_vec(&nft_id);
-        
-        // owner_vec.remove_item(&token_id);
-        let pos = owner_vec.iter().position(|x| *x == token_id);
-        match Some(owner_vec.remove(pos.unwrap())){
-            Some(c) => {},
-            None => {
-                // to do, alert this tokenid not list under this nft id
-            }
+        <ReserveTokens<T>>::remove(token_id);
+        <TokenLocks<T>>::remove(token_id);
+        <Tokens<T>>::remove(token_id);
+        if let Some((depositor, deposit)) = <TokenDeposits<T>>::take(token_id){
+            Self::_unreserve_deposit(&depositor, deposit);
         };
+        <TokensUnderNFTVec<T>>::mutate(&nft_id, |x| x.retain(|id| id != token_id));
 
-        Self::deposit_event(RawEvent::TokenDestroy(issuer, token_id));
-        // call erc721 module functions
-        <erc721::Module<T>>::_burn(token_id)
-
+        Self::deposit_event(RawEvent::TokenDestroy(who.clone(), *token_id, reason));
+        Ok(())
     }
     fn _supply_increase(nft_id: T::Hash) -> Result{
         let mut nft = match Self::get_nft(&nft_id){
@@ -375,35 +912,56 @@ impl<T: Trait> Module<T> //where <T as system::Trait>::Origin: core::convert::Fr
         <NFTs<T>>::insert(nft_id, nft);
         Ok(())
     }
-    fn _issue_token(issuer:T::AccountId, nft_id: T::Hash, symbol: Vec<u8>) -> Result{
+    fn _issue_token(issuer:T::AccountId, nft_id: T::Hash, symbol: Vec<u8>, expiry: Option<T::Moment>) -> Result{
+        Self::_issue_token_to(issuer.clone(), issuer, nft_id, symbol, expiry)?;
+        Ok(())
+    }
+    // issue a token paid by the issuer straight into the account of `to`
+    pub fn _issue_token_to(issuer:T::AccountId, to: T::AccountId, nft_id: T::Hash, symbol: Vec<u8>, expiry: Option<T::Moment>) -> result::Result<T::Hash, &'static str>{
         let token_nft_idx = Self::get_nft_token_counter(&nft_id);
         ensure!(Self::_exists(nft_id), "Nft id not exist");
+        if let Some(t) = expiry {
+            ensure!(t > <timestamp::Module<T>>::get(), "expiry must be in the future");
+        };
         ensure!(Self::_nft_owner_check(issuer.clone(), nft_id), "dont have the auth to issue your token as you are not the issuer of this nft assigned");
         let new_token_nft_idx = match token_nft_idx.checked_add(&1.into()){
             Some(c) => c,
             None => return Err("Overflow adding a new token to an existing nft"),
         };
-        let token_id =  (&issuer, new_token_nft_idx, nft_id).using_encoded(<T as system::Trait>::Hashing::hash);
-        ensure!(!<erc721::Module<T>>::_exists(token_id), "token id already exists");
+        let deposit = Self::_deposit_of(&issuer, T::TokenDeposit::get());
+        Self::_reserve_deposit(&issuer, deposit)?;
+        match Self::_supply_increase(nft_id){
+            Err(e) => {
+                Self::_unreserve_deposit(&issuer, deposit);
+                return Err(e)
+            },
+            Ok(()) => {},
+        };
+        // call erc721 module functions, the token id is the next serial under this nft
+        let token_id = match <erc721::Module<T>>::_mint_next(to.clone(), nft_id){
+            Ok(t) => t,
+            Err(e) => {
+                Self::_unreserve_deposit(&issuer, deposit);
+                Self::_supply_decrease(nft_id)?;
+                return Err(e)
+            },
+        };
         let new_token = Token{
             token_id: token_id,
             symbol: symbol, // symbol of this token
             nft_id: nft_id, // nft_id
+            expiry: expiry,
         };
-        match Self::_supply_increase(nft_id){
-            Err(e) => return Err(e),
-            Ok(()) => {},
-        };
+        <TokenDeposits<T>>::insert(&token_id, (issuer.clone(), deposit));
         <ReserveTokens<T>>::insert(token_id, false);
         <Tokens<T>>::insert(&token_id, new_token);
         <TokenUnderNftCounter<T>>::insert(nft_id, new_token_nft_idx);
         // let mut owner_vec = Self::get_tokens_nft_vec(&nft_id);
         // owner_vec.push(token_id);
         <TokensUnderNFTVec<T>>::mutate(&nft_id, |x| x.push(token_id));
-        Self::deposit_event(RawEvent::TokenIssue(issuer.clone(), token_id, nft_id));
-        // call erc721 module functions
-        <erc721::Module<T>>::_mint(issuer, token_id)
-
+        Self::_holding_increase(&to, nft_id);
+        Self::deposit_event(RawEvent::TokenIssue(issuer, token_id, nft_id));
+        Ok(token_id)
     }
     pub fn _reserve_safe_transfer(from:T::AccountId, to: T::AccountId, token_id:T::Hash) -> Result{
         // get nft_id and check validate
@@ -424,9 +982,199 @@ impl<T: Trait> Module<T> //where <T as system::Trait>::Origin: core::convert::Fr
         <erc721::Module<T>>::_transfer_from(from, to, token_id)
 
     }
+    // move a token into the account of a module and lock it there, nobody can sign for that account
+    pub fn _escrow_token(from: T::AccountId, pot: T::AccountId, token_id: T::Hash) -> Result {
+        ensure!(!Self::_is_expired(&token_id), "token expired, reserve now is forbidden");
+        Self::_reserve_safe_transfer(from, pot.clone(), token_id)?;
+        Self::_token_lock(pot, token_id, ESCROW_LOCK_ID)
+    }
+    // hand an escrowed token to `to`, an expired token can not move anymore and is reaped instead,
+    // returns whether the token was delivered
+    pub fn _release_escrow(pot: T::AccountId, to: T::AccountId, token_id: T::Hash) -> result::Result<bool, &'static str> {
+        Self::_token_unlock(pot.clone(), token_id, ESCROW_LOCK_ID)?;
+        let res = if Self::_is_expired(&token_id) {
+            Self::_destroy_token(pot, token_id, EXPIRED_BURN_REASON).map(|_| false)
+        } else {
+            <erc721::Module<T>>::_transfer_from(pot, to, token_id).map(|_| true)
+        };
+        if res.is_err() {
+            // the token stays in escrow
+            <ReserveTokens<T>>::insert(token_id, true);
+            <TokenLocks<T>>::insert(token_id, ESCROW_LOCK_ID);
+        };
+        res
+    }
+    // every holder of a nft with the number of tokens it holds
+    pub fn class_holders(nft_id: T::Hash) -> Vec<(T::AccountId, u64)> {
+        let mut holders = BTreeMap::<T::AccountId, u64>::new();
+        for token_id in Self::get_tokens_nft_vec(&nft_id).iter(){
+            if let Some(owner) = <erc721::Module<T>>::owner_of(token_id){
+                *holders.entry(owner).or_insert(0) += 1;
+            };
+        };
+        holders.into_iter().collect()
+    }
+    fn _airdrop(issuer: T::AccountId, nft_id: T::Hash, symbol: Vec<u8>, target: AirdropTarget<T::AccountId, T::Hash>, batch: u32) -> Result {
+        ensure!(Self::_exists(nft_id), "Nft id not exist");
+        ensure!(Self::_nft_owner_check(issuer.clone(), nft_id), "not authorized as not the issuer of this nft");
+        ensure!(batch > 0 && batch <= MAX_AIRDROP_BATCH, "airdrop batch out of range");
+        let recipients = match target {
+            AirdropTarget::Accounts(accounts) => accounts,
+            AirdropTarget::Holders(source) => {
+                let source_nft = match Self::get_nft(&source){
+                    Some(t) => t,
+                    None => return Err("source nft not exist"),
+                };
+                // bounded before the tokens are read
+                ensure!(source_nft.total_supply.saturated_into::<u64>() <= MAX_AIRDROP_RECIPIENTS as u64, "too many recipients for one airdrop");
+                Self::get_tokens_nft_vec(&source).iter()
+                    .filter_map(|token_id| <erc721::Module<T>>::owner_of(token_id))
+                    .collect()
+            },
+        };
+        ensure!(recipients.len() > 0, "no recipient for this airdrop");
+        ensure!(recipients.len() <= MAX_AIRDROP_RECIPIENTS, "too many recipients for one airdrop");
+
+        let nonce = AirdropNonce::get();
+        let airdrop_id = (b"airdrop", &issuer, nft_id, nonce).using_encoded(<T as system::Trait>::Hashing::hash);
+        // held until the airdrop finishes or is cancelled, given back when the first batch fails
+        let deposit = Self::_deposit_of(&issuer, Self::_byte_deposit(recipients.encode().len()));
+        Self::_reserve_deposit(&issuer, deposit)?;
+        let mut job = AirdropJob{
+            issuer: issuer.clone(),
+            nft_id,
+            symbol,
+            total: recipients.len() as u32,
+            cursor: 0,
+        };
+        let end = recipients.len().min(batch as usize);
+        if let Err(e) = Self::_airdrop_batch(&issuer, &mut job, &recipients[..end]) {
+            Self::_unreserve_deposit(&issuer, deposit);
+            return Err(e)
+        };
+        AirdropNonce::mutate(|n| *n += 1);
+        // only the recipients not served yet are kept, each under its own key
+        for (i, to) in recipients.into_iter().enumerate().skip(job.cursor as usize) {
+            <AirdropRecipients<T>>::insert((airdrop_id, i as u32), to);
+        };
+        <AirdropDeposits<T>>::insert(airdrop_id, (issuer.clone(), deposit));
+        Self::deposit_event(RawEvent::AirdropCreate(issuer.clone(), airdrop_id));
+        Self::_airdrop_save(issuer, airdrop_id, job);
+        Ok(())
+    }
+    fn _airdrop_continue(sender: T::AccountId, airdrop_id: T::Hash, batch: u32) -> Result {
+        let mut job = match Self::get_airdrop(&airdrop_id){
+            Some(t) => t,
+            None => return Err("airdrop not found"),
+        };
+        ensure!(job.issuer == sender, "not authorized as not the creator of this airdrop");
+        ensure!(batch > 0 && batch <= MAX_AIRDROP_BATCH, "airdrop batch out of range");
+        ensure!(Self::_nft_owner_check(sender.clone(), job.nft_id), "not authorized as not the issuer of this nft");
+
+        let start = job.cursor;
+        let end = job.total.min(start.saturating_add(batch));
+        let recipients: Vec<T::AccountId> = (start..end)
+            .filter_map(|i| Self::get_airdrop_recipient((airdrop_id, i)))
+            .collect();
+        Self::_airdrop_batch(&sender, &mut job, &recipients)?;
+        for i in start..job.cursor {
+            <AirdropRecipients<T>>::remove((airdrop_id, i));
+        };
+        Self::_airdrop_save(sender, airdrop_id, job);
+        Ok(())
+    }
+    // issue the tokens of the next recipients of an airdrop, it stops at the first failing recipient
+    // and only fails when nothing was issued, so a failed call leaves nothing behind
+    fn _airdrop_batch(sender: &T::AccountId, job: &mut AirdropJob<T::AccountId, T::Hash>, recipients: &[T::AccountId]) -> Result {
+        let mut issued = false;
+        for to in recipients.iter() {
+            let to = to.clone();
+            // blacklisted accounts are skipped
+            if Self::nft_perm((job.nft_id, to.clone())) != Some(false) {
+                match Self::_issue_token_to(sender.clone(), to, job.nft_id, job.symbol.clone(), None) {
+                    Ok(_) => issued = true,
+                    Err(e) => {
+                        if !issued {
+                            return Err(e)
+                        };
+                        break;
+                    },
+                };
+            };
+            job.cursor += 1;
+        };
+        Ok(())
+    }
+    // keep the progress of an airdrop, served recipients are never served twice
+    fn _airdrop_save(sender: T::AccountId, airdrop_id: T::Hash, job: AirdropJob<T::AccountId, T::Hash>) {
+        if job.cursor >= job.total {
+            <AirdropJobs<T>>::remove(airdrop_id);
+            if let Some((depositor, deposit)) = <AirdropDeposits<T>>::take(airdrop_id){
+                Self::_unreserve_deposit(&depositor, deposit);
+            };
+            Self::deposit_event(RawEvent::AirdropFinish(sender, airdrop_id));
+        } else {
+            let cursor = job.cursor;
+            <AirdropJobs<T>>::insert(airdrop_id, job);
+            Self::deposit_event(RawEvent::AirdropProgress(sender, airdrop_id, cursor));
+        };
+    }
+    fn _airdrop_cancel(sender: T::AccountId, airdrop_id: T::Hash) -> Result {
+        let job = match Self::get_airdrop(&airdrop_id){
+            Some(t) => t,
+            None => return Err("airdrop not found"),
+        };
+        ensure!(job.issuer == sender, "not authorized as not the creator of this airdrop");
+        <AirdropJobs<T>>::remove(airdrop_id);
+        for i in job.cursor..job.total {
+            <AirdropRecipients<T>>::remove((airdrop_id, i));
+        };
+        if let Some((depositor, deposit)) = <AirdropDeposits<T>>::take(airdrop_id){
+            Self::_unreserve_deposit(&depositor, deposit);
+        };
+        Self::deposit_event(RawEvent::AirdropCancel(sender, airdrop_id, job.cursor));
+        Ok(())
+    }
+    pub fn _is_expired(token_id: &T::Hash) -> bool {
+        let token = match Self::get_token(token_id){
+            Some(t) => t,
+            None => return false,
+        };
+        match token.expiry {
+            Some(t) => t <= <timestamp::Module<T>>::get(),
+            None => false,
+        }
+    }
+    pub fn _ensure_transferable(token_id: &T::Hash) -> Result {
+        // reserved tokens are held in escrow, they move only once unreserved
+        ensure!(!Self::get_token_reserve(token_id), "token reserved, transfer now is forbidden");
+        ensure!(!Self::_is_expired(token_id), "token expired, transfer now is forbidden");
+        Ok(())
+    }
+    fn _reap_expired_token(sender: T::AccountId, token_id: T::Hash) -> Result {
+        ensure!(Self::get_token(&token_id).is_some(), "null token under this token_id");
+        ensure!(Self::_is_expired(&token_id), "token not expired yet");
+        <T as erc721::Trait>::Hooks::on_burn(&sender, &token_id, EXPIRED_BURN_REASON)?;
+        <erc721::Module<T>>::_burn(token_id)
+    }
     // End ERC721 : Enumerable : Internal Functions //
 }
 
+impl<T: Trait> erc721::TokenHooks<T::AccountId, T::Hash> for Module<T> {
+    fn on_mint(who: &T::AccountId, token_id: &T::Hash) -> Result {
+        Self::_on_mint(who, token_id)
+    }
+    fn on_burn(who: &T::AccountId, token_id: &T::Hash, reason: u8) -> Result {
+        Self::_on_burn(who, token_id, reason)
+    }
+    fn ensure_transferable(token_id: &T::Hash) -> Result {
+        Self::_ensure_transferable(token_id)
+    }
+    fn on_transfer(from: &T::AccountId, to: &T::AccountId, token_id: &T::Hash) {
+        Self::_on_transfer(from, to, token_id)
+    }
+}
+
 #[cfg(test)]
 mod tests {
         use super::*;
@@ -490,18 +1238,40 @@ mod tests {
 
 
         
+        parameter_types! {
+            pub const MinimumPeriod: u64 = 5;
+        }
+        impl timestamp::Trait for Test {
+            type Moment = u64;
+            type OnTimestampSet = ();
+            type MinimumPeriod = MinimumPeriod;
+        }
         impl erc721::Trait for Test{
             type Event = ();
+            type Hooks = Module<Test>;
+        }
+        parameter_types! {
+            pub const ClassDeposit: Balance = 10;
+            pub const TokenDeposit: Balance = 2;
+            pub const DepositPerByte: Balance = 1;
         }
         impl Trait for Test{
             type Event = ();
             type Currency = balances::Module<Self>;
+            type ClassDeposit = ClassDeposit;
+            type TokenDeposit = TokenDeposit;
+            type DepositPerByte = DepositPerByte;
         }
 
         // This function basically just builds a genesis storage key/value store according to
         // our desired mockup.
         fn new_test_ext() -> runtime_io::TestExternalities {
-            system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+            let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+            balances::GenesisConfig::<Test>{
+                balances: vec![(0, 1000), (1, 1000), (2, 1000), (3, 1000)],
+                vesting: vec![],
+            }.assimilate_storage(&mut t).unwrap();
+            t.into()
         }
 
         pub type NftsModule = Module<Test>;
@@ -568,10 +1338,25 @@ mod tests {
                     permissions,
                     max_supply : 5,
                     description : "doggy".as_bytes().to_vec(),
+                    issuer_burnable : false,
                 };
-                NftsModule::nonfungible_update(Origin::signed(bob), Some(bobby), Some(new_option), nft_id);
+                // both the issuer and the option change in one call
+                let res = NftsModule::nonfungible_update(Origin::signed(bob), Some(bobby), Some(new_option.clone()), nft_id);
+                assert_eq!(res, Ok(()));
                 let nft_new =  NftsModule::get_nft(&nft_id).unwrap();
                 assert_eq!(nft_new.issuer , bobby);
+                assert_eq!(nft_new.option.max_supply, 5);
+                assert_eq!(NftsModule::nft_perm((nft_id, alice)), Some(false));
+                assert_eq!(NftsModule::nft_perm((nft_id, jack)), Some(true));
+                // the issuer indexes followed
+                assert_eq!(NftsModule::nft_counter_owner(&bob), 0);
+                assert_eq!(NftsModule::nft_counter_owner(&bobby), 1);
+                assert_eq!(NftsModule::nft_of_owner_by_index((bobby, 0)), nft_id);
+                // the old issuer lost every right, nothing moved on a refused update
+                let res = NftsModule::nonfungible_update(Origin::signed(bob), Some(bob), Some(new_option), nft_id);
+                assert_eq!(res, Err("not authorized as not the issuer of this nft"));
+                assert_eq!(NftsModule::get_nft(&nft_id).unwrap().issuer, bobby);
+                assert_eq!(NftsModule::nft_counter_owner(&bobby), 1);
                 print_option(nft_new.option);
 
                 
@@ -595,6 +1380,8 @@ mod tests {
                 assert_eq!(token.nft_id, nft_id);
                 assert_eq!(NftsModule::get_nft_token_counter(nft_id), 1);
                 assert_eq!(NftsModule::get_tokens_nft_vec(nft_id).len(), 1);
+                assert_eq!(Erc721Module::token_by_serial((nft_id, 0)), Some(token_id));
+                assert_eq!(Erc721Module::token_serial_of(token_id), Some((nft_id, 0)));
             });
         }
         #[test]
@@ -618,6 +1405,216 @@ mod tests {
             });
         }
 
+        #[test]
+        fn issuer_burn_token_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let burnable = |issuer_burnable: bool| NonfungibleOption{
+                    permissions: Vec::new(),
+                    max_supply : 10,
+                    description : Vec::new(),
+                    issuer_burnable,
+                };
+                NftsModule::nonfungible_create(Origin::signed(bob), "kitty".as_bytes().to_vec(), 10);
+                let fixed_nft = <AllNFTsIndex<Test>>::get(0);
+                NftsModule::issue_token(Origin::signed(bob), fixed_nft, "token_x".as_bytes().to_vec());
+                let tkx = Erc721Module::token_by_index(0);
+                NftsModule::_reserve_safe_transfer(bob, alice, tkx);
+                let res = NftsModule::burn_token(Origin::signed(bob), tkx, 7);
+                assert_eq!(res, Err("not authrized to burn this token"));
+                // the issuer can not grant itself the right to burn tokens already out
+                let res = NftsModule::nonfungible_update(Origin::signed(bob), None, Some(burnable(true)), fixed_nft);
+                assert_eq!(res, Err("issuer_burnable can only be turned on before any token is issued"));
+
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = <AllNFTsIndex<Test>>::get(1);
+                let res = NftsModule::nonfungible_update(Origin::signed(bob), None, Some(burnable(true)), nft_id);
+                assert_eq!(res, Ok(()));
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(1);
+                let tk1 = Erc721Module::token_by_index(2);
+                NftsModule::_reserve_safe_transfer(bob, alice, tk0);
+
+                let res = NftsModule::burn_token(Origin::signed(bob), tk0, 7);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token(tk0), None);
+                assert_eq!(Erc721Module::owner_of(tk0), None);
+                assert_eq!(Erc721Module::balance_of(alice), 1);
+                assert_eq!(NftsModule::get_tokens_nft_vec(nft_id), vec![tk1]);
+                assert_eq!(NftsModule::get_nft(nft_id).unwrap().total_supply, 1);
+                // turning it off again is always allowed
+                let res = NftsModule::nonfungible_update(Origin::signed(bob), None, Some(burnable(false)), nft_id);
+                assert_eq!(res, Ok(()));
+
+                // the plain erc721 path cleans up the nft side as well
+                let res = Erc721Module::burn_token(Origin::signed(bob), tk1);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token(tk1), None);
+                assert_eq!(NftsModule::get_token_deposit(tk1), None);
+                assert_eq!(NftsModule::get_nft(nft_id).unwrap().total_supply, 0);
+            });
+        }
+
+        #[test]
+        fn token_expiry_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                type Timestamp = timestamp::Module<Test>;
+                type BalancesModule = balances::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = <AllNFTsIndex<Test>>::get(0);
+                let res = NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec(), 100);
+                assert_eq!(res, Err("expiry must be in the future"));
+                let res = NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec(), 200);
+                assert_eq!(res, Ok(()));
+                let tk0 = Erc721Module::token_by_index(0);
+                assert_eq!(NftsModule::get_token(tk0).unwrap().expiry, Some(200));
+                assert_eq!(NftsModule::_is_expired(&tk0), false);
+                let res = NftsModule::reap_expired_token(Origin::signed(jack), tk0);
+                assert_eq!(res, Err("token not expired yet"));
+                let res = Erc721Module::transfer_from(Origin::signed(bob), bob, alice, tk0);
+                assert_eq!(res, Ok(()));
+
+                Timestamp::set_timestamp(200);
+                assert_eq!(NftsModule::_is_expired(&tk0), true);
+                let res = Erc721Module::transfer_from(Origin::signed(alice), alice, bob, tk0);
+                assert_eq!(res, Err("token expired, transfer now is forbidden"));
+                let res = NftsModule::token_reserve(Origin::signed(alice), tk0);
+                assert_eq!(res, Err("token expired, reserve now is forbidden"));
+
+                // anyone can reap it, the deposit goes back to the issuer
+                let reserved = BalancesModule::reserved_balance(bob);
+                let res = NftsModule::reap_expired_token(Origin::signed(jack), tk0);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token(tk0), None);
+                assert_eq!(Erc721Module::owner_of(tk0), None);
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved - 2);
+                assert_eq!(NftsModule::get_nft(nft_id).unwrap().total_supply, 0);
+            });
+        }
+
+        #[test]
+        fn account_nft_index_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                NftsModule::nonfungible_create(Origin::signed(bob), "catty".as_bytes().to_vec(), 10);
+                let nft0 = <AllNFTsIndex<Test>>::get(0);
+                let nft1 = <AllNFTsIndex<Test>>::get(1);
+
+                // issuer change moves the nft between issuer indexes
+                let res = NftsModule::nonfungible_update(Origin::signed(bob), Some(alice), None, nft0);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::nft_counter_owner(bob), 1);
+                assert_eq!(NftsModule::nft_of_owner_by_index((bob, 0)), nft1);
+                assert_eq!(NftsModule::get_nfts_owner_vec(bob), vec![nft1]);
+                assert_eq!(NftsModule::nft_counter_owner(alice), 1);
+                assert_eq!(NftsModule::nft_of_owner_by_index((alice, 0)), nft0);
+                assert_eq!(NftsModule::get_nfts_owner_vec(alice), vec![nft0]);
+
+                // holdings follow issue, transfer and burn
+                NftsModule::issue_token(Origin::signed(alice), nft0, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(alice), nft0, "token_1".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft1, "token_2".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                assert_eq!(NftsModule::get_held_nfts(alice), vec![nft0]);
+                assert_eq!(NftsModule::held_token_count((alice, nft0)), 2);
+
+                let res = NftsModule::_reserve_safe_transfer(alice, bob, tk0);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::held_token_count((alice, nft0)), 1);
+                assert_eq!(NftsModule::get_held_nfts(bob), vec![nft1, nft0]);
+                assert_eq!(NftsModule::held_token_count((bob, nft0)), 1);
+
+                let res = NftsModule::destroy_token(Origin::signed(alice), tk1);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::held_token_count((alice, nft0)), 0);
+                assert_eq!(NftsModule::get_held_nfts(alice).len(), 0);
+            });
+        }
+
+        #[test]
+        fn airdrop_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                let bobby = 3;
+                type BalancesModule = balances::Module<Test>;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                NftsModule::nonfungible_create(Origin::signed(bob), "badge".as_bytes().to_vec(), 10);
+                let nft0 = <AllNFTsIndex<Test>>::get(0);
+                let nft1 = <AllNFTsIndex<Test>>::get(1);
+
+                // one token per listed account, served in batches
+                let res = NftsModule::airdrop(Origin::signed(alice), nft0, "drop".as_bytes().to_vec(), AirdropTarget::Accounts(vec![alice]), 2);
+                assert_eq!(res, Err("not authorized as not the issuer of this nft"));
+                let res = NftsModule::airdrop(Origin::signed(bob), nft0, "drop".as_bytes().to_vec(), AirdropTarget::Accounts(vec![alice]), MAX_AIRDROP_BATCH + 1);
+                assert_eq!(res, Err("airdrop batch out of range"));
+                let res = NftsModule::airdrop(Origin::signed(bob), nft0, "drop".as_bytes().to_vec(), AirdropTarget::Accounts(vec![alice, jack, alice]), 2);
+                assert_eq!(res, Ok(()));
+                let airdrop_id = (b"airdrop", bob, nft0, 0u64).using_encoded(<Test as system::Trait>::Hashing::hash);
+                assert_eq!(NftsModule::get_airdrop(airdrop_id).unwrap().cursor, 2);
+                // only the unserved recipient is kept, paid for by the creator until the airdrop is done
+                assert_eq!(NftsModule::get_airdrop_recipient((airdrop_id, 1)), None);
+                assert_eq!(NftsModule::get_airdrop_recipient((airdrop_id, 2)), Some(alice));
+                let deposit = vec![alice, jack, alice].encode().len() as u64;
+                assert_eq!(NftsModule::get_airdrop_deposit(airdrop_id), Some((bob, deposit)));
+                assert_eq!(BalancesModule::reserved_balance(bob), 20 + 4 + deposit);
+                assert_eq!(NftsModule::class_holders(nft0), vec![(alice, 1), (jack, 1)]);
+
+                let res = NftsModule::airdrop_continue(Origin::signed(alice), airdrop_id, 2);
+                assert_eq!(res, Err("not authorized as not the creator of this airdrop"));
+                let res = NftsModule::airdrop_continue(Origin::signed(bob), airdrop_id, 2);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_airdrop(airdrop_id), None);
+                assert_eq!(NftsModule::get_airdrop_recipient((airdrop_id, 2)), None);
+                assert_eq!(NftsModule::get_airdrop_deposit(airdrop_id), None);
+                assert_eq!(BalancesModule::reserved_balance(bob), 20 + 6);
+                assert_eq!(NftsModule::class_holders(nft0), vec![(alice, 2), (jack, 1)]);
+                assert_eq!(NftsModule::get_nft(nft0).unwrap().total_supply, 3);
+
+                // one token per held token of the snapshotted nft
+                let res = NftsModule::airdrop(Origin::signed(bob), nft1, "badge".as_bytes().to_vec(), AirdropTarget::Holders(nft0), 8);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::class_holders(nft1), vec![(alice, 2), (jack, 1)]);
+                assert_eq!(NftsModule::held_token_count((alice, nft1)), 2);
+                assert_eq!(NftsModule::class_holders(nft1).iter().any(|(who, _)| *who == bobby), false);
+
+                // a batch stopping early keeps its progress, a call issuing nothing fails without a trace
+                let res = NftsModule::airdrop(Origin::signed(bob), nft1, "badge".as_bytes().to_vec(), AirdropTarget::Accounts(vec![jack; 9]), 8);
+                assert_eq!(res, Ok(()));
+                let airdrop_id = (b"airdrop", bob, nft1, 2u64).using_encoded(<Test as system::Trait>::Hashing::hash);
+                assert_eq!(NftsModule::get_airdrop(airdrop_id).unwrap().cursor, 6);
+                assert_eq!(NftsModule::get_nft(nft1).unwrap().total_supply, 9);
+                let res = NftsModule::airdrop_continue(Origin::signed(bob), airdrop_id, 2);
+                assert_eq!(res, Err("Overflow adding total_supply to max_supply"));
+                assert_eq!(NftsModule::get_airdrop(airdrop_id).unwrap().cursor, 6);
+                let res = NftsModule::airdrop(Origin::signed(bob), nft1, "badge".as_bytes().to_vec(), AirdropTarget::Accounts(vec![jack]), 1);
+                assert_eq!(res, Err("Overflow adding total_supply to max_supply"));
+                assert_eq!(AirdropNonce::get(), 3);
+                let reserved = BalancesModule::reserved_balance(bob);
+                assert_eq!(NftsModule::get_airdrop_deposit(airdrop_id), Some((bob, vec![jack; 9].encode().len() as u64)));
+
+                // a stalled airdrop can be dropped by its creator
+                let res = NftsModule::airdrop_cancel(Origin::signed(alice), airdrop_id);
+                assert_eq!(res, Err("not authorized as not the creator of this airdrop"));
+                let res = NftsModule::airdrop_cancel(Origin::signed(bob), airdrop_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_airdrop(airdrop_id), None);
+                assert_eq!(NftsModule::get_airdrop_recipient((airdrop_id, 8)), None);
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved - vec![jack; 9].encode().len() as u64);
+                assert_eq!(NftsModule::held_token_count((jack, nft1)), 7);
+            });
+        }
+
         #[test]
         fn reserve_token_test() {
             new_test_ext().execute_with(|| {
@@ -637,6 +1634,139 @@ mod tests {
                 NftsModule::token_unreserve(Origin::signed(bob), token_id);
                 assert_eq!(NftsModule::get_token_reserve(token_id), false);
 
+                // a reservation of the owner does not stop a burn and is dropped with the token
+                NftsModule::token_reserve(Origin::signed(bob), token_id).unwrap();
+                let res = NftsModule::burn_token(Origin::signed(bob), token_id, 0);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(token_id), false);
+
+                // an expired token reserved by its owner can still be reaped
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec(), 200).unwrap();
+                let token_id = Erc721Module::token_by_index(0);
+                NftsModule::token_reserve(Origin::signed(bob), token_id).unwrap();
+                Timestamp::set_timestamp(200);
+                let res = NftsModule::reap_expired_token(Origin::signed(jack), token_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token(token_id), None);
+
+                // a token locked by a module can only be burnt once the module released it
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_2".as_bytes().to_vec());
+                let token_id = Erc721Module::token_by_index(0);
+                NftsModule::_escrow_token(bob, bobby, token_id).unwrap();
+                assert_eq!(NftsModule::get_token_lock(token_id), Some(ESCROW_LOCK_ID));
+                let res = NftsModule::burn_token(Origin::signed(bobby), token_id, 0);
+                assert_eq!(res, Err("token locked by a module, burn now is forbidden"));
+                let res = NftsModule::token_unreserve(Origin::signed(bobby), token_id);
+                assert_eq!(res, Err("token locked by a module, unreserve now is forbidden"));
+                let res = NftsModule::_release_escrow(bobby, alice, token_id);
+                assert_eq!(res, Ok(true));
+                assert_eq!(NftsModule::get_token_lock(token_id), None);
+                let res = NftsModule::burn_token(Origin::signed(alice), token_id, 0);
+                assert_eq!(res, Ok(()));
+            });
+        }
+
+        #[test]
+        fn deposit_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type BalancesModule = balances::Module<Test>;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = <AllNFTsIndex<Test>>::get(0);
+                assert_eq!(BalancesModule::reserved_balance(bob), 10);
+                assert_eq!(NftsModule::get_nft_deposit(nft_id), Some((bob, 10)));
+
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let token_id = Erc721Module::token_by_index(0);
+                assert_eq!(BalancesModule::reserved_balance(bob), 12);
+
+                // destroying a nft with tokens left is not allowed
+                let res = NftsModule::nonfungible_destroy(Origin::signed(bob), nft_id);
+                assert_eq!(res, Err("nft still has tokens issued under it"));
+
+                // the deposit goes back to the issuer even when the token has changed hands
+                NftsModule::_reserve_safe_transfer(bob, alice, token_id);
+                NftsModule::destroy_token(Origin::signed(alice), token_id);
+                assert_eq!(BalancesModule::reserved_balance(bob), 10);
+                assert_eq!(NftsModule::get_token_deposit(token_id), None);
+
+                let res = NftsModule::nonfungible_destroy(Origin::signed(alice), nft_id);
+                assert_eq!(res, Err("not authorized as not the issuer of this nft"));
+                let res = NftsModule::nonfungible_destroy(Origin::signed(bob), nft_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(BalancesModule::reserved_balance(bob), 0);
+                assert_eq!(NftsModule::get_nft(nft_id), None);
+                assert_eq!(NftsModule::total_nft_supply(), 0);
+                assert_eq!(NftsModule::get_nfts_owner_vec(bob).len(), 0);
+
+                // not enough free balance to cover the deposit
+                let res = NftsModule::nonfungible_create(Origin::signed(4), "catty".as_bytes().to_vec(), 10);
+                assert!(res.is_err());
+                assert_eq!(NftsModule::total_nft_supply(), 0);
+                assert_eq!(NftsModule::nft_counter_owner(4), 0);
+
+                // plain erc721 tokens pay the token deposit too
+                let res = Erc721Module::create_token(Origin::signed(alice));
+                assert_eq!(res, Ok(()));
+                let plain_id = Erc721Module::token_by_index(0);
+                assert_eq!(BalancesModule::reserved_balance(alice), 2);
+                assert_eq!(NftsModule::get_token_deposit(plain_id), Some((alice, 2)));
+                let res = Erc721Module::burn_token(Origin::signed(alice), plain_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(BalancesModule::reserved_balance(alice), 0);
+                assert_eq!(NftsModule::get_token_deposit(plain_id), None);
+                let res = Erc721Module::create_token(Origin::signed(4));
+                assert!(res.is_err());
+                assert_eq!(Erc721Module::total_supply(), 0);
+            });
+        }
+
+        #[test]
+        fn nft_metadata_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type BalancesModule = balances::Module<Test>;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = <AllNFTsIndex<Test>>::get(0);
+                let reserved = BalancesModule::reserved_balance(bob);
+
+                let mut metadata = NftMetadata{
+                    name: "Doggy".as_bytes().to_vec(),
+                    description: "dogs of the chain".as_bytes().to_vec(),
+                    image_uri: "ipfs://doggy".as_bytes().to_vec(),
+                    external_url: Vec::new(),
+                    extra: vec![("breed".as_bytes().to_vec(), "husky".as_bytes().to_vec())],
+                };
+                let res = NftsModule::set_nft_metadata(Origin::signed(alice), nft_id, metadata.clone());
+                assert_eq!(res, Err("not authorized as not the issuer of this nft"));
+                let res = NftsModule::set_nft_metadata(Origin::signed(bob), nft_id, metadata.clone());
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_nft_metadata(nft_id), Some(metadata.clone()));
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved + metadata.encode().len() as u64);
+
+                metadata.external_url = vec![b'x'; MAX_METADATA_FIELD_LEN + 1];
+                let res = NftsModule::set_nft_metadata(Origin::signed(bob), nft_id, metadata.clone());
+                assert_eq!(res, Err("metadata external url too long"));
+
+                let res = NftsModule::clear_nft_metadata(Origin::signed(bob), nft_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_nft_metadata(nft_id), None);
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved);
+
+                metadata.external_url = Vec::new();
+                NftsModule::set_nft_metadata(Origin::signed(bob), nft_id, metadata.clone());
+                let res = NftsModule::lock_nft_metadata(Origin::signed(bob), nft_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::is_nft_metadata_locked(nft_id), true);
+                let res = NftsModule::set_nft_metadata(Origin::signed(bob), nft_id, NftMetadata::default());
+                assert_eq!(res, Err("nft metadata is locked"));
+                let res = NftsModule::clear_nft_metadata(Origin::signed(bob), nft_id);
+                assert_eq!(res, Err("nft metadata is locked"));
+                assert_eq!(NftsModule::get_nft_metadata(nft_id), Some(metadata));
             });
         }
 
@@ -1,13 +1,17 @@
 
 use codec::{Encode, Decode};
-use system::ensure_signed;
-use sr_primitives::traits::{Hash};
+use system::{ensure_signed, ensure_root};
+use sr_primitives::traits::{Hash, SignedExtension};
+use sr_primitives::transaction_validity::{TransactionValidity, TransactionValidityError, InvalidTransaction};
+use sr_primitives::weights::DispatchInfo;
 use rstd::prelude::*;
 use support::{
-    decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap,     
+    decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue,
+    traits::{Get, EnsureOrigin, LockIdentifier},
 
 };
-use sr_primitives::traits::{CheckedAdd, CheckedSub};
+use sr_primitives::traits::{CheckedAdd, CheckedSub, SaturatedConversion, Saturating, Zero, AccountIdConversion};
+use sr_primitives::ModuleId;
 use crate::erc721;
 use rstd::collections::btree_map::BTreeMap;
 // use rstd::collections::btree_set::BTreeSet;
@@ -22,18 +26,43 @@ use rstd::ops::Bound::*;
 
 pub trait Trait: nfts::Trait + timestamp::Trait + generic_asset::Trait {
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;   
+    // a bid this close to the end of an english auction pushes the end out to now + extension
+    type AuctionExtension: Get<Self::Moment>;
+    // sets the trading fees and the account they are paid to
+    type FeeOrigin: EnsureOrigin<Self::Origin>;
 }
 
 // pub type HashOf<T> = <T as system::Trait>::Hash;
 
 #[derive(Encode, Decode, Clone, PartialEq, Debug)]
-enum OrderStatus{
+pub enum OrderStatus{
     Open,
     PartialFilled,
     Filled,
     Closed,
     Canceled,
 }
+
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Debug)]
+pub enum TimeInForce{
+    // rest in the orderbook until filled, canceled or expired
+    GoodTillCancel,
+    // fill what matches on entry, drop the rest
+    ImmediateOrCancel,
+    // fill completely on entry or reject the order
+    FillOrKill,
+    // rest without matching, reject the order if it would match on entry
+    PostOnly,
+}
+impl TimeInForce{
+    // whether what is left after matching goes to the orderbook
+    fn rests(&self) -> bool {
+        match *self {
+            TimeInForce::GoodTillCancel | TimeInForce::PostOnly => true,
+            TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => false,
+        }
+    }
+}
 #[derive(Encode, Decode, Clone, PartialEq, Debug)]
 pub enum TokenAttrValType{
     String(Vec<u8>),
@@ -65,10 +94,44 @@ impl TokenAttrValType{
             Uint64(t2) => unwrap_failed("called `Result::unwrap()` on an `Err` value", &t2),
         }
     }
+    pub fn kind(&self) -> nfts::AttrValKind {
+        match *self {
+            String(_) => nfts::AttrValKind::String,
+            Uint64(_) => nfts::AttrValKind::Uint64,
+        }
+    }
     
     
 }
 type TokenAttrType = BTreeMap<Vec<u8>, TokenAttrValType>;
+// built-in attribute holding the token expiry as Uint64, selectable but never written by users
+pub const EXPIRY_ATTR_KEY: &[u8] = b"_expiry";
+// bounds the orders reaped in on_finalize of one block
+pub const MAX_EXPIRED_REAP_PER_BLOCK: usize = 32;
+// orders expiring within one bucket of this many moments are reaped together
+pub const EXPIRY_BUCKET: u64 = 1000;
+// bounds the buckets on_finalize of one block walks through
+pub const MAX_EXPIRY_BUCKETS_PER_BLOCK: u32 = 64;
+// latest order expiry, in moments from the order creation
+pub const MAX_ORDER_LIFETIME: u64 = 90 * 24 * 3600 * 1000;
+// bounds the tokens of one unlock_stranded_tokens call
+pub const MAX_UNLOCK_BATCH: usize = 100;
+// bounds the tokens a call gate looks at while validating a transaction
+pub const MAX_GATE_SCAN: usize = 64;
+// lock held on the tokens reserved by this module, owners can not lift it themselves
+pub const ORDER_LOCK_ID: LockIdentifier = *b"nft/ordr";
+// account holding the tokens put up for auction
+const ORDER_MODULE_ID: ModuleId = ModuleId(*b"nft/ordr");
+// trades kept on chain, the oldest one is overwritten beyond it
+pub const MAX_TRADE_RECORDS: u64 = 10000;
+// bounds one page of the trade records api
+pub const MAX_TRADE_PAGE: u32 = 100;
+// closed orders kept per account, the oldest one is dropped beyond it
+pub const MAX_CLOSED_ORDERS: usize = 100;
+// highest maker or taker fee, in per mille of the trade price
+pub const MAX_FEE_PER_MILLE: u32 = 100;
+// bounds the work of settling a sealed bid auction
+pub const MAX_SEALED_BIDDERS: usize = 100;
 
 #[derive(Encode, Decode, Clone, PartialEq, PartialOrd, Copy, Debug)]
 pub enum CompareOpcode
@@ -457,7 +520,8 @@ pub struct AskOrderItem<T> where T:Trait{
     asset: T::AssetId,
     price: T::Balance,
     timepoint: T::Moment,
-    immediate_or_cancel: bool,
+    time_in_force: TimeInForce,
+    expire_at: Option<T::Moment>,
     bind_tokens: Vec<T::Hash>,
     status: OrderStatus,
 }
@@ -469,7 +533,7 @@ impl<T:Trait> core::fmt::Display for AskOrderItem<T>{
         self.asset, 
         self.price, 
         self.timepoint, 
-        self.immediate_or_cancel, 
+        self.time_in_force, 
         self.bind_tokens.len(), 
         self.status  )
     }
@@ -483,7 +547,8 @@ pub struct BidOrderItem<T> where T:Trait{
     asset: T::AssetId,
     price: T::Balance,
     timepoint: T::Moment,
-    immediate_or_cancel: bool,
+    time_in_force: TimeInForce,
+    expire_at: Option<T::Moment>,
     tk_count_to_buy: BalanceOf<T>,
     status: OrderStatus,
 }
@@ -496,13 +561,106 @@ impl<T:Trait> core::fmt::Display for BidOrderItem<T>{
         self.asset, 
         self.price, 
         self.timepoint, 
-        self.immediate_or_cancel, 
+        self.time_in_force, 
         self.tk_count_to_buy, 
         self.status  )
     }
 }
 
 
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq)]
+pub enum PriceCurve{
+    // price falls continuously from start to end
+    Linear,
+    // price falls in this many equal steps from start to end
+    Stepwise(u32),
+}
+
+// pricing of a dutch listing, the listing itself is an ask order of a single token
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+pub struct DutchListing<Balance, Moment>{
+    start_price: Balance,
+    floor_price: Balance,
+    start: Moment,
+    end: Moment,
+    curve: PriceCurve,
+}
+
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct EnglishAuction<T> where T:Trait{
+    seller: T::AccountId,
+    auction_id: T::Hash,
+    token_id: T::Hash,
+    asset: T::AssetId,
+    reserve_price: T::Balance,
+    min_increment: T::Balance,
+    end: T::Moment,
+    top_bid: Option<(T::AccountId, T::Balance)>, // bidder and amount, reserved
+}
+
+// bids are committed as hashes, revealed later, and the highest bidder pays the second price
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct SealedAuction<T> where T:Trait{
+    seller: T::AccountId,
+    auction_id: T::Hash,
+    token_id: T::Hash,
+    asset: T::AssetId,
+    reserve_price: T::Balance,
+    deposit: T::Balance, // reserved with every commit, lost if the bid is never revealed
+    commit_end: T::Moment,
+    reveal_end: T::Moment,
+    highest: Option<(T::AccountId, T::Balance)>, // bidder and amount, reserved
+    second_price: T::Balance, // what the highest bidder pays, at least the reserve price
+}
+
+// an open order as the order api hands it out
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+pub struct OrderDetails<Hash, AssetId, Balance, Moment>{
+    pub order_id: Hash,
+    pub is_bid: bool,
+    pub asset: AssetId,
+    pub price: Balance,
+    pub timepoint: Moment,
+    pub time_in_force: TimeInForce,
+    pub expire_at: Option<Moment>,
+    pub status: OrderStatus,
+    pub remaining: u32, // tokens left to sell or to buy
+    pub bind_tokens: Vec<Hash>, // tokens left to sell, empty for bid orders
+}
+
+// trading fees of an asset in per mille of the trade price, the resting order is the maker
+#[derive(Encode, Decode, Clone, Copy, Default, Debug, PartialEq)]
+pub struct FeeSchedule{
+    maker: u32,
+    taker: u32,
+}
+
+// a filled token, orders of auctions and dutch buys use the default hash on the side they lack
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+pub struct TradeRecord<AccountId, Hash, AssetId, Balance, Moment>{
+    pub seq: u64,
+    pub ask_order_id: Hash,
+    pub bid_order_id: Hash,
+    pub seller: AccountId,
+    pub buyer: AccountId,
+    pub token_id: Hash,
+    pub asset: AssetId,
+    pub price: Balance,
+    pub timepoint: Moment,
+}
+
+// what is left of an order once it leaves the orderbook
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+pub struct ClosedOrder<Hash, AssetId, Balance, Moment>{
+    pub order_id: Hash,
+    pub is_bid: bool,
+    pub asset: AssetId,
+    pub price: Balance,
+    pub remaining: u32, // tokens left unsold or unbought
+    pub status: OrderStatus, // Filled, Closed or Canceled
+    pub closed_at: Moment,
+}
+
 #[derive(Encode, Decode, Clone, Debug, PartialEq)]
 pub struct TokenPrice<T> where T:Trait{
     asset: T::AssetId,
@@ -525,14 +683,38 @@ decl_event!(
     {
         // TokenOrderCreate(AccountId, Hash), 
 
-        // creator, order_id, asset, price, timepoint, immediate_or_cancel
-        OrderOpened(AccountId, Hash, AssetId, Balance, Moment, bool),
-        // seller, buyer, token_id,trade_asset, trade_price, timepoint, 
-        OrderFilled(AccountId, AccountId, Hash, AssetId, Balance, Moment),
+        // creator, order_id, asset, price, timepoint, time_in_force
+        OrderOpened(AccountId, Hash, AssetId, Balance, Moment, TimeInForce),
+        // seller, buyer, token_id,trade_asset, trade_price, timepoint, seller_fee, buyer_fee
+        OrderFilled(AccountId, AccountId, Hash, AssetId, Balance, Moment, Balance, Balance),
         // creator, order_id, timepoint, 
         OrderCanceled(AccountId, Hash, Moment),
-        // creator, order_id, asset, price, timepoint, immediate_or_cancel
-        OrderClosed(AccountId, Hash, AssetId, Balance, Moment, bool),
+        // creator, order_id, asset, price, timepoint, time_in_force
+        OrderClosed(AccountId, Hash, AssetId, Balance, Moment, TimeInForce),
+        // creator, order_id, timepoint
+        OrderExpired(AccountId, Hash, Moment),
+        // order_id, the order stays open and can still be canceled
+        OrderExpiryFailed(Hash),
+        // module index, call index
+        CallGateSet(u8, u8),
+        // token_id
+        StrandedTokenUnlocked(Hash),
+        // asset, maker fee, taker fee, both per mille
+        FeeScheduleSet(AssetId, u32, u32),
+        // account receiving the trading fees, `None` stops charging them
+        FeeAccountSet(Option<AccountId>),
+        // seller, auction_id, token_id, asset, reserve_price, end
+        AuctionCreated(AccountId, Hash, Hash, AssetId, Balance, Moment),
+        // bidder, auction_id, amount, end
+        AuctionBid(AccountId, Hash, Balance, Moment),
+        // seller, winner, auction_id, price
+        AuctionSettled(AccountId, AccountId, Hash, Balance),
+        // seller, auction_id, closed without a sale
+        AuctionClosed(AccountId, Hash),
+        // bidder, auction_id
+        SealedBidCommitted(AccountId, Hash),
+        // bidder, auction_id, amount
+        SealedBidRevealed(AccountId, Hash, Balance),
     }
 );
 
@@ -541,6 +723,30 @@ decl_storage! {
     trait Store for Module<T: Trait> as NFTStorage {
         // token_id => token Attributes btreemap(String -> enum(String, integer) )
         TokenAttribuites get(get_token_attr) : map T::Hash => TokenAttrType;
+        // (token_id, attribute key) => (depositor, amount) reserved for storing the attribute
+        AttributeDeposits get(get_attr_deposit) : map (T::Hash, Vec<u8>) => Option<(T::AccountId, BalanceOf<T>)>;
+        // token_id => ask order the token is bound to
+        TokenAskOrder get(get_token_ask_order) : map T::Hash => Option<T::Hash>;
+        // (module index, call index) => tokens the signer must hold to submit the call
+        CallGates get(get_call_gate) : map (u8, u8) => Option<TokenSelector<T::Hash>>;
+        // order_id => pricing of a dutch listing, those ask orders stay out of the orderbook
+        DutchListings get(get_dutch_listing) : map T::Hash => Option<DutchListing<T::Balance, T::Moment>>;
+        // end of an EXPIRY_BUCKET => orders resting in the orderbooks until an expiry within that bucket
+        ExpiryQueue get(get_expiry_queue) : map T::Moment => Vec<T::Hash>;
+        // next bucket of ExpiryQueue to reap
+        ExpiryCursor get(get_expiry_cursor) : T::Moment;
+        // orders waiting in ExpiryQueue
+        ExpiryCount get(get_expiry_count) : u64;
+        // auction_id => english auction
+        EnglishAuctions get(get_english_auction) : map T::Hash => Option<EnglishAuction<T>>;
+        // token_id => english or sealed bid auction selling the token
+        TokenAuction get(get_token_auction) : map T::Hash => Option<T::Hash>;
+        // auction_id => sealed bid auction
+        SealedAuctions get(get_sealed_auction) : map T::Hash => Option<SealedAuction<T>>;
+        // (auction_id, bidder) => commitment not revealed yet
+        SealedCommits get(get_sealed_commit) : map (T::Hash, T::AccountId) => Option<T::Hash>;
+        // auction_id => every account that committed
+        SealedBidders get(get_sealed_bidders) : map T::Hash => Vec<T::AccountId>;
         // order_id =>  AskOrderItem
         AskTokenOrders get(get_ask_token_order) : map T::Hash => Option<AskOrderItem<T>>;
         // order_id => bidorderitem
@@ -548,11 +754,20 @@ decl_storage! {
         // orderbook[asset]     price => order_id vec
         AskOrderBook get(get_orderbook_ask): map T::AssetId => BTreeMap<T::Balance, Vec<T::Hash>>;
         BidOrderBook get(get_orderbook_bid): map T::AssetId => BTreeMap<T::Balance, Vec<T::Hash>>;
-        // order id => account
-        // OrderOwner get(get_order_owner): map T::Hash => T::AccountId;
-        // (account, index ) => order_id
-        // account_id => vec<order_id>
-        // OwnedOrders get(get_orders_owned): map T::AccountId => Vec<T::Hash>;
+        // account_id => open ask orders, dutch listings included
+        OwnedAskOrders get(get_owned_ask_orders): map T::AccountId => Vec<T::Hash>;
+        // account_id => open bid orders
+        OwnedBidOrders get(get_owned_bid_orders): map T::AccountId => Vec<T::Hash>;
+        // asset => trading fees, none until set
+        FeeSchedules get(get_fee_schedule): map T::AssetId => FeeSchedule;
+        // account receiving the trading fees, no fees are charged until it is set
+        FeeAccount get(get_fee_account): Option<T::AccountId>;
+        // seq % MAX_TRADE_RECORDS => trade
+        TradeRecords get(get_trade_record): map u64 => Option<TradeRecord<T::AccountId, T::Hash, T::AssetId, T::Balance, T::Moment>>;
+        // trades recorded so far, the seq of the next one
+        TradeCount get(get_trade_count): u64;
+        // account_id => latest closed orders, oldest first
+        ClosedOrders get(get_closed_orders): map T::AccountId => Vec<ClosedOrder<T::Hash, T::AssetId, T::Balance, T::Moment>>;
         // Nonce: u64;
     }
 }
@@ -561,6 +776,10 @@ decl_module! {
     
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         fn deposit_event() = default;
+
+        fn on_finalize(_n: T::BlockNumber) {
+            Self::_reap_expired_orders(MAX_EXPIRED_REAP_PER_BLOCK);
+        }
         fn set_token_attr(origin, token_id:T::Hash , attribute: Attributes ) -> Result{
             let sender = ensure_signed(origin)?;
             Self::_set_token_attr(sender, token_id, attribute)
@@ -573,17 +792,19 @@ decl_module! {
             selector: TokenSelector<T::Hash>,
             asset: T::AssetId,
             price: T::Balance,
-            immediate_or_cancel: bool) -> Result{
+            time_in_force: TimeInForce,
+            expire_at: Option<T::Moment>) -> Result{
             let creator = ensure_signed(origin)?;
-            Self::_token_buy_order_create(creator, selector, asset, price, immediate_or_cancel)
+            Self::_token_buy_order_create(creator, selector, asset, price, time_in_force, expire_at)
         }
         fn token_sell_order_create(origin,
             selector: TokenSelector<T::Hash>,
             asset: T::AssetId,
             price: T::Balance,
-            immediate_or_cancel: bool) -> Result{
+            time_in_force: TimeInForce,
+            expire_at: Option<T::Moment>) -> Result{
             let creator = ensure_signed(origin)?;
-            Self::_token_sell_order_create(creator, selector, asset, price, immediate_or_cancel)
+            Self::_token_sell_order_create(creator, selector, asset, price, time_in_force, expire_at)
         }
         
         fn token_buy_order_cancel(origin, order_id:T::Hash) -> Result{
@@ -595,6 +816,112 @@ decl_module! {
             let creator = ensure_signed(origin)?;
             Self::_token_sell_order_cancel(creator, order_id)
         }
+
+        fn dutch_listing_create(origin,
+            token_id: T::Hash,
+            asset: T::AssetId,
+            start_price: T::Balance,
+            floor_price: T::Balance,
+            end: T::Moment,
+            curve: PriceCurve) -> Result{
+            let seller = ensure_signed(origin)?;
+            Self::_dutch_listing_create(seller, token_id, asset, start_price, floor_price, end, curve)
+        }
+
+        // buy at the current price, which must not exceed `max_price`
+        fn dutch_buy(origin, order_id: T::Hash, max_price: T::Balance) -> Result{
+            let buyer = ensure_signed(origin)?;
+            Self::_dutch_buy(buyer, order_id, max_price)
+        }
+
+        fn dutch_listing_cancel(origin, order_id: T::Hash) -> Result{
+            let seller = ensure_signed(origin)?;
+            Self::_dutch_listing_cancel(seller, order_id)
+        }
+
+        fn english_auction_create(origin,
+            token_id: T::Hash,
+            asset: T::AssetId,
+            reserve_price: T::Balance,
+            min_increment: T::Balance,
+            end: T::Moment) -> Result{
+            let seller = ensure_signed(origin)?;
+            Self::_english_auction_create(seller, token_id, asset, reserve_price, min_increment, end)
+        }
+
+        fn english_auction_bid(origin, auction_id: T::Hash, amount: T::Balance) -> Result{
+            let bidder = ensure_signed(origin)?;
+            Self::_english_auction_bid(bidder, auction_id, amount)
+        }
+
+        // anyone can settle an auction once it ended
+        fn english_auction_settle(origin, auction_id: T::Hash) -> Result{
+            ensure_signed(origin)?;
+            Self::_english_auction_settle(auction_id)
+        }
+
+        // only before the first bid
+        fn english_auction_cancel(origin, auction_id: T::Hash) -> Result{
+            let seller = ensure_signed(origin)?;
+            Self::_english_auction_cancel(seller, auction_id)
+        }
+
+        fn sealed_auction_create(origin,
+            token_id: T::Hash,
+            asset: T::AssetId,
+            reserve_price: T::Balance,
+            deposit: T::Balance,
+            commit_end: T::Moment,
+            reveal_end: T::Moment) -> Result{
+            let seller = ensure_signed(origin)?;
+            Self::_sealed_auction_create(seller, token_id, asset, reserve_price, deposit, commit_end, reveal_end)
+        }
+
+        // `commitment` is `sealed_bid_commitment(auction_id, bidder, amount, salt)`
+        fn sealed_bid_commit(origin, auction_id: T::Hash, commitment: T::Hash) -> Result{
+            let bidder = ensure_signed(origin)?;
+            Self::_sealed_bid_commit(bidder, auction_id, commitment)
+        }
+
+        fn sealed_bid_reveal(origin, auction_id: T::Hash, amount: T::Balance, salt: Vec<u8>) -> Result{
+            let bidder = ensure_signed(origin)?;
+            Self::_sealed_bid_reveal(bidder, auction_id, amount, salt)
+        }
+
+        // anyone can settle an auction once the reveal phase ended
+        fn sealed_auction_settle(origin, auction_id: T::Hash) -> Result{
+            ensure_signed(origin)?;
+            Self::_sealed_auction_settle(auction_id)
+        }
+
+        // unlock tokens left locked by this module while no order or auction holds them anymore,
+        // tokens locked by other modules are skipped
+        fn unlock_stranded_tokens(origin, token_ids: Vec<T::Hash>) -> Result{
+            ensure_root(origin)?;
+            Self::_unlock_stranded_tokens(token_ids)
+        }
+
+        fn set_fee_schedule(origin, asset: T::AssetId, maker: u32, taker: u32) -> Result{
+            T::FeeOrigin::ensure_origin(origin)?;
+            Self::_set_fee_schedule(asset, maker, taker)
+        }
+
+        // fees are only charged while a fee account is set, `None` clears it
+        fn set_fee_account(origin, account: Option<T::AccountId>) -> Result{
+            T::FeeOrigin::ensure_origin(origin)?;
+            match account {
+                Some(ref t) => <FeeAccount<T>>::put(t),
+                None => <FeeAccount<T>>::kill(),
+            };
+            Self::deposit_event(RawEvent::FeeAccountSet(account));
+            Ok(())
+        }
+
+        // gate a call of the runtime behind token holdings, `None` lifts the gate
+        fn set_call_gate(origin, module_index: u8, call_index: u8, gate: Option<TokenSelector<T::Hash>>) -> Result{
+            ensure_root(origin)?;
+            Self::_set_call_gate(module_index, call_index, gate)
+        }
     }
 
     
@@ -602,33 +929,110 @@ decl_module! {
 
 
 impl<T: Trait> Module<T> {
+    pub fn account_id() -> T::AccountId {
+        ORDER_MODULE_ID.into_account()
+    }
     
     fn _set_token_attr(sender: T::AccountId, token_id:T::Hash , attribute: Attributes ) -> Result{
         // let mut token_attributes = Self::get_token_attr(&token_id);
         // token_attributes.insert(attribute.key, attribute.value);
-        let owner = <erc721::Module<T>>::owner_of(&token_id);
-        if owner.is_none() {
-            return Err("token not found")
+        ensure!(attribute.key.as_slice() != EXPIRY_ATTR_KEY, "built-in attribute key is read only");
+        <nfts::Module<T>>::_ensure_attr_writable(&sender, token_id, &attribute.key, Some(attribute.value.kind()))?;
+        let nft_id = Self::_nft_of_token(&token_id)?;
+        let deposit = <nfts::Module<T>>::_byte_deposit(attribute.key.len() + attribute.value.encode().len());
+        <nfts::Module<T>>::_reserve_deposit(&sender, deposit)?;
+        // an overwritten value gives back the deposit of the old one
+        if let Some((depositor, old_deposit)) = <AttributeDeposits<T>>::take((token_id, attribute.key.clone())){
+            <nfts::Module<T>>::_unreserve_deposit(&depositor, old_deposit);
         };
-        ensure!(owner.unwrap() == sender, "sender is not the owner of this token");
-        <TokenAttribuites<T>>::mutate(token_id, |x| x.insert(attribute.key, attribute.value));
+        <AttributeDeposits<T>>::insert((token_id, attribute.key.clone()), (sender, deposit));
+        let key = attribute.key.clone();
+        let kind = attribute.value.kind();
+        if let Some(old) = <TokenAttribuites<T>>::mutate(token_id, |x| x.insert(attribute.key, attribute.value)){
+            <nfts::Module<T>>::_attr_usage_decrease(nft_id, key.clone(), old.kind());
+        };
+        <nfts::Module<T>>::_attr_usage_increase(nft_id, key, kind);
         Ok(())
     }
     fn _rmv_token_attr(sender: T::AccountId, token_id:T::Hash , attribute_key: Vec<u8> ) -> Result{
         // <nfts::Module<T>>::
         // let mut token_attributes = Self::get_token_attr(&token_id);
         // token_attributes.remove(&attribute_key);
-        let owner = <erc721::Module<T>>::owner_of(&token_id);
-        if owner.is_none() {
-            return Err("token not found")
-        };
-        ensure!(owner.unwrap() == sender, "sender is not the owner of this token");
+        ensure!(attribute_key.as_slice() != EXPIRY_ATTR_KEY, "built-in attribute key is read only");
+        <nfts::Module<T>>::_ensure_attr_writable(&sender, token_id, &attribute_key, None)?;
+        let nft_id = Self::_nft_of_token(&token_id)?;
         match <TokenAttribuites<T>>::mutate(token_id, |x| x.remove(&attribute_key)){
             None => return Err("attribute key not found"),
-            Some(_) => return Ok(())
+            Some(old) => <nfts::Module<T>>::_attr_usage_decrease(nft_id, attribute_key.clone(), old.kind()),
+        };
+        if let Some((depositor, deposit)) = <AttributeDeposits<T>>::take((token_id, attribute_key)){
+            <nfts::Module<T>>::_unreserve_deposit(&depositor, deposit);
+        };
+        Ok(())
+    }
+    fn _nft_of_token(token_id: &T::Hash) -> result::Result<T::Hash, &'static str> {
+        match <nfts::Module<T>>::get_token(token_id){
+            Some(t) => Ok(t.nft_id),
+            None => Err("token not found"),
         }
     }
+    // stored attributes plus the built-in ones, used when matching selectors
+    // a token can only be listed with every required attribute of its nft schema, selectors see no gaps
+    fn _ensure_required_attrs(token_id: &T::Hash) -> Result {
+        let token = match <nfts::Module<T>>::get_token(token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        let attr = Self::get_token_attr(token_id);
+        for key in <nfts::Module<T>>::get_attr_schema_keys(&token.nft_id).iter(){
+            if let Some(schema) = <nfts::Module<T>>::get_attr_schema((token.nft_id, key.clone())){
+                ensure!(!schema.required || attr.contains_key(key), "token misses a required attribute");
+            };
+        };
+        Ok(())
+    }
+    fn _token_attr_with_builtins(token_id: &T::Hash) -> TokenAttrType {
+        let mut attr = Self::get_token_attr(token_id);
+        if let Some(token) = <nfts::Module<T>>::get_token(token_id){
+            if let Some(expiry) = token.expiry {
+                attr.insert(EXPIRY_ATTR_KEY.to_vec(), TokenAttrValType::Uint64(expiry.saturated_into::<u64>()));
+            };
+        };
+        attr
+    }
+    fn _set_call_gate(module_index: u8, call_index: u8, gate: Option<TokenSelector<T::Hash>>) -> Result{
+        match gate {
+            Some(selector) => {
+                selector.validate()?;
+                // both kinds are checked without walking the tokens of the caller
+                match selector.selector {
+                    IdSelect(ref t) => ensure!(t.id_set.len() <= MAX_GATE_SCAN, "gate selects too many token ids"),
+                    AttrSelect(ref t) => ensure!(t.stack.len() == 0, "gate can only select token ids or a whole nft"),
+                };
+                <CallGates<T>>::insert((module_index, call_index), selector);
+            },
+            None => <CallGates<T>>::remove((module_index, call_index)),
+        };
+        Self::deposit_event(RawEvent::CallGateSet(module_index, call_index));
+        Ok(())
+    }
+    // an encoded runtime call starts with its module index and call index
+    pub fn _check_call_gate(who: &T::AccountId, encoded_call: &[u8]) -> Result{
+        if encoded_call.len() < 2 {
+            return Ok(())
+        };
+        let selector = match Self::get_call_gate((encoded_call[0], encoded_call[1])){
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        ensure!(<Self as TokenGate<T::AccountId, T::Hash>>::holds_matching(who, &selector), "call is gated to token holders");
+        Ok(())
+    }
     fn _token_match_visitor(sender:T::AccountId, selector: &TokenSelector<T::Hash>) ->  Vec<T::Hash>{ // add to bind_tokens
+        Self::_token_visitor(sender, selector, false)
+    }
+    // tokens of `sender` matching the selector, reserved ones only when `include_reserved`
+    fn _token_visitor(sender:T::AccountId, selector: &TokenSelector<T::Hash>, include_reserved: bool) ->  Vec<T::Hash>{
         // let owner_tokens = <nfts::Module<T>>::get_nfts_owner_vec(&sender);
         let own_count = <erc721::Module<T>>::balance_of(&sender);
         
@@ -647,9 +1051,12 @@ impl<T: Trait> Module<T> {
                     if token_id == T::Hash::default(){
                         continue;
                     };
-                    if <nfts::Module<T>>::get_token_reserve(token_id){
+                    if !include_reserved && <nfts::Module<T>>::get_token_reserve(token_id){
                         continue; // escape if token reserved
                     };
+                    if <nfts::Module<T>>::_is_expired(&token_id){
+                        continue; // expired tokens can not be traded
+                    };
                     match t.id_set.iter().position(|x| *x == token_id){
                         None => {},
                         Some(_) => bind_tokens.push(token_id),
@@ -669,7 +1076,7 @@ impl<T: Trait> Module<T> {
                     if token_id == T::Hash::default(){
                         continue;
                     };
-                    let token_attr = Self::get_token_attr(&token_id);
+                    let token_attr = Self::_token_attr_with_builtins(&token_id);
 
                     let token = <nfts::Module<T>>::get_token(&token_id);
                     if token.is_none() {
@@ -679,9 +1086,12 @@ impl<T: Trait> Module<T> {
                     if token.nft_id != selector.nft_type  {
                         continue;
                     };
-                    if <nfts::Module<T>>::get_token_reserve(token_id){
+                    if !include_reserved && <nfts::Module<T>>::get_token_reserve(token_id){
                         continue; // escape if token reserved
                     };
+                    if <nfts::Module<T>>::_is_expired(&token_id){
+                        continue; // expired tokens can not be traded
+                    };
                     if _token_selector_match(&token_attr, &subselector) {
                         bind_tokens.push(token_id);
                     }
@@ -692,7 +1102,7 @@ impl<T: Trait> Module<T> {
         
         bind_tokens
     }
-    fn _remove_order_from_orderbook(fill_price:T::Balance, fill_asset:T::AssetId, order_id: T::Hash,  is_bid :bool)-> Result{
+    fn _remove_order_from_orderbook(fill_price:T::Balance, fill_asset:T::AssetId, order_id: T::Hash,  is_bid :bool, status: OrderStatus)-> Result{
         let orders_map = match is_bid {
             false => Self::get_orderbook_ask(&fill_asset),
             true => Self::get_orderbook_bid(&fill_asset),
@@ -702,10 +1112,20 @@ impl<T: Trait> Module<T> {
                 Some(t) => t,
                 None => return Err("order not found"),
             };
+            Self::_unschedule_order_expiry(order_id, order.expire_at);
             // order.status = OrderStatus::Closed;
-            Self::deposit_event(RawEvent::OrderClosed(order.creator, order_id, order.asset, order.price, order.timepoint, order.immediate_or_cancel));
+            Self::deposit_event(RawEvent::OrderClosed(order.creator, order_id, order.asset, order.price, order.timepoint, order.time_in_force));
             // remove order from BidTokenOrders
             <BidTokenOrders<T>>::remove(&order_id);
+            Self::_archive_order(&order.creator, ClosedOrder{
+                order_id,
+                is_bid: true,
+                asset: order.asset,
+                price: order.price,
+                remaining: order.tk_count_to_buy.saturated_into::<u32>(),
+                status,
+                closed_at: <timestamp::Module<T>>::get(),
+            });
 
             let order_vec = match orders_map.get(&fill_price){
                 Some(x) => x,
@@ -726,10 +1146,20 @@ impl<T: Trait> Module<T> {
                 Some(t) => t,
                 None => return Err("order not found"),
             };
+            Self::_unschedule_order_expiry(order_id, order.expire_at);
             // order.status = OrderStatus::Closed;
-            Self::deposit_event(RawEvent::OrderClosed(order.creator, order_id, order.asset, order.price, order.timepoint, order.immediate_or_cancel));
+            Self::deposit_event(RawEvent::OrderClosed(order.creator, order_id, order.asset, order.price, order.timepoint, order.time_in_force));
             // remove order from AskTokenOrders
             <AskTokenOrders<T>>::remove(&order_id);
+            Self::_archive_order(&order.creator, ClosedOrder{
+                order_id,
+                is_bid: false,
+                asset: order.asset,
+                price: order.price,
+                remaining: order.bind_tokens.len().saturated_into::<u32>(),
+                status,
+                closed_at: <timestamp::Module<T>>::get(),
+            });
 
             let order_vec = match orders_map.get(&fill_price){
                 Some(x) => x,
@@ -767,9 +1197,13 @@ impl<T: Trait> Module<T> {
         Ok(())
     }
     
-    fn _fill(fill_price:T::Balance, fill_asset:T::AssetId, order_id: T::Hash, token_id: T::Hash, buyer:T::AccountId, seller:T::AccountId, is_bid: bool) ->Result {
+    fn _fill(fill_price:T::Balance, fill_asset:T::AssetId, order_id: T::Hash, taker_order_id: T::Hash, token_id: T::Hash, buyer:T::AccountId, seller:T::AccountId, is_bid: bool) ->Result {
+        // the payment is reserved already, the buyer fee comes out of the free balance
+        let free = <generic_asset::Module<T>>::free_balance(&fill_asset, &buyer);
+        let (seller_fee, buyer_fee) = Self::_check_fill(fill_price, fill_asset, &token_id, &buyer, &seller, is_bid, free)?;
+        ensure!(<nfts::Module<T>>::get_token_lock(&token_id) == Some(ORDER_LOCK_ID), "token not locked by this module");
         let timepoint = <timestamp::Module<T>>::get() ;
-        Self::deposit_event(RawEvent::OrderFilled(seller.clone(), buyer.clone(), token_id, fill_asset, fill_price, timepoint));
+        Self::deposit_event(RawEvent::OrderFilled(seller.clone(), buyer.clone(), token_id, fill_asset, fill_price, timepoint, seller_fee, buyer_fee));
        
         if is_bid == false{// ask order
             // let mut order = match Self::get_ask_token_order(&order_id){
@@ -786,7 +1220,7 @@ impl<T: Trait> Module<T> {
                 return Err("token not found")
             };
             // unreserve token
-            <nfts::Module<T>>::_token_unreserve(seller.clone(), token_id)?;
+            <nfts::Module<T>>::_token_unlock(seller.clone(), token_id, ORDER_LOCK_ID)?;
             // exchange token with money
             <nfts::Module<T>>::_reserve_safe_transfer(seller.clone(), buyer.clone() , token_id)?;
 
@@ -802,6 +1236,7 @@ impl<T: Trait> Module<T> {
                     Some(xx) => {
                         xx.status = OrderStatus::PartialFilled;
                         xx.bind_tokens.remove(pos.unwrap());
+                        <TokenAskOrder<T>>::remove(token_id);
                         if xx.bind_tokens.len() == 0 {
                             xx.status = OrderStatus::Filled;
                             // Self::_remove_order_from_orderbook(fill_price, fill_asset, order_id, is_bid);
@@ -813,8 +1248,10 @@ impl<T: Trait> Module<T> {
                             
             });
             if will_remove{
-                Self::_remove_order_from_orderbook(fill_price, fill_asset, order_id, is_bid)?;
+                Self::_close_ask_order(fill_price, fill_asset, order_id, OrderStatus::Filled)?;
             }
+            Self::_charge_fees(seller.clone(), buyer.clone(), fill_asset, seller_fee, buyer_fee)?;
+            Self::_record_trade(order_id, taker_order_id, seller, buyer, token_id, fill_asset, fill_price);
 
         }else{
             // let mut order = match Self::get_bid_token_order(&order_id){
@@ -826,7 +1263,7 @@ impl<T: Trait> Module<T> {
             let mut tk_count_to_buy = order.tk_count_to_buy;
             tk_count_to_buy -= 1.into();
             // unreserve token
-            <nfts::Module<T>>::_token_unreserve(seller.clone(), token_id)?;
+            <nfts::Module<T>>::_token_unlock(seller.clone(), token_id, ORDER_LOCK_ID)?;
             // exchange token with money
             match <nfts::Module<T>>::_reserve_safe_transfer(seller.clone(), buyer.clone() , token_id){
                 Ok(_) => {},
@@ -857,13 +1294,150 @@ impl<T: Trait> Module<T> {
                             
             });
             if will_remove{
-                Self::_remove_order_from_orderbook(fill_price, fill_asset, order_id, is_bid)?;
+                Self::_remove_order_from_orderbook(fill_price, fill_asset, order_id, is_bid, OrderStatus::Filled)?;
             }
+            Self::_charge_fees(seller.clone(), buyer.clone(), fill_asset, seller_fee, buyer_fee)?;
+            Self::_record_trade(taker_order_id, order_id, seller, buyer, token_id, fill_asset, fill_price);
 
         }
         Ok(())
     }
-    fn _token_match_bid(buyer: T::AccountId, bid_price: T::Balance, bid_asset: T::AssetId, mut tk_count_to_buy: u32 , selector: &TokenSelector<T::Hash> ) -> u32{ // if fill remove from bind_tokens
+    fn _set_fee_schedule(asset: T::AssetId, maker: u32, taker: u32) -> Result{
+        ensure!(maker <= MAX_FEE_PER_MILLE && taker <= MAX_FEE_PER_MILLE, "fee above the highest fee");
+        <FeeSchedules<T>>::insert(asset, FeeSchedule{ maker, taker });
+        Self::deposit_event(RawEvent::FeeScheduleSet(asset, maker, taker));
+        Ok(())
+    }
+    // seller fee and buyer fee of a fill, a filled bid order makes the buyer the maker
+    // what _fill checks before it writes, `free` is the balance the buyer pays its fee from,
+    // gives back the seller and the buyer fee
+    fn _check_fill(fill_price: T::Balance, fill_asset: T::AssetId, token_id: &T::Hash, buyer: &T::AccountId, seller: &T::AccountId, is_bid: bool, free: T::Balance) -> result::Result<(T::Balance, T::Balance), &'static str> {
+        ensure!(!<nfts::Module<T>>::_is_expired(token_id), "token expired, fill now is forbidden");
+        let token = match <nfts::Module<T>>::get_token(token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        ensure!(<nfts::Module<T>>::nft_perm((token.nft_id, buyer.clone())) != Some(false), "nft blacklist contains to-account");
+        ensure!(<erc721::Module<T>>::owner_of(token_id).as_ref() == Some(seller), "You do not own this token");
+        let (seller_fee, buyer_fee) = Self::_fill_fees(fill_asset, fill_price, is_bid);
+        ensure!(free >= buyer_fee, "free balance too low to pay the trading fee");
+        Ok((seller_fee, buyer_fee))
+    }
+    fn _fill_fees(asset: T::AssetId, price: T::Balance, is_bid: bool) -> (T::Balance, T::Balance) {
+        if Self::get_fee_account().is_none() {
+            return (Zero::zero(), Zero::zero())
+        };
+        let schedule = Self::get_fee_schedule(asset);
+        let fee = |per_mille: u32| (price.saturated_into::<u128>()
+            .saturating_mul(per_mille as u128) / 1000).saturated_into::<T::Balance>();
+        if is_bid {
+            (fee(schedule.taker), fee(schedule.maker))
+        } else {
+            (fee(schedule.maker), fee(schedule.taker))
+        }
+    }
+    fn _charge_fees(seller: T::AccountId, buyer: T::AccountId, asset: T::AssetId, seller_fee: T::Balance, buyer_fee: T::Balance) -> Result{
+        let fee_account = match Self::get_fee_account(){
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        if !seller_fee.is_zero() {
+            Self::_transfer_asset(seller, fee_account.clone(), seller_fee, asset)?;
+        };
+        if !buyer_fee.is_zero() {
+            Self::_transfer_asset(buyer, fee_account, buyer_fee, asset)?;
+        };
+        Ok(())
+    }
+    // whether a token can be filled against an order with this selector
+    fn _token_fits_selector(token_id: &T::Hash, selector: &TokenSelector<T::Hash>) -> bool {
+        if <nfts::Module<T>>::_is_expired(token_id) {
+            return false
+        };
+        match &selector.selector {
+            IdSelect(t) => t.id_set.contains(token_id),
+            AttrSelect(subselector) => match <nfts::Module<T>>::get_token(token_id){
+                Some(token) => token.nft_id == selector.nft_type
+                    && _token_selector_match(&Self::_token_attr_with_builtins(token_id), subselector),
+                None => false,
+            },
+        }
+    }
+    // tokens _token_match_bid would buy right now, without buying them, each fill checked as _fill checks it
+    fn _count_match_bid(buyer: &T::AccountId, bid_price: T::Balance, bid_asset: T::AssetId, tk_count_to_buy: u32, selector: &TokenSelector<T::Hash>) -> u32 {
+        let orderbook = Self::get_orderbook_ask(&bid_asset);
+        // the buyer reserves its price for every token before matching, fees come out of what is left
+        let tk_count_to_buy_balance: T::Balance = tk_count_to_buy.into();
+        let mut free = <generic_asset::Module<T>>::free_balance(&bid_asset, buyer).saturating_sub(tk_count_to_buy_balance * bid_price);
+        let mut count = 0;
+        for (&price, order_vec) in orderbook.range((Unbounded, Included(&bid_price))) {
+            for order_id in order_vec.iter(){
+                let order = match Self::get_ask_token_order(order_id){
+                    Some(t) => t,
+                    None => continue,
+                };
+                if Self::_is_order_expired(order.expire_at) {
+                    continue;
+                };
+                for token_id in order.bind_tokens.iter(){
+                    if count >= tk_count_to_buy {
+                        return count
+                    };
+                    if !Self::_token_fits_selector(token_id, selector) {
+                        continue;
+                    };
+                    if <nfts::Module<T>>::get_token_lock(token_id) != Some(ORDER_LOCK_ID) {
+                        continue;
+                    };
+                    let buyer_fee = match Self::_check_fill(price, bid_asset, token_id, buyer, &order.creator, false, free){
+                        Ok((_, t)) => t,
+                        Err(_) => continue,
+                    };
+                    // a cheaper ask gives back the difference to the free balance
+                    free = free - buyer_fee + (bid_price - price);
+                    count += 1;
+                };
+            };
+        };
+        count
+    }
+    // tokens of `bind_tokens` _token_match_ask would sell right now, without selling them,
+    // each fill checked as _fill checks it
+    fn _count_match_ask(seller: &T::AccountId, ask_price: T::Balance, ask_asset: T::AssetId, bind_tokens: &Vec<T::Hash>) -> usize {
+        let orderbook = Self::get_orderbook_bid(&ask_asset);
+        let mut left = bind_tokens.clone();
+        // fees a buyer pays for earlier fills of this order
+        let mut fees_paid: BTreeMap<T::AccountId, T::Balance> = BTreeMap::new();
+        for (&price, order_vec) in orderbook.range((Included(&ask_price), Unbounded)).rev() {
+            for order_id in order_vec.iter(){
+                let order = match Self::get_bid_token_order(order_id){
+                    Some(t) => t,
+                    None => continue,
+                };
+                if Self::_is_order_expired(order.expire_at) {
+                    continue;
+                };
+                let buyer = order.creator.clone();
+                let mut tk_count_to_buy = order.tk_count_to_buy.saturated_into::<u32>();
+                left.retain(|token_id| {
+                    if tk_count_to_buy == 0 || !Self::_token_fits_selector(token_id, &order.selector) {
+                        return true
+                    };
+                    let paid = fees_paid.get(&buyer).cloned().unwrap_or_else(Zero::zero);
+                    let free = <generic_asset::Module<T>>::free_balance(&ask_asset, &buyer).saturating_sub(paid);
+                    let buyer_fee = match Self::_check_fill(price, ask_asset, token_id, &buyer, seller, true, free){
+                        Ok((_, t)) => t,
+                        Err(_) => return true,
+                    };
+                    fees_paid.insert(buyer.clone(), paid + buyer_fee);
+                    tk_count_to_buy -= 1;
+                    false
+                });
+            };
+        };
+        bind_tokens.len() - left.len()
+    }
+    fn _token_match_bid(buyer: T::AccountId, taker_order_id: T::Hash, bid_price: T::Balance, bid_asset: T::AssetId, mut tk_count_to_buy: u32 , selector: &TokenSelector<T::Hash> ) -> u32{ // if fill remove from bind_tokens
         // let mut amount = 0;
         let orderbook = Self::get_orderbook_ask(&bid_asset);
         // let mut amount_to_pay: T::Balance = tk_count_to_buy * bid_price;
@@ -877,20 +1451,25 @@ impl<T: Trait> Module<T> {
                      continue;
                 };
                 let order = order.unwrap();
+                if Self::_is_order_expired(order.expire_at) {
+                    continue; // waiting to be reaped
+                };
                 let mut bind_tokens = order.bind_tokens;
                 let seller = order.creator;
 
                 // let bind_tokens_copy = bind_tokens.clone();
-                let mut i:usize = 0;
                 // let maker_price = *price;// maker price
 
-                for &token_id in bind_tokens.clone().iter(){
+                for (i, &token_id) in bind_tokens.clone().iter().enumerate(){
                     match &selector.selector {
                         IdSelect(t) => {
                             if t.id_set.contains(&token_id) {
                                 // fill
-                                match Self::_fill(price, bid_asset, order_id, token_id, buyer.clone(), seller.clone(), false){
-                                    Ok(_) => {},
+                                match Self::_fill(price, bid_asset, order_id, taker_order_id, token_id, buyer.clone(), seller.clone(), false){
+                                    Ok(_) => {
+                                        // the buyer reserved its own price, release what the cheaper ask saved
+                                        let _ = Self::_unreserve_asset(buyer.clone(), bid_asset, bid_price - price);
+                                    },
                                     Err(_) => continue,
                                 };
                                 // bind_tokens.remove(i);
@@ -904,22 +1483,21 @@ impl<T: Trait> Module<T> {
                             }
                         },
                         AttrSelect(subselector) => {
-                            let token_attr = Self::get_token_attr(token_id);
+                            let token_attr = Self::_token_attr_with_builtins(&token_id);
                             let token = match <nfts::Module<T>>::get_token(token_id){
                                 Some(t) => t,
-                                None => {
-                                    i += 1;
-                                    continue
-                                },
+                                None => continue,
                             };
                             if token.nft_id != selector.nft_type {
-                                i += 1;
                                 continue
                             };
                             if _token_selector_match(&token_attr, &subselector) {
                                 // fill
-                                match Self::_fill(price, bid_asset, order_id, token_id, buyer.clone(), seller.clone(), false){
-                                    Ok(_) => {},
+                                match Self::_fill(price, bid_asset, order_id, taker_order_id, token_id, buyer.clone(), seller.clone(), false){
+                                    Ok(_) => {
+                                        // the buyer reserved its own price, release what the cheaper ask saved
+                                        let _ = Self::_unreserve_asset(buyer.clone(), bid_asset, bid_price - price);
+                                    },
                                     Err(_) => continue,
                                 };
                                 // bind_tokens.remove(i);
@@ -934,7 +1512,6 @@ impl<T: Trait> Module<T> {
                             
                         }, 
                     };
-                    i += 1;
                 };
                     
             }
@@ -942,7 +1519,7 @@ impl<T: Trait> Module<T> {
         return tk_count_to_buy
         
     }
-    fn _token_match_ask(seller: T::AccountId, ask_price: T::Balance, ask_asset: T::AssetId, mut bind_tokens: Vec<T::Hash> ) -> Vec<T::Hash>{ // if fill remove from bind_tokens
+    fn _token_match_ask(seller: T::AccountId, taker_order_id: T::Hash, ask_price: T::Balance, ask_asset: T::AssetId, mut bind_tokens: Vec<T::Hash> ) -> Vec<T::Hash>{ // if fill remove from bind_tokens
         let orderbook = Self::get_orderbook_bid(&ask_asset);
         for (&price, order_vec) in orderbook.range((Included(&ask_price), Unbounded )).rev() {
             for &order_id in order_vec.iter(){// it: order_id
@@ -953,6 +1530,9 @@ impl<T: Trait> Module<T> {
                     Some(t) => t,
                     None => continue,
                 };
+                if Self::_is_order_expired(order.expire_at) {
+                    continue; // waiting to be reaped
+                };
                 let buyer = match Self::get_bid_token_order(order_id){
                     Some(t) => t.creator,
                     None => continue,
@@ -962,14 +1542,13 @@ impl<T: Trait> Module<T> {
                 let selector = order.selector;
                 // let maker_price = *price;// maker price
                 // let bind_tokens_copy = bind_tokens.clone();
-                let mut i:usize = 0;
                 
-                for &token_id in bind_tokens.clone().iter(){
+                for (i, &token_id) in bind_tokens.clone().iter().enumerate(){
                     match &selector.selector {
                         IdSelect(t) => {
                             if t.id_set.contains(&token_id) {
                                 // send fill op
-                                match Self::_fill(price, ask_asset, order_id, token_id, buyer.clone(), seller.clone(), true){
+                                match Self::_fill(price, ask_asset, order_id, taker_order_id, token_id, buyer.clone(), seller.clone(), true){
                                     Ok(_) => {},
                                     Err(_) => continue,
                                 };
@@ -987,21 +1566,17 @@ impl<T: Trait> Module<T> {
                             }
                         },
                         AttrSelect(subselector) => {
-                            let token_attr = Self::get_token_attr(token_id);
+                            let token_attr = Self::_token_attr_with_builtins(&token_id);
                             let token = match <nfts::Module<T>>::get_token(token_id){
                                 Some(t) => t,
-                                None => {
-                                    i += 1;
-                                    continue
-                                },
+                                None => continue,
                             };
                             if token.nft_id != selector.nft_type {
-                                i += 1;
                                 continue
                             };
                             if _token_selector_match(&token_attr, &subselector) {
                                 // fill
-                                match Self::_fill(price, ask_asset, order_id, token_id, buyer.clone(), seller.clone(), true){
+                                match Self::_fill(price, ask_asset, order_id, taker_order_id, token_id, buyer.clone(), seller.clone(), true){
                                     Ok(_) => {},
                                     Err(_) => continue,
                                 };
@@ -1019,7 +1594,6 @@ impl<T: Trait> Module<T> {
                             
                         }, 
                     };
-                    i += 1;
                 };
             }
         };
@@ -1073,32 +1647,55 @@ impl<T: Trait> Module<T> {
         selector: TokenSelector<T::Hash>,
         asset: T::AssetId,
         price: T::Balance,
-        immediate_or_cancel: bool) -> Result {
+        time_in_force: TimeInForce,
+        expire_at: Option<T::Moment>) -> Result {
 
         let timepoint = <timestamp::Module<T>>::get() ;
-        let order_id = (&creator, &asset, timepoint,  price, immediate_or_cancel, true, &selector).using_encoded(<T as system::Trait>::Hashing::hash);
+        if let Some(t) = expire_at {
+            ensure!(t > timepoint, "order expiry must be in the future");
+            ensure!(t <= timepoint.saturating_add(MAX_ORDER_LIFETIME.saturated_into()), "order expiry too far in the future");
+        };
+        let order_id = (&creator, &asset, timepoint,  price, time_in_force, true, &selector).using_encoded(<T as system::Trait>::Hashing::hash);
 
         // trigger match, send fill op if filled
         let bind_tokens = Self::_token_match_visitor(creator.clone(), &selector);
         ensure!(bind_tokens.len() > 0, "no token selected, so invalid, please check your token_id and Attributes and reset");
         // let token_upper_limit_size: usize = selector.token_count().into();
         ensure!(bind_tokens.len() <= selector.token_count().try_into().unwrap(), "upper limit amount of bind tokens is exceeded");
-        Self::deposit_event(RawEvent::OrderOpened(creator.clone(), order_id, asset, price, timepoint, immediate_or_cancel));
+        for token_id in bind_tokens.iter(){
+            Self::_ensure_required_attrs(token_id)?;
+        };
+        // checked before anything is written, so a rejected order leaves no trace
+        match time_in_force {
+            TimeInForce::FillOrKill => ensure!(Self::_count_match_ask(&creator, price, asset, &bind_tokens) == bind_tokens.len(), "fill-or-kill order can not be filled completely"),
+            TimeInForce::PostOnly => ensure!(Self::_count_match_ask(&creator, price, asset, &bind_tokens) == 0, "post-only order would match on entry"),
+            _ => {},
+        };
+        Self::deposit_event(RawEvent::OrderOpened(creator.clone(), order_id, asset, price, timepoint, time_in_force));
 
         // reserve tokens
         for token_id in bind_tokens.iter(){
-            <nfts::Module<T>>::_token_reserve(creator.clone(), *token_id)?;
+            <nfts::Module<T>>::_token_lock(creator.clone(), *token_id, ORDER_LOCK_ID)?;
         };
         
         // remove from bind_tokens if fill
-        let bind_tokens = Self::_token_match_ask(creator.clone(), price, asset, bind_tokens);
-        // add left to token ask orderbook if not immediate_or_cancel
-        if bind_tokens.len() ==0 || immediate_or_cancel {
+        let bind_tokens = Self::_token_match_ask(creator.clone(), order_id, price, asset, bind_tokens);
+        // add left to token ask orderbook if the order rests
+        if bind_tokens.len() ==0 || !time_in_force.rests() {
             // unreserve left tokens
             for token_id in bind_tokens.iter(){
-                <nfts::Module<T>>::_token_unreserve(creator.clone(), *token_id)?;
+                <nfts::Module<T>>::_token_unlock(creator.clone(), *token_id, ORDER_LOCK_ID)?;
             };
-            Self::deposit_event(RawEvent::OrderClosed(creator.clone(), order_id, asset, price, timepoint, immediate_or_cancel));
+            Self::deposit_event(RawEvent::OrderClosed(creator.clone(), order_id, asset, price, timepoint, time_in_force));
+            Self::_archive_order(&creator, ClosedOrder{
+                order_id,
+                is_bid: false,
+                asset,
+                price,
+                remaining: bind_tokens.len().saturated_into::<u32>(),
+                status: if bind_tokens.len() == 0 { OrderStatus::Filled } else { OrderStatus::Closed },
+                closed_at: timepoint,
+            });
 
             return Ok(())
         };
@@ -1110,16 +1707,22 @@ impl<T: Trait> Module<T> {
             asset,
             price,
             timepoint,
-            immediate_or_cancel,
+            time_in_force,
+            expire_at,
             bind_tokens:bind_tokens.clone(),
             status: OrderStatus::Open,
         };
         
         // wirte to tokenorders
         <AskTokenOrders<T>>::insert(order_id, order);
+        Self::_index_order(&creator, order_id, false);
+        for token_id in bind_tokens.iter(){
+            <TokenAskOrder<T>>::insert(token_id, order_id);
+        };
         // add to orderbook
         Self::_try_init_orderbook(asset, false)?;// add asset entry
         Self::_try_add_order_to_orderbook(price, asset, order_id, false)?; // add price entry
+        Self::_schedule_order_expiry(order_id, expire_at);
 
         Ok(())
         
@@ -1128,23 +1731,43 @@ impl<T: Trait> Module<T> {
         selector: TokenSelector<T::Hash>,
         asset: T::AssetId,
         price: T::Balance,
-        immediate_or_cancel: bool) -> Result {
+        time_in_force: TimeInForce,
+        expire_at: Option<T::Moment>) -> Result {
 
         let timepoint = <timestamp::Module<T>>::get() ;
-        let order_id = (&creator, &asset, timepoint, price, immediate_or_cancel, false, &selector).using_encoded(<T as system::Trait>::Hashing::hash);
+        if let Some(t) = expire_at {
+            ensure!(t > timepoint, "order expiry must be in the future");
+            ensure!(t <= timepoint.saturating_add(MAX_ORDER_LIFETIME.saturated_into()), "order expiry too far in the future");
+        };
+        let order_id = (&creator, &asset, timepoint, price, time_in_force, false, &selector).using_encoded(<T as system::Trait>::Hashing::hash);
+        let tk_count_to_buy = selector.token_count();
+        // checked before anything is written, so a rejected order leaves no trace
+        match time_in_force {
+            TimeInForce::FillOrKill => ensure!(Self::_count_match_bid(&creator, price, asset, tk_count_to_buy, &selector) == tk_count_to_buy, "fill-or-kill order can not be filled completely"),
+            TimeInForce::PostOnly => ensure!(Self::_count_match_bid(&creator, price, asset, tk_count_to_buy, &selector) == 0, "post-only order would match on entry"),
+            _ => {},
+        };
         // send order create event
-        Self::deposit_event(RawEvent::OrderOpened(creator.clone(), order_id, asset, price, timepoint, immediate_or_cancel));
+        Self::deposit_event(RawEvent::OrderOpened(creator.clone(), order_id, asset, price, timepoint, time_in_force));
 
-        let tk_count_to_buy = selector.token_count();
         // reserve asset
         let tk_count_to_buy_balance : T::Balance = tk_count_to_buy.into();
         let reserve_amount = tk_count_to_buy_balance * price;
         Self::_reserve_asset(creator.clone(), asset, reserve_amount)?;
         
         // trigger match, send fill op if filled
-        let tk_count_to_buy = Self::_token_match_bid(creator.clone(), price, asset, tk_count_to_buy, &selector);
-        // add left to token bid orderbook if not immediate_or_cancel
-        if tk_count_to_buy == 0 || immediate_or_cancel {
+        let tk_count_to_buy = Self::_token_match_bid(creator.clone(), order_id, price, asset, tk_count_to_buy, &selector);
+        // add left to token bid orderbook if the order rests
+        if tk_count_to_buy == 0 || !time_in_force.rests() {
+            Self::_archive_order(&creator, ClosedOrder{
+                order_id,
+                is_bid: true,
+                asset,
+                price,
+                remaining: tk_count_to_buy,
+                status: if tk_count_to_buy == 0 { OrderStatus::Filled } else { OrderStatus::Closed },
+                closed_at: timepoint,
+            });
             let tk_count_to_buy_balance : T::Balance = tk_count_to_buy.into();
             let unreserved_balance = tk_count_to_buy_balance * price;
             return Self::_unreserve_asset(creator.clone(), asset, unreserved_balance );
@@ -1158,32 +1781,77 @@ impl<T: Trait> Module<T> {
             asset,
             price,
             timepoint,
-            immediate_or_cancel,
+            time_in_force,
+            expire_at,
             tk_count_to_buy: tk_count_to_buy.into() ,
             status: OrderStatus::Open,
         };
         
         // wirte to tokenorders
         <BidTokenOrders<T>>::insert(order_id, order);
+        Self::_index_order(&creator, order_id, true);
         // add to orderbook
         Self::_try_init_orderbook(asset, true)?;// add asset entry
         Self::_try_add_order_to_orderbook(price, asset, order_id, true)?; // add price entry
+        Self::_schedule_order_expiry(order_id, expire_at);
 
         Ok(())
         
     }
+    fn _unbind_token_from_ask_order(order_id: T::Hash, token_id: T::Hash) -> Result {
+        <TokenAskOrder<T>>::remove(token_id);
+        let mut order = match Self::get_ask_token_order(&order_id){
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        order.bind_tokens.retain(|x| *x != token_id);
+        let (price, asset, nothing_left) = (order.price, order.asset, order.bind_tokens.len() == 0);
+        <AskTokenOrders<T>>::insert(order_id, order);
+        if nothing_left {
+            return Self::_close_ask_order(price, asset, order_id, OrderStatus::Closed)
+        };
+        Ok(())
+    }
+    // unbind the token from its ask order, drop its attributes and let nfts clean up the rest
+    fn _on_token_burn(who: &T::AccountId, token_id: &T::Hash, reason: u8) -> Result {
+        if let Some(order_id) = Self::get_token_ask_order(token_id){
+            let owner = match <erc721::Module<T>>::owner_of(token_id){
+                Some(t) => t,
+                None => return Err("token not found"),
+            };
+            Self::_unbind_token_from_ask_order(order_id, *token_id)?;
+            <nfts::Module<T>>::_token_unlock(owner, *token_id, ORDER_LOCK_ID)?;
+        };
+        let nft_id = <nfts::Module<T>>::get_token(token_id).map(|t| t.nft_id);
+        <nfts::Module<T>>::_on_burn(who, token_id, reason)?;
+        let attributes = <TokenAttribuites<T>>::take(token_id);
+        for (key, value) in attributes.iter(){
+            if let Some(nft_id) = nft_id {
+                <nfts::Module<T>>::_attr_usage_decrease(nft_id, key.clone(), value.kind());
+            };
+            if let Some((depositor, deposit)) = <AttributeDeposits<T>>::take((*token_id, key.clone())){
+                <nfts::Module<T>>::_unreserve_deposit(&depositor, deposit);
+            };
+        };
+        Ok(())
+    }
     fn _token_sell_order_cancel(creator : T::AccountId, order_id:T::Hash)->Result{
         let order = match Self::get_ask_token_order(&order_id) {
             Some(t) => t,
             None => return Err("")
         };
+        ensure!(!<DutchListings<T>>::exists(&order_id), "dutch listings are canceled by dutch_listing_cancel");
         ensure!(order.creator == creator,"creator dismatch, creator not own this order");
         let timepoint = <timestamp::Module<T>>::get() ;
         Self::deposit_event(RawEvent::OrderCanceled(creator.clone(), order_id, timepoint));
         // remove from orderbook
-        Self::_remove_order_from_orderbook(order.price, order.asset, order_id, false)?;
+        Self::_remove_order_from_orderbook(order.price, order.asset, order_id, false, OrderStatus::Canceled)?;
         // remove from orderitems
         <AskTokenOrders<T>>::remove(order_id);
+        for token_id in order.bind_tokens.iter(){
+            <TokenAskOrder<T>>::remove(token_id);
+            Self::_release_bound_token(&creator, token_id);
+        };
         Ok(())
     }
     fn _token_buy_order_cancel(creator : T::AccountId, order_id:T::Hash)->Result{
@@ -1195,54 +1863,791 @@ impl<T: Trait> Module<T> {
         let timepoint = <timestamp::Module<T>>::get() ;
         Self::deposit_event(RawEvent::OrderCanceled(creator.clone(), order_id, timepoint));
         // remove from orderbook
-        Self::_remove_order_from_orderbook(order.price, order.asset, order_id, true)?;
+        Self::_remove_order_from_orderbook(order.price, order.asset, order_id, true, OrderStatus::Canceled)?;
         // remove from orderitems
         <BidTokenOrders<T>>::remove(order_id);
-        Ok(()) 
+        // release what is still reserved for the tokens not bought
+        let tk_count_to_buy: u32 = order.tk_count_to_buy.saturated_into::<u32>();
+        let tk_count_to_buy_balance: T::Balance = tk_count_to_buy.into();
+        Self::_unreserve_asset(creator, order.asset, tk_count_to_buy_balance * order.price)
     }
-}
+    // funds the open bid orders of an account still hold in reserve, auction bids, sealed bid
+    // deposits and buyout bids reserve on top of it
+    pub fn bid_obligations(who: &T::AccountId, asset: T::AssetId) -> T::Balance {
+        let mut total = T::Balance::zero();
+        for order_id in Self::get_owned_bid_orders(who).iter(){
+            let order = match Self::get_bid_token_order(order_id){
+                Some(t) => t,
+                None => continue,
+            };
+            if order.asset != asset {
+                continue;
+            };
+            let tk_count_to_buy: u32 = order.tk_count_to_buy.saturated_into::<u32>();
+            let tk_count_to_buy_balance: T::Balance = tk_count_to_buy.into();
+            total += tk_count_to_buy_balance * order.price;
+        };
+        total
+    }
+    pub fn sealed_bid_commitment(auction_id: &T::Hash, bidder: &T::AccountId, amount: T::Balance, salt: &Vec<u8>) -> T::Hash {
+        (auction_id, bidder, amount, salt).using_encoded(<T as system::Trait>::Hashing::hash)
+    }
+    fn _sealed_auction_create(seller: T::AccountId,
+        token_id: T::Hash,
+        asset: T::AssetId,
+        reserve_price: T::Balance,
+        deposit: T::Balance,
+        commit_end: T::Moment,
+        reveal_end: T::Moment) -> Result{
+        let timepoint = <timestamp::Module<T>>::get();
+        ensure!(commit_end > timepoint, "commit end must be in the future");
+        ensure!(reveal_end > commit_end, "reveal end must be after commit end");
+        let auction_id = (&seller, token_id, timepoint, b"sealed").using_encoded(<T as system::Trait>::Hashing::hash);
+        ensure!(Self::get_sealed_auction(&auction_id).is_none(), "auction id conflicts");
+        Self::_ensure_required_attrs(&token_id)?;
+        <nfts::Module<T>>::_escrow_token(seller.clone(), Self::account_id(), token_id)?;
+
+        <SealedAuctions<T>>::insert(auction_id, SealedAuction{
+            seller: seller.clone(),
+            auction_id,
+            token_id,
+            asset,
+            reserve_price,
+            deposit,
+            commit_end,
+            reveal_end,
+            highest: None,
+            second_price: reserve_price,
+        });
+        <TokenAuction<T>>::insert(token_id, auction_id);
+        Self::deposit_event(RawEvent::AuctionCreated(seller, auction_id, token_id, asset, reserve_price, reveal_end));
+        Ok(())
+    }
+    fn _sealed_bid_commit(bidder: T::AccountId, auction_id: T::Hash, commitment: T::Hash) -> Result{
+        let auction = match Self::get_sealed_auction(&auction_id){
+            Some(t) => t,
+            None => return Err("auction not found"),
+        };
+        ensure!(<timestamp::Module<T>>::get() < auction.commit_end, "commit phase already ended");
+        ensure!(bidder != auction.seller, "seller can not bid on own auction");
+        ensure!(!<SealedCommits<T>>::exists((auction_id, bidder.clone())), "bid already committed");
+        let mut bidders = Self::get_sealed_bidders(&auction_id);
+        ensure!(bidders.len() < MAX_SEALED_BIDDERS, "too many bidders in this auction");
+        Self::_reserve_asset(bidder.clone(), auction.asset, auction.deposit)?;
+        bidders.push(bidder.clone());
+        <SealedBidders<T>>::insert(auction_id, bidders);
+        <SealedCommits<T>>::insert((auction_id, bidder.clone()), commitment);
+        Self::deposit_event(RawEvent::SealedBidCommitted(bidder, auction_id));
+        Ok(())
+    }
+    fn _sealed_bid_reveal(bidder: T::AccountId, auction_id: T::Hash, amount: T::Balance, salt: Vec<u8>) -> Result{
+        let mut auction = match Self::get_sealed_auction(&auction_id){
+            Some(t) => t,
+            None => return Err("auction not found"),
+        };
+        let timepoint = <timestamp::Module<T>>::get();
+        ensure!(timepoint >= auction.commit_end, "reveal phase not started yet");
+        ensure!(timepoint < auction.reveal_end, "reveal phase already ended");
+        let commitment = match Self::get_sealed_commit((auction_id, bidder.clone())){
+            Some(t) => t,
+            None => return Err("no bid committed"),
+        };
+        ensure!(Self::sealed_bid_commitment(&auction_id, &bidder, amount, &salt) == commitment, "bid does not match the commitment");
 
+        // a revealed bid gets its deposit back, the highest one stays reserved instead
+        let (top_bidder, top_amount) = match auction.highest.clone() {
+            Some(t) => (Some(t.0), t.1),
+            None => (None, auction.reserve_price),
+        };
+        let outbids = amount > top_amount || (top_bidder.is_none() && amount >= top_amount);
+        if outbids {
+            Self::_reserve_asset(bidder.clone(), auction.asset, amount)?;
+        };
+        Self::_unreserve_asset(bidder.clone(), auction.asset, auction.deposit)?;
+        if outbids {
+            if let Some(prev_bidder) = top_bidder {
+                Self::_unreserve_asset(prev_bidder, auction.asset, top_amount)?;
+                auction.second_price = top_amount;
+            };
+            auction.highest = Some((bidder.clone(), amount));
+        } else if top_bidder.is_some() && amount > auction.second_price {
+            auction.second_price = amount;
+        };
+        <SealedCommits<T>>::remove((auction_id, bidder.clone()));
+        <SealedAuctions<T>>::insert(auction_id, auction);
+        Self::deposit_event(RawEvent::SealedBidRevealed(bidder, auction_id, amount));
+        Ok(())
+    }
+    fn _sealed_auction_settle(auction_id: T::Hash) -> Result{
+        let auction = match Self::get_sealed_auction(&auction_id){
+            Some(t) => t,
+            None => return Err("auction not found"),
+        };
+        let timepoint = <timestamp::Module<T>>::get();
+        ensure!(timepoint >= auction.reveal_end, "reveal phase not ended yet");
 
-#[cfg(test)]
-mod tests {
-        use super::*;
+        let deliverable = match auction.highest.clone() {
+            Some((bidder, amount)) => {
+                let deliverable = Self::_auction_deliverable(&auction.token_id, &bidder);
+                if deliverable {
+                    ensure!(<generic_asset::Module<T>>::reserved_balance(&auction.asset, &bidder) >= amount, "top bid no longer reserved");
+                };
+                deliverable
+            },
+            None => false,
+        };
+        let bidders = Self::get_sealed_bidders(&auction_id);
+        for bidder in bidders.iter(){
+            if <SealedCommits<T>>::exists((auction_id, bidder.clone())) {
+                ensure!(<generic_asset::Module<T>>::reserved_balance(&auction.asset, bidder) >= auction.deposit, "bid deposit no longer reserved");
+            };
+        };
 
-        use primitives::{H256};
-        use support::{impl_outer_origin, parameter_types};
-        use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
-        use sr_primitives::weights::Weight;
-        use sr_primitives::Perbill;
+        // Writing to storage begins here
+        let pot = Self::account_id();
+        match auction.highest {
+            Some((bidder, amount)) => if deliverable {
+                let price = auction.second_price;
+                <nfts::Module<T>>::_release_escrow(pot, bidder.clone(), auction.token_id)?;
+                Self::_unreserve_asset(bidder.clone(), auction.asset, amount)?;
+                Self::_transfer_asset(bidder.clone(), auction.seller.clone(), price, auction.asset)?;
+                Self::deposit_event(RawEvent::OrderFilled(auction.seller.clone(), bidder.clone(), auction.token_id, auction.asset, price, timepoint, Zero::zero(), Zero::zero()));
+                Self::_record_trade(auction_id, T::Hash::default(), auction.seller.clone(), bidder.clone(), auction.token_id, auction.asset, price);
+                Self::deposit_event(RawEvent::AuctionSettled(auction.seller.clone(), bidder, auction_id, price));
+            } else {
+                // the bid goes back and the token to the seller, an expired token is reaped instead
+                Self::_unreserve_asset(bidder, auction.asset, amount)?;
+                <nfts::Module<T>>::_release_escrow(pot, auction.seller.clone(), auction.token_id)?;
+                Self::deposit_event(RawEvent::AuctionClosed(auction.seller.clone(), auction_id));
+            },
+            None => {
+                <nfts::Module<T>>::_release_escrow(pot, auction.seller.clone(), auction.token_id)?;
+                Self::deposit_event(RawEvent::AuctionClosed(auction.seller.clone(), auction_id));
+            },
+        };
 
-        impl_outer_origin! {
-            pub enum Origin for Test {}
+        // deposits of bids never revealed go to the seller
+        for bidder in bidders.iter(){
+            if <SealedCommits<T>>::take((auction_id, bidder.clone())).is_some() {
+                Self::_unreserve_asset(bidder.clone(), auction.asset, auction.deposit)?;
+                Self::_transfer_asset(bidder.clone(), auction.seller.clone(), auction.deposit, auction.asset)?;
+            };
+        };
+        <SealedBidders<T>>::remove(&auction_id);
+        <SealedAuctions<T>>::remove(auction_id);
+        <TokenAuction<T>>::remove(auction.token_id);
+        Ok(())
+    }
+    fn _is_order_expired(expire_at: Option<T::Moment>) -> bool {
+        match expire_at {
+            Some(t) => t <= <timestamp::Module<T>>::get(),
+            None => false,
         }
-
-
-
-        // For testing the module, we construct most of a mock runtime. This means
-        // first constructing a configuration type (`Test`) which `impl`s each of the
-        // configuration traits of modules we want to use.
-       #[derive(Clone, Eq, PartialEq, Debug)]
-        pub struct Test;
-        parameter_types! {
-            pub const BlockHashCount: u64 = 250;
-            pub const MaximumBlockWeight: Weight = 1024;
-            pub const MaximumBlockLength: u32 = 2 * 1024;
-            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+    }
+    // the bucket an expiry is reaped in, the end of its bucket so it is reaped once expired
+    fn _expiry_bucket(expire_at: T::Moment) -> T::Moment {
+        let bucket: T::Moment = EXPIRY_BUCKET.saturated_into();
+        let rest = expire_at % bucket;
+        if rest.is_zero() {
+            expire_at
+        } else {
+            expire_at.saturating_add(bucket - rest)
         }
-        impl system::Trait for Test {
-            type Origin = Origin;
-            type Call = ();
-            type Index = u64;
-            type BlockNumber = u64;
-            type Hash = H256;
-            type Hashing = BlakeTwo256;
-            type AccountId = u64;
-            type Lookup = IdentityLookup<Self::AccountId>;
-            type Header = Header;
-            type Event = ();
-            type BlockHashCount = BlockHashCount;
-            type MaximumBlockWeight = MaximumBlockWeight;
+    }
+    fn _schedule_order_expiry(order_id: T::Hash, expire_at: Option<T::Moment>) {
+        if let Some(t) = expire_at {
+            <ExpiryQueue<T>>::mutate(Self::_expiry_bucket(t), |x| x.push(order_id));
+            ExpiryCount::mutate(|x| *x += 1);
+        };
+    }
+    // drop an order closed before its expiry from the queue
+    fn _unschedule_order_expiry(order_id: T::Hash, expire_at: Option<T::Moment>) {
+        let bucket = match expire_at {
+            Some(t) => Self::_expiry_bucket(t),
+            None => return,
+        };
+        let mut order_ids = Self::get_expiry_queue(bucket);
+        let len = order_ids.len();
+        order_ids.retain(|id| *id != order_id);
+        if order_ids.len() == len {
+            return // being reaped right now
+        };
+        if order_ids.is_empty() {
+            <ExpiryQueue<T>>::remove(bucket);
+        } else {
+            <ExpiryQueue<T>>::insert(bucket, order_ids);
+        };
+        ExpiryCount::mutate(|x| *x = x.saturating_sub(1));
+    }
+    // close expired orders giving back what they hold, filled or canceled ones left the queue already
+    fn _reap_expired_orders(limit: usize) {
+        let now = <timestamp::Module<T>>::get();
+        let bucket: T::Moment = EXPIRY_BUCKET.saturated_into();
+        if Self::get_expiry_count() == 0 {
+            // nothing to reap, skip the empty buckets up to now
+            let start = now - now % bucket;
+            if Self::get_expiry_cursor() != start {
+                <ExpiryCursor<T>>::put(start);
+            };
+            return
+        };
+        let mut cursor = Self::get_expiry_cursor();
+        let mut reaped = 0;
+        let mut walked = 0;
+        while cursor <= now && walked < MAX_EXPIRY_BUCKETS_PER_BLOCK {
+            let mut order_ids = <ExpiryQueue<T>>::take(cursor);
+            while reaped < limit {
+                let order_id = match order_ids.pop(){
+                    Some(x) => x,
+                    None => break,
+                };
+                reaped += 1;
+                ExpiryCount::mutate(|x| *x = x.saturating_sub(1));
+                if Self::_expire_order(order_id, now).is_err() {
+                    Self::deposit_event(RawEvent::OrderExpiryFailed(order_id));
+                };
+            };
+            if order_ids.len() > 0 {
+                <ExpiryQueue<T>>::insert(cursor, order_ids);
+                break;
+            };
+            cursor = cursor.saturating_add(bucket);
+            walked += 1;
+        };
+        <ExpiryCursor<T>>::put(cursor);
+    }
+    // checked before anything is written, an order that can not expire is left untouched
+    fn _expire_order(order_id: T::Hash, now: T::Moment) -> Result {
+        if let Some(order) = Self::get_ask_token_order(&order_id) {
+            ensure!(Self::_is_order_expired(order.expire_at), "order not expired");
+            ensure!(Self::get_orderbook_ask(&order.asset).get(&order.price).map_or(false, |x| x.contains(&order_id)), "order not found in the orderbook");
+
+            // Writing to storage begins here
+            for token_id in order.bind_tokens.iter(){
+                <TokenAskOrder<T>>::remove(token_id);
+                Self::_release_bound_token(&order.creator, token_id);
+            };
+            Self::_remove_order_from_orderbook(order.price, order.asset, order_id, false, OrderStatus::Closed)?;
+            Self::deposit_event(RawEvent::OrderExpired(order.creator, order_id, now));
+        } else if let Some(order) = Self::get_bid_token_order(&order_id) {
+            ensure!(Self::_is_order_expired(order.expire_at), "order not expired");
+            ensure!(Self::get_orderbook_bid(&order.asset).get(&order.price).map_or(false, |x| x.contains(&order_id)), "order not found in the orderbook");
+            let tk_count_to_buy: u32 = order.tk_count_to_buy.saturated_into::<u32>();
+            let tk_count_to_buy_balance: T::Balance = tk_count_to_buy.into();
+            let reserved = tk_count_to_buy_balance * order.price;
+            ensure!(<generic_asset::Module<T>>::reserved_balance(&order.asset, &order.creator) >= reserved, "order funds no longer reserved");
+
+            // Writing to storage begins here
+            Self::_unreserve_asset(order.creator.clone(), order.asset, reserved)?;
+            Self::_remove_order_from_orderbook(order.price, order.asset, order_id, true, OrderStatus::Closed)?;
+            Self::deposit_event(RawEvent::OrderExpired(order.creator, order_id, now));
+        };
+        Ok(())
+    }
+    fn _index_order(owner: &T::AccountId, order_id: T::Hash, is_bid: bool) {
+        if is_bid {
+            <OwnedBidOrders<T>>::mutate(owner, |x| x.push(order_id));
+        } else {
+            <OwnedAskOrders<T>>::mutate(owner, |x| x.push(order_id));
+        };
+    }
+    fn _unindex_order(owner: &T::AccountId, order_id: T::Hash, is_bid: bool) {
+        if is_bid {
+            <OwnedBidOrders<T>>::mutate(owner, |x| x.retain(|id| *id != order_id));
+        } else {
+            <OwnedAskOrders<T>>::mutate(owner, |x| x.retain(|id| *id != order_id));
+        };
+    }
+    // move an order from the open index of its owner to its closed orders
+    fn _archive_order(owner: &T::AccountId, order: ClosedOrder<T::Hash, T::AssetId, T::Balance, T::Moment>) {
+        Self::_unindex_order(owner, order.order_id, order.is_bid);
+        <ClosedOrders<T>>::mutate(owner, |x| {
+            if x.len() >= MAX_CLOSED_ORDERS {
+                x.remove(0);
+            };
+            x.push(order);
+        });
+    }
+    fn _record_trade(ask_order_id: T::Hash,
+        bid_order_id: T::Hash,
+        seller: T::AccountId,
+        buyer: T::AccountId,
+        token_id: T::Hash,
+        asset: T::AssetId,
+        price: T::Balance) {
+        let seq = Self::get_trade_count();
+        <TradeRecords<T>>::insert(seq % MAX_TRADE_RECORDS, TradeRecord{
+            seq,
+            ask_order_id,
+            bid_order_id,
+            seller,
+            buyer,
+            token_id,
+            asset,
+            price,
+            timepoint: <timestamp::Module<T>>::get(),
+        });
+        TradeCount::put(seq + 1);
+    }
+    // trades from seq `start` on, trades already overwritten are skipped
+    pub fn trade_records(start: u64, count: u32) -> Vec<TradeRecord<T::AccountId, T::Hash, T::AssetId, T::Balance, T::Moment>> {
+        let total = Self::get_trade_count();
+        let start = start.max(total.saturating_sub(MAX_TRADE_RECORDS));
+        let end = total.min(start.saturating_add(count.min(MAX_TRADE_PAGE) as u64));
+        (start..end).filter_map(|seq| Self::get_trade_record(seq % MAX_TRADE_RECORDS)).collect()
+    }
+    // open orders of an account, asks first
+    pub fn account_orders(who: &T::AccountId) -> Vec<OrderDetails<T::Hash, T::AssetId, T::Balance, T::Moment>> {
+        let mut orders = Vec::new();
+        for order_id in Self::get_owned_ask_orders(who).iter(){
+            if let Some(order) = Self::get_ask_token_order(order_id) {
+                orders.push(OrderDetails{
+                    order_id: *order_id,
+                    is_bid: false,
+                    asset: order.asset,
+                    price: order.price,
+                    timepoint: order.timepoint,
+                    time_in_force: order.time_in_force,
+                    expire_at: order.expire_at,
+                    status: order.status,
+                    remaining: order.bind_tokens.len().saturated_into::<u32>(),
+                    bind_tokens: order.bind_tokens,
+                });
+            };
+        };
+        for order_id in Self::get_owned_bid_orders(who).iter(){
+            if let Some(order) = Self::get_bid_token_order(order_id) {
+                orders.push(OrderDetails{
+                    order_id: *order_id,
+                    is_bid: true,
+                    asset: order.asset,
+                    price: order.price,
+                    timepoint: order.timepoint,
+                    time_in_force: order.time_in_force,
+                    expire_at: order.expire_at,
+                    status: order.status,
+                    remaining: order.tk_count_to_buy.saturated_into::<u32>(),
+                    bind_tokens: Vec::new(),
+                });
+            };
+        };
+        orders
+    }
+    // give a token back to its owner once nothing here holds it anymore
+    fn _release_bound_token(owner: &T::AccountId, token_id: &T::Hash) {
+        if <nfts::Module<T>>::get_token_reserve(token_id) {
+            // fails only when the token already left the owner, leaving nothing to release
+            let _ = <nfts::Module<T>>::_token_unlock(owner.clone(), *token_id, ORDER_LOCK_ID);
+        };
+    }
+    fn _unlock_stranded_tokens(token_ids: Vec<T::Hash>) -> Result {
+        ensure!(token_ids.len() <= MAX_UNLOCK_BATCH, "too many tokens in one batch");
+        for token_id in token_ids.iter(){
+            if Self::get_token_ask_order(token_id).is_some() || Self::get_token_auction(token_id).is_some() {
+                continue; // still held by an order or an auction
+            };
+            // only locks taken by this module are repaired, collateral, staked or vaulted tokens
+            // are locked by their own module and reservations of the owner are lifted by the owner
+            if <nfts::Module<T>>::get_token_lock(token_id) != Some(ORDER_LOCK_ID) {
+                continue;
+            };
+            let owner = match <erc721::Module<T>>::owner_of(token_id){
+                Some(t) => t,
+                None => continue,
+            };
+            <nfts::Module<T>>::_token_unlock(owner, *token_id, ORDER_LOCK_ID)?;
+            Self::deposit_event(RawEvent::StrandedTokenUnlocked(*token_id));
+        };
+        Ok(())
+    }
+    // ask orders leave the orderbook once their last token is gone, dutch listings never entered it
+    fn _close_ask_order(price: T::Balance, asset: T::AssetId, order_id: T::Hash, status: OrderStatus) -> Result{
+        if <DutchListings<T>>::exists(&order_id) {
+            let order = match <AskTokenOrders<T>>::take(&order_id){
+                Some(t) => t,
+                None => return Err("order not found"),
+            };
+            <DutchListings<T>>::remove(&order_id);
+            Self::_archive_order(&order.creator, ClosedOrder{
+                order_id,
+                is_bid: false,
+                asset: order.asset,
+                price: order.price,
+                remaining: order.bind_tokens.len().saturated_into::<u32>(),
+                status,
+                closed_at: <timestamp::Module<T>>::get(),
+            });
+            Self::deposit_event(RawEvent::OrderClosed(order.creator, order_id, order.asset, order.price, order.timepoint, order.time_in_force));
+            return Ok(())
+        };
+        Self::_remove_order_from_orderbook(price, asset, order_id, false, status)
+    }
+    pub fn dutch_price(listing: &DutchListing<T::Balance, T::Moment>, now: T::Moment) -> T::Balance {
+        if now <= listing.start {
+            return listing.start_price
+        };
+        if now >= listing.end {
+            return listing.floor_price
+        };
+        let elapsed = (now - listing.start).saturated_into::<u128>();
+        let duration = (listing.end - listing.start).saturated_into::<u128>();
+        let drop = (listing.start_price - listing.floor_price).saturated_into::<u128>();
+        let fallen = match listing.curve {
+            PriceCurve::Linear => drop.saturating_mul(elapsed) / duration,
+            PriceCurve::Stepwise(steps) => {
+                let steps = steps as u128;
+                drop.saturating_mul(elapsed.saturating_mul(steps) / duration) / steps
+            },
+        };
+        listing.start_price - fallen.saturated_into::<T::Balance>()
+    }
+    fn _dutch_listing_create(seller: T::AccountId,
+        token_id: T::Hash,
+        asset: T::AssetId,
+        start_price: T::Balance,
+        floor_price: T::Balance,
+        end: T::Moment,
+        curve: PriceCurve) -> Result{
+        let timepoint = <timestamp::Module<T>>::get();
+        ensure!(end > timepoint, "listing end must be in the future");
+        ensure!(floor_price <= start_price, "floor price above start price");
+        if let PriceCurve::Stepwise(steps) = curve {
+            ensure!(steps > 0, "stepwise curve needs at least one step");
+        };
+        let token = match <nfts::Module<T>>::get_token(&token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        Self::_ensure_required_attrs(&token_id)?;
+        let selector = TokenSelector{
+            selector: IdSelect(TokenIdSelector{ id_set: vec![token_id] }),
+            nft_type: token.nft_id,
+        };
+        let order_id = (&seller, &asset, timepoint, start_price, floor_price, end, &selector).using_encoded(<T as system::Trait>::Hashing::hash);
+        ensure!(Self::get_ask_token_order(&order_id).is_none(), "order id conflicts");
+        <nfts::Module<T>>::_token_lock(seller.clone(), token_id, ORDER_LOCK_ID)?;
+
+        <AskTokenOrders<T>>::insert(order_id, AskOrderItem{
+            creator: seller.clone(),
+            order_id,
+            selector,
+            asset,
+            price: start_price,
+            timepoint,
+            time_in_force: TimeInForce::GoodTillCancel,
+            expire_at: None,
+            bind_tokens: vec![token_id],
+            status: OrderStatus::Open,
+        });
+        <TokenAskOrder<T>>::insert(token_id, order_id);
+        Self::_index_order(&seller, order_id, false);
+        <DutchListings<T>>::insert(order_id, DutchListing{
+            start_price,
+            floor_price,
+            start: timepoint,
+            end,
+            curve,
+        });
+        Self::deposit_event(RawEvent::OrderOpened(seller, order_id, asset, start_price, timepoint, TimeInForce::GoodTillCancel));
+        Ok(())
+    }
+    fn _dutch_buy(buyer: T::AccountId, order_id: T::Hash, max_price: T::Balance) -> Result{
+        let listing = match Self::get_dutch_listing(&order_id){
+            Some(t) => t,
+            None => return Err("dutch listing not found"),
+        };
+        let order = match Self::get_ask_token_order(&order_id){
+            Some(t) => t,
+            None => return Err("order not found"),
+        };
+        let token_id = match order.bind_tokens.get(0){
+            Some(t) => *t,
+            None => return Err("token not found"),
+        };
+        ensure!(buyer != order.creator, "seller can not buy own listing");
+        let price = Self::dutch_price(&listing, <timestamp::Module<T>>::get());
+        ensure!(price <= max_price, "current price above max price");
+        // the fill is checked before the price is reserved, so neither can fail halfway
+        let free = match <generic_asset::Module<T>>::free_balance(&order.asset, &buyer).checked_sub(&price){
+            Some(t) => t,
+            None => return Err("free balance too low to pay the price"),
+        };
+        Self::_check_fill(price, order.asset, &token_id, &buyer, &order.creator, false, free)?;
+        ensure!(<nfts::Module<T>>::get_token_lock(&token_id) == Some(ORDER_LOCK_ID), "token not locked by this module");
+
+        // Writing to storage begins here
+        // the payment goes through the usual ask fill, which closes the listing
+        Self::_reserve_asset(buyer.clone(), order.asset, price)?;
+        Self::_fill(price, order.asset, order_id, T::Hash::default(), token_id, buyer, order.creator, false)
+    }
+    fn _dutch_listing_cancel(seller: T::AccountId, order_id: T::Hash) -> Result{
+        ensure!(<DutchListings<T>>::exists(&order_id), "dutch listing not found");
+        let order = match Self::get_ask_token_order(&order_id){
+            Some(t) => t,
+            None => return Err("order not found"),
+        };
+        ensure!(order.creator == seller, "creator dismatch, creator not own this order");
+        for token_id in order.bind_tokens.iter(){
+            <nfts::Module<T>>::_token_unlock(seller.clone(), *token_id, ORDER_LOCK_ID)?;
+            <TokenAskOrder<T>>::remove(token_id);
+        };
+        <AskTokenOrders<T>>::remove(&order_id);
+        <DutchListings<T>>::remove(&order_id);
+        let timepoint = <timestamp::Module<T>>::get();
+        Self::_archive_order(&seller, ClosedOrder{
+            order_id,
+            is_bid: false,
+            asset: order.asset,
+            price: order.price,
+            remaining: order.bind_tokens.len().saturated_into::<u32>(),
+            status: OrderStatus::Canceled,
+            closed_at: timepoint,
+        });
+        Self::deposit_event(RawEvent::OrderCanceled(seller, order_id, timepoint));
+        Ok(())
+    }
+    fn _english_auction_create(seller: T::AccountId,
+        token_id: T::Hash,
+        asset: T::AssetId,
+        reserve_price: T::Balance,
+        min_increment: T::Balance,
+        end: T::Moment) -> Result{
+        let timepoint = <timestamp::Module<T>>::get();
+        ensure!(end > timepoint, "auction end must be in the future");
+        let auction_id = (&seller, token_id, timepoint, b"english").using_encoded(<T as system::Trait>::Hashing::hash);
+        ensure!(Self::get_english_auction(&auction_id).is_none(), "auction id conflicts");
+        Self::_ensure_required_attrs(&token_id)?;
+        // the token waits in the order account until the auction is over
+        <nfts::Module<T>>::_escrow_token(seller.clone(), Self::account_id(), token_id)?;
+
+        <EnglishAuctions<T>>::insert(auction_id, EnglishAuction{
+            seller: seller.clone(),
+            auction_id,
+            token_id,
+            asset,
+            reserve_price,
+            min_increment,
+            end,
+            top_bid: None,
+        });
+        <TokenAuction<T>>::insert(token_id, auction_id);
+        Self::deposit_event(RawEvent::AuctionCreated(seller, auction_id, token_id, asset, reserve_price, end));
+        Ok(())
+    }
+    fn _english_auction_bid(bidder: T::AccountId, auction_id: T::Hash, amount: T::Balance) -> Result{
+        let mut auction = match Self::get_english_auction(&auction_id){
+            Some(t) => t,
+            None => return Err("auction not found"),
+        };
+        let timepoint = <timestamp::Module<T>>::get();
+        ensure!(timepoint < auction.end, "auction already ended");
+        ensure!(bidder != auction.seller, "seller can not bid on own auction");
+        let min_bid = match auction.top_bid {
+            Some((_, top)) => match top.checked_add(&auction.min_increment){
+                Some(t) => t,
+                None => return Err("Overflow adding the minimum increment"),
+            },
+            None => auction.reserve_price,
+        };
+        ensure!(amount >= min_bid, "bid lower than the minimum bid");
+
+        // the new bid is locked before the outbid one is released
+        Self::_reserve_asset(bidder.clone(), auction.asset, amount)?;
+        if let Some((prev_bidder, prev_amount)) = auction.top_bid.take() {
+            Self::_unreserve_asset(prev_bidder, auction.asset, prev_amount)?;
+        };
+        auction.top_bid = Some((bidder.clone(), amount));
+        let extension = T::AuctionExtension::get();
+        if auction.end - timepoint < extension {
+            auction.end = timepoint + extension;
+        };
+        let end = auction.end;
+        <EnglishAuctions<T>>::insert(auction_id, auction);
+        Self::deposit_event(RawEvent::AuctionBid(bidder, auction_id, amount, end));
+        Ok(())
+    }
+    fn _english_auction_settle(auction_id: T::Hash) -> Result{
+        let auction = match Self::get_english_auction(&auction_id){
+            Some(t) => t,
+            None => return Err("auction not found"),
+        };
+        let timepoint = <timestamp::Module<T>>::get();
+        ensure!(timepoint >= auction.end, "auction not ended yet");
+
+        let pot = Self::account_id();
+        match auction.top_bid {
+            Some((bidder, amount)) => if Self::_auction_deliverable(&auction.token_id, &bidder) {
+                ensure!(<generic_asset::Module<T>>::reserved_balance(&auction.asset, &bidder) >= amount, "top bid no longer reserved");
+                // Writing to storage begins here
+                <nfts::Module<T>>::_release_escrow(pot, bidder.clone(), auction.token_id)?;
+                Self::_unreserve_asset(bidder.clone(), auction.asset, amount)?;
+                Self::_transfer_asset(bidder.clone(), auction.seller.clone(), amount, auction.asset)?;
+                // auctions are fee-free, fees only apply to orderbook and dutch fills
+                Self::deposit_event(RawEvent::OrderFilled(auction.seller.clone(), bidder.clone(), auction.token_id, auction.asset, amount, timepoint, Zero::zero(), Zero::zero()));
+                Self::_record_trade(auction_id, T::Hash::default(), auction.seller.clone(), bidder.clone(), auction.token_id, auction.asset, amount);
+                Self::deposit_event(RawEvent::AuctionSettled(auction.seller.clone(), bidder, auction_id, amount));
+            } else {
+                // the bid goes back and the token to the seller, an expired token is reaped instead
+                Self::_unreserve_asset(bidder, auction.asset, amount)?;
+                <nfts::Module<T>>::_release_escrow(pot, auction.seller.clone(), auction.token_id)?;
+                Self::deposit_event(RawEvent::AuctionClosed(auction.seller.clone(), auction_id));
+            },
+            None => {
+                <nfts::Module<T>>::_release_escrow(pot, auction.seller.clone(), auction.token_id)?;
+                Self::deposit_event(RawEvent::AuctionClosed(auction.seller.clone(), auction_id));
+            },
+        };
+        <EnglishAuctions<T>>::remove(auction_id);
+        <TokenAuction<T>>::remove(auction.token_id);
+        Ok(())
+    }
+    // an auctioned token reaches its winner unless it expired or the winner is blacklisted under the nft
+    fn _auction_deliverable(token_id: &T::Hash, winner: &T::AccountId) -> bool {
+        let token = match <nfts::Module<T>>::get_token(token_id){
+            Some(t) => t,
+            None => return false,
+        };
+        !<nfts::Module<T>>::_is_expired(token_id) && <nfts::Module<T>>::nft_perm((token.nft_id, winner.clone())) != Some(false)
+    }
+    fn _english_auction_cancel(seller: T::AccountId, auction_id: T::Hash) -> Result{
+        let auction = match Self::get_english_auction(&auction_id){
+            Some(t) => t,
+            None => return Err("auction not found"),
+        };
+        ensure!(auction.seller == seller, "seller dismatch, seller not own this auction");
+        ensure!(auction.top_bid.is_none(), "auction with bids can not be canceled");
+        <nfts::Module<T>>::_release_escrow(Self::account_id(), seller.clone(), auction.token_id)?;
+        <EnglishAuctions<T>>::remove(auction_id);
+        <TokenAuction<T>>::remove(auction.token_id);
+        Self::deposit_event(RawEvent::AuctionClosed(seller, auction_id));
+        Ok(())
+    }
+}
+
+// token holding checks for other modules
+pub trait TokenGate<AccountId, Hash> {
+    // tokens of `who` matching the selector, reserved tokens count as held
+    fn matching_tokens(who: &AccountId, selector: &TokenSelector<Hash>) -> Vec<Hash>;
+    fn holds_matching(who: &AccountId, selector: &TokenSelector<Hash>) -> bool {
+        !Self::matching_tokens(who, selector).is_empty()
+    }
+}
+
+impl<T: Trait> TokenGate<T::AccountId, T::Hash> for Module<T> {
+    fn matching_tokens(who: &T::AccountId, selector: &TokenSelector<T::Hash>) -> Vec<T::Hash> {
+        Self::_token_visitor(who.clone(), selector, true)
+    }
+    // runs while validating transactions for the selectors a gate accepts: at most MAX_GATE_SCAN
+    // selected ids are looked at, a whole nft is checked on the held count, which keeps counting
+    // expired tokens until they are reaped
+    fn holds_matching(who: &T::AccountId, selector: &TokenSelector<T::Hash>) -> bool {
+        match &selector.selector {
+            IdSelect(t) => t.id_set.iter().take(MAX_GATE_SCAN).any(|token_id| {
+                <erc721::Module<T>>::owner_of(token_id).as_ref() == Some(who) && Self::_token_fits_selector(token_id, selector)
+            }),
+            AttrSelect(t) if t.stack.len() == 0 => {
+                <nfts::Module<T>>::held_token_count((who.clone(), selector.nft_type)) > 0
+            },
+            AttrSelect(_) => !Self::matching_tokens(who, selector).is_empty(),
+        }
+    }
+}
+
+// custom validity error of a call refused by its gate
+pub const TOKEN_GATE_ERROR: u8 = 10;
+
+// signed extension refusing gated calls from accounts holding no matching token, only the
+// outer call is checked, so calls nested in sudo or a batch are not gated
+#[derive(Encode, Decode, Clone, Eq, PartialEq)]
+pub struct CheckTokenGate<T: Trait + Send + Sync>(rstd::marker::PhantomData<T>);
+
+impl<T: Trait + Send + Sync> CheckTokenGate<T> {
+    pub fn new() -> Self {
+        CheckTokenGate(rstd::marker::PhantomData)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Trait + Send + Sync> fmt::Debug for CheckTokenGate<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CheckTokenGate")
+    }
+}
+
+impl<T: Trait + Send + Sync> SignedExtension for CheckTokenGate<T> where <T as system::Trait>::Call: Encode {
+    type AccountId = T::AccountId;
+    type Call = <T as system::Trait>::Call;
+    type AdditionalSigned = ();
+    type Pre = ();
+
+    fn additional_signed(&self) -> result::Result<(), TransactionValidityError> {
+        Ok(())
+    }
+
+    fn validate(&self, who: &Self::AccountId, call: &Self::Call, _info: DispatchInfo, _len: usize) -> TransactionValidity {
+        match <Module<T>>::_check_call_gate(who, &call.encode()){
+            Ok(_) => Ok(Default::default()),
+            Err(_) => Err(InvalidTransaction::Custom(TOKEN_GATE_ERROR).into()),
+        }
+    }
+}
+
+impl<T: Trait> erc721::TokenHooks<T::AccountId, T::Hash> for Module<T> {
+    fn on_mint(who: &T::AccountId, token_id: &T::Hash) -> Result {
+        <nfts::Module<T>>::_on_mint(who, token_id)
+    }
+    fn on_burn(who: &T::AccountId, token_id: &T::Hash, reason: u8) -> Result {
+        Self::_on_token_burn(who, token_id, reason)
+    }
+    fn ensure_transferable(token_id: &T::Hash) -> Result {
+        <nfts::Module<T>>::_ensure_transferable(token_id)
+    }
+    fn on_transfer(from: &T::AccountId, to: &T::AccountId, token_id: &T::Hash) {
+        <nfts::Module<T>>::_on_transfer(from, to, token_id)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        use primitives::{H256};
+        use support::{impl_outer_origin, parameter_types};
+        use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
+        use sr_primitives::weights::Weight;
+        use sr_primitives::Perbill;
+
+        impl_outer_origin! {
+            pub enum Origin for Test {}
+        }
+
+
+
+        // For testing the module, we construct most of a mock runtime. This means
+        // first constructing a configuration type (`Test`) which `impl`s each of the
+        // configuration traits of modules we want to use.
+       #[derive(Clone, Eq, PartialEq, Debug)]
+        pub struct Test;
+        parameter_types! {
+            pub const BlockHashCount: u64 = 250;
+            pub const MaximumBlockWeight: Weight = 1024;
+            pub const MaximumBlockLength: u32 = 2 * 1024;
+            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+        }
+        impl system::Trait for Test {
+            type Origin = Origin;
+            type Call = ();
+            type Index = u64;
+            type BlockNumber = u64;
+            type Hash = H256;
+            type Hashing = BlakeTwo256;
+            type AccountId = u64;
+            type Lookup = IdentityLookup<Self::AccountId>;
+            type Header = Header;
+            type Event = ();
+            type BlockHashCount = BlockHashCount;
+            type MaximumBlockWeight = MaximumBlockWeight;
             type MaximumBlockLength = MaximumBlockLength;
             type AvailableBlockRatio = AvailableBlockRatio;
             type Version = ();
@@ -1268,10 +2673,19 @@ mod tests {
         
         impl erc721::Trait for Test{
             type Event = ();
+            type Hooks = Module<Test>;
+        }
+        parameter_types! {
+            pub const ClassDeposit: Balance = 10;
+            pub const TokenDeposit: Balance = 2;
+            pub const DepositPerByte: Balance = 1;
         }
         impl nfts::Trait for Test{
             type Event = ();
             type Currency = balances::Module<Self>;
+            type ClassDeposit = ClassDeposit;
+            type TokenDeposit = TokenDeposit;
+            type DepositPerByte = DepositPerByte;
         }
         impl generic_asset::Trait for Test {
                 type Event = ();
@@ -1289,14 +2703,24 @@ mod tests {
         }
 
 
+        parameter_types! {
+            pub const AuctionExtension: u64 = 10;
+        }
         impl Trait for Test {
             type Event = ();
+            type AuctionExtension = AuctionExtension;
+            type FeeOrigin = system::EnsureRoot<u64>;
         }
 
         // This function basically just builds a genesis storage key/value store according to
         // our desired mockup.
         fn new_test_ext() -> runtime_io::TestExternalities {
-            system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+            let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+            balances::GenesisConfig::<Test>{
+                balances: vec![(0, 1000), (1, 1000), (2, 1000), (3, 1000)],
+                vesting: vec![],
+            }.assimilate_storage(&mut t).unwrap();
+            t.into()
         }
 
         pub type NftsModule = nfts::Module<Test>;
@@ -1363,61 +2787,462 @@ mod tests {
         }
 
         #[test]
-        fn token_selector_test() {
+        fn token_attr_deposit_test() {
             new_test_ext().execute_with(|| {
-
-                fn print_attributes(attr_value: &TokenAttrValType ){
-                    match &attr_value{
-                        String(x1) => {// Vec<u8>
-                            println!("String as => {}", str::from_utf8(&x1).unwrap());
-                        },
-                        Uint64(x2) => {
-                            println!("integer as => {}", x2);
-                        },
-                    }
-                    
-                };
                 let bob = 0;
-                let bobby = 3;
-                let alice = 1;  
-                let jack = 2;  
+                type BalancesModule = balances::Module<Test>;
                 NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
                 let nft_id = NftsModule::get_nft_by_index(0);
-
                 NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
                 let token_id = Erc721Module::token_by_index(0);
-                let attr1 = Attributes{
-                    key: "attr1".as_bytes().to_vec(),
-                    value: String("attr1_value1".as_bytes().to_vec()),
-                };
-                let attr2 = Attributes{
-                    key: "attr2".as_bytes().to_vec(),
+                let reserved = BalancesModule::reserved_balance(bob);
+
+                let attr = Attributes{
+                    key: "attr".as_bytes().to_vec(),
                     value: Uint64(88),
                 };
-                let attr3 = Attributes{
-                    key: "attr3".as_bytes().to_vec(),
-                    value: Uint64(20),
-                };
-                OrderModule::set_token_attr(Origin::signed(bob),token_id, attr1, );
-                OrderModule::set_token_attr(Origin::signed(bob),token_id, attr2, );
-                OrderModule::set_token_attr(Origin::signed(bob),token_id, attr3, );
-                let mut stack = Vec::<FilterItem>::new();
-                let f1 = BooleanExpression {
-                    op: CompareOpcode::TokenCmpEq,
-                    key: "attr2".as_bytes().to_vec(),
-                    val : String("attr1_value1".as_bytes().to_vec()),
-                };
-                let f2 = BooleanExpression {
-                    op: CompareOpcode::TokenCmpEq,
-                    key: "attr1".as_bytes().to_vec(),
-                    val : Uint64(88),
+                // 4 bytes of key, 1 byte of enum index and 8 bytes of u64
+                let res = OrderModule::set_token_attr(Origin::signed(bob), token_id, attr);
+                assert_eq!(res, Ok(()));
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved + 13);
+
+                // overwrite with a longer value, only the new deposit stays reserved
+                let attr = Attributes{
+                    key: "attr".as_bytes().to_vec(),
+                    value: String("a longer string".as_bytes().to_vec()),
                 };
-                let f3 = BooleanExpression {
-                    op: CompareOpcode::TokenCmpLt,
-                    key: "attr3".as_bytes().to_vec(),
-                    val : Uint64(22),
+                let res = OrderModule::set_token_attr(Origin::signed(bob), token_id, attr);
+                assert_eq!(res, Ok(()));
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved + 21);
+
+                let res = OrderModule::rmv_token_attr(Origin::signed(bob), token_id, "attr".as_bytes().to_vec());
+                assert_eq!(res, Ok(()));
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved);
+                assert_eq!(OrderModule::get_attr_deposit((token_id, "attr".as_bytes().to_vec())), None);
+            });
+        }
+
+        #[test]
+        fn token_attr_schema_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let token_id = Erc721Module::token_by_index(0);
+                NftsModule::_reserve_safe_transfer(bob, alice, token_id);
+
+                let res = NftsModule::set_attr_schema(Origin::signed(bob), nft_id, "level".as_bytes().to_vec(), nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::Uint64,
+                    required: false,
+                    mutability: nfts::AttrMutability::OwnerWritable,
+                });
+                assert_eq!(res, Ok(()));
+                NftsModule::set_attr_schema(Origin::signed(bob), nft_id, "rarity".as_bytes().to_vec(), nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::String,
+                    required: false,
+                    mutability: nfts::AttrMutability::IssuerOnly,
+                });
+
+                // keys outside of the schema are rejected
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, Attributes{
+                    key: "color".as_bytes().to_vec(),
+                    value: String("red".as_bytes().to_vec()),
+                });
+                assert_eq!(res, Err("attribute key not in the schema of this nft"));
+                // value type has to follow the schema
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, Attributes{
+                    key: "level".as_bytes().to_vec(),
+                    value: String("high".as_bytes().to_vec()),
+                });
+                assert_eq!(res, Err("attribute value type mismatches the schema"));
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, Attributes{
+                    key: "level".as_bytes().to_vec(),
+                    value: Uint64(3),
+                });
+                assert_eq!(res, Ok(()));
+                // a key becomes required only once every token holds it
+                let res = NftsModule::set_attr_schema(Origin::signed(bob), nft_id, "rarity".as_bytes().to_vec(), nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::String,
+                    required: true,
+                    mutability: nfts::AttrMutability::IssuerOnly,
+                });
+                assert_eq!(res, Err("attribute missing on some tokens, it can not be made required"));
+                let required_level = nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::Uint64,
+                    required: true,
+                    mutability: nfts::AttrMutability::OwnerWritable,
+                };
+                let res = NftsModule::set_attr_schema(Origin::signed(bob), nft_id, "level".as_bytes().to_vec(), required_level.clone());
+                assert_eq!(res, Ok(()));
+                // issuer only attribute
+                let rarity = Attributes{
+                    key: "rarity".as_bytes().to_vec(),
+                    value: String("epic".as_bytes().to_vec()),
+                };
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, rarity.clone());
+                assert_eq!(res, Err("attribute can only be written by the nft issuer"));
+                let res = OrderModule::set_token_attr(Origin::signed(bob), token_id, rarity);
+                assert_eq!(res, Ok(()));
+
+                let res = OrderModule::rmv_token_attr(Origin::signed(alice), token_id, "level".as_bytes().to_vec());
+                assert_eq!(res, Err("required attribute can not be removed"));
+                let res = OrderModule::rmv_token_attr(Origin::signed(bob), token_id, "rarity".as_bytes().to_vec());
+                assert_eq!(res, Ok(()));
+
+                // the schema of a key in use can not drift away from the values already set
+                let level = "level".as_bytes().to_vec();
+                assert_eq!(NftsModule::get_attr_usage((nft_id, level.clone(), nfts::AttrValKind::Uint64)), 1);
+                let res = NftsModule::set_attr_schema(Origin::signed(bob), nft_id, level.clone(), nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::String,
+                    required: true,
+                    mutability: nfts::AttrMutability::OwnerWritable,
+                });
+                assert_eq!(res, Err("attribute key in use with another value type"));
+                // relaxing a required key leaves no token behind
+                let res = NftsModule::set_attr_schema(Origin::signed(bob), nft_id, level.clone(), nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::Uint64,
+                    required: false,
+                    mutability: nfts::AttrMutability::OwnerWritable,
+                });
+                assert_eq!(res, Ok(()));
+                let res = NftsModule::set_attr_schema(Origin::signed(bob), nft_id, level.clone(), required_level);
+                assert_eq!(res, Ok(()));
+                let res = NftsModule::remove_attr_schema(Origin::signed(bob), nft_id, level.clone());
+                assert_eq!(res, Err("attribute key in use, remove its values first"));
+                assert_eq!(NftsModule::get_attr_schema((nft_id, level.clone())).unwrap().val_kind, nfts::AttrValKind::Uint64);
+                // a key nobody holds anymore is free to change
+                let rarity_key = "rarity".as_bytes().to_vec();
+                assert_eq!(NftsModule::get_attr_usage((nft_id, rarity_key.clone(), nfts::AttrValKind::String)), 0);
+                let res = NftsModule::set_attr_schema(Origin::signed(bob), nft_id, rarity_key.clone(), nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::Uint64,
+                    required: false,
+                    mutability: nfts::AttrMutability::IssuerOnly,
+                });
+                assert_eq!(res, Ok(()));
+                let res = NftsModule::remove_attr_schema(Origin::signed(bob), nft_id, rarity_key);
+                assert_eq!(res, Ok(()));
+
+                // a token missing a required attribute can not be listed
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk1 = Erc721Module::token_by_index(1);
+                let selector = TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: vec![tk1] }),
+                    nft_type: nft_id,
                 };
-                let f4 = LogicOpcode::TokenLogicOr;
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), 0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Err("token misses a required attribute"));
+                let res = OrderModule::english_auction_create(Origin::signed(bob), tk1, 0, 100, 10, 1000);
+                assert_eq!(res, Err("token misses a required attribute"));
+                assert_eq!(Erc721Module::owner_of(tk1), Some(bob));
+                let res = OrderModule::set_token_attr(Origin::signed(bob), tk1, Attributes{
+                    key: level.clone(),
+                    value: Uint64(1),
+                });
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector, 0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let res = NftsModule::burn_token(Origin::signed(bob), tk1, 0);
+                assert_eq!(res, Ok(()));
+
+                // burning the token releases its values
+                let res = NftsModule::burn_token(Origin::signed(alice), token_id, 0);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_attr_usage((nft_id, level.clone(), nfts::AttrValKind::Uint64)), 0);
+                let res = NftsModule::remove_attr_schema(Origin::signed(bob), nft_id, level);
+                assert_eq!(res, Ok(()));
+            });
+        }
+
+        #[test]
+        fn token_attr_permission_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let token_id = Erc721Module::token_by_index(0);
+                NftsModule::_reserve_safe_transfer(bob, alice, token_id);
+
+                let rarity = Attributes{
+                    key: "rarity".as_bytes().to_vec(),
+                    value: String("epic".as_bytes().to_vec()),
+                };
+                // attributes are issuer only by default, even for the token owner
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, rarity.clone());
+                assert_eq!(res, Err("attribute can only be written by the nft issuer"));
+                let res = OrderModule::set_token_attr(Origin::signed(bob), token_id, rarity.clone());
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::rmv_token_attr(Origin::signed(alice), token_id, "rarity".as_bytes().to_vec());
+                assert_eq!(res, Err("attribute can only be written by the nft issuer"));
+
+                // declared owner writable in the schema
+                NftsModule::set_attr_schema(Origin::signed(bob), nft_id, "nickname".as_bytes().to_vec(), nfts::AttrSchema{
+                    val_kind: nfts::AttrValKind::String,
+                    required: false,
+                    mutability: nfts::AttrMutability::OwnerWritable,
+                });
+                let nickname = Attributes{
+                    key: "nickname".as_bytes().to_vec(),
+                    value: String("rex".as_bytes().to_vec()),
+                };
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, nickname.clone());
+                assert_eq!(res, Ok(()));
+
+                // frozen while the token is reserved
+                NftsModule::token_reserve(Origin::signed(alice), token_id);
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, nickname.clone());
+                assert_eq!(res, Err("token reserved, attributes are frozen"));
+                let res = OrderModule::rmv_token_attr(Origin::signed(bob), token_id, "rarity".as_bytes().to_vec());
+                assert_eq!(res, Err("token reserved, attributes are frozen"));
+                NftsModule::token_unreserve(Origin::signed(alice), token_id);
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, nickname.clone());
+                assert_eq!(res, Ok(()));
+
+                // a token on sale is locked by this module, the owner can not lift the freeze
+                let selector = TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: vec![token_id] }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector, 0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_lock(token_id), Some(ORDER_LOCK_ID));
+                let res = NftsModule::token_unreserve(Origin::signed(alice), token_id);
+                assert_eq!(res, Err("token locked by a module, unreserve now is forbidden"));
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, nickname.clone());
+                assert_eq!(res, Err("token reserved, attributes are frozen"));
+                let order_id = OrderModule::get_token_ask_order(token_id).unwrap();
+                let res = OrderModule::token_sell_order_cancel(Origin::signed(alice), order_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_lock(token_id), None);
+                let res = OrderModule::set_token_attr(Origin::signed(alice), token_id, nickname);
+                assert_eq!(res, Ok(()));
+            });
+        }
+
+        #[test]
+        fn burn_token_cleanup_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                type BalancesModule = balances::Module<Test>;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                let reserved = BalancesModule::reserved_balance(bob);
+                OrderModule::set_token_attr(Origin::signed(bob), tk0, Attributes{
+                    key: "age".as_bytes().to_vec(),
+                    value: Uint64(20),
+                });
+                assert!(BalancesModule::reserved_balance(bob) > reserved);
+
+                let selector = TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: vec![tk0, tk1] }),
+                    nft_type: nft_id,
+                };
+                let asset = 0;
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector, asset, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let order_id = *OrderModule::get_orderbook_ask(&asset).get(&100).unwrap().get(0).unwrap();
+                assert_eq!(OrderModule::get_token_ask_order(tk0), Some(order_id));
+
+                // burning through erc721 unbinds the token and drops its attributes
+                let res = Erc721Module::burn_token(Origin::signed(bob), tk0);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_token_ask_order(tk0), None);
+                assert_eq!(OrderModule::get_ask_token_order(order_id).unwrap().bind_tokens, vec![tk1]);
+                assert_eq!(OrderModule::get_token_attr(tk0).len(), 0);
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(BalancesModule::reserved_balance(bob), reserved - 2);
+
+                // burning the last bound token closes the order
+                let res = NftsModule::burn_token(Origin::signed(bob), tk1, 1);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_ask_token_order(order_id).is_none(), true);
+                assert_eq!(OrderModule::get_orderbook_ask(&asset).get(&100).unwrap().len(), 0);
+            });
+        }
+
+        #[test]
+        fn token_expiry_selector_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec(), 300);
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec(), 120);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_2".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                let tk2 = Erc721Module::token_by_index(2);
+
+                let res = OrderModule::set_token_attr(Origin::signed(bob), tk2, Attributes{
+                    key: EXPIRY_ATTR_KEY.to_vec(),
+                    value: Uint64(500),
+                });
+                assert_eq!(res, Err("built-in attribute key is read only"));
+
+                let mut stack = Vec::<FilterItem>::new();
+                stack.push(Uint8T(LogicOpcode::TokenLogicAnd));
+                stack.push(BoolExp(BooleanExpression {
+                    op: CompareOpcode::TokenCmpGe,
+                    key: EXPIRY_ATTR_KEY.to_vec(),
+                    val : Uint64(150),
+                }));
+                stack.push(BoolExp(BooleanExpression {
+                    op: CompareOpcode::TokenCmpLt,
+                    key: EXPIRY_ATTR_KEY.to_vec(),
+                    val : Uint64(1000),
+                }));
+                let selector = TokenSelector{
+                    selector: AttrSelect(TokenAttrSelector{ max_count: 5, stack }),
+                    nft_type: nft_id,
+                };
+                assert_eq!(OrderModule::_token_match_visitor(bob, &selector), vec![tk0]);
+
+                // expired tokens are never selected
+                Timestamp::set_timestamp(150);
+                let selector = TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: vec![tk0, tk1, tk2] }),
+                    nft_type: nft_id,
+                };
+                let bind_tokens = OrderModule::_token_match_visitor(bob, &selector);
+                assert_eq!(bind_tokens.len(), 2);
+                assert_eq!(bind_tokens.contains(&tk1), false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector, 0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_token_ask_order(tk1), None);
+                assert_eq!(NftsModule::get_token_reserve(tk1), false);
+            });
+        }
+        #[test]
+        fn token_gate_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                OrderModule::set_token_attr(Origin::signed(bob), tk0, Attributes{
+                    key: "level".as_bytes().to_vec(),
+                    value: Uint64(5),
+                });
+
+                let mut stack = Vec::<FilterItem>::new();
+                stack.push(BoolExp(BooleanExpression {
+                    op: CompareOpcode::TokenCmpGe,
+                    key: "level".as_bytes().to_vec(),
+                    val : Uint64(3),
+                }));
+                let selector = TokenSelector{
+                    selector: AttrSelect(TokenAttrSelector{ max_count: 1, stack }),
+                    nft_type: nft_id,
+                };
+                assert_eq!(OrderModule::holds_matching(&bob, &selector), true);
+                assert_eq!(OrderModule::holds_matching(&alice, &selector), false);
+
+                // attribute filters would need a walk over the tokens of the caller, gates only take a whole nft
+                let res = OrderModule::set_call_gate(Origin::ROOT, 5, 1, Some(selector.clone()));
+                assert_eq!(res, Err("gate can only select token ids or a whole nft"));
+                let selector = TokenSelector{
+                    selector: AttrSelect(TokenAttrSelector{ max_count: 1, stack: Vec::new() }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::set_call_gate(Origin::signed(bob), 5, 1, Some(selector.clone()));
+                assert_eq!(res.is_err(), true);
+                let res = OrderModule::set_call_gate(Origin::ROOT, 5, 1, Some(selector.clone()));
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::_check_call_gate(&bob, &[5, 1, 7]), Ok(()));
+                assert_eq!(OrderModule::_check_call_gate(&alice, &[5, 1, 7]), Err("call is gated to token holders"));
+                assert_eq!(OrderModule::_check_call_gate(&alice, &[5, 2, 7]), Ok(()));
+
+                // reserved tokens are still held
+                NftsModule::token_reserve(Origin::signed(bob), tk0);
+                assert_eq!(OrderModule::_token_match_visitor(bob, &selector).len(), 0);
+                assert_eq!(OrderModule::_check_call_gate(&bob, &[5, 1, 7]), Ok(()));
+
+                // id gates only look at the selected ids, so their number is bounded
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::set_call_gate(Origin::ROOT, 5, 2, Some(id_selector(vec![tk0; MAX_GATE_SCAN + 1])));
+                assert_eq!(res, Err("gate selects too many token ids"));
+                let res = OrderModule::set_call_gate(Origin::ROOT, 5, 2, Some(id_selector(vec![H256::repeat_byte(1), tk0])));
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::_check_call_gate(&bob, &[5, 2, 7]), Ok(()));
+                assert_eq!(OrderModule::_check_call_gate(&alice, &[5, 2, 7]), Err("call is gated to token holders"));
+
+                let res = OrderModule::set_call_gate(Origin::ROOT, 5, 1, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::_check_call_gate(&alice, &[5, 1, 7]), Ok(()));
+            });
+        }
+
+
+        #[test]
+        fn token_selector_test() {
+            new_test_ext().execute_with(|| {
+
+                fn print_attributes(attr_value: &TokenAttrValType ){
+                    match &attr_value{
+                        String(x1) => {// Vec<u8>
+                            println!("String as => {}", str::from_utf8(&x1).unwrap());
+                        },
+                        Uint64(x2) => {
+                            println!("integer as => {}", x2);
+                        },
+                    }
+                    
+                };
+                let bob = 0;
+                let bobby = 3;
+                let alice = 1;  
+                let jack = 2;  
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let token_id = Erc721Module::token_by_index(0);
+                let attr1 = Attributes{
+                    key: "attr1".as_bytes().to_vec(),
+                    value: String("attr1_value1".as_bytes().to_vec()),
+                };
+                let attr2 = Attributes{
+                    key: "attr2".as_bytes().to_vec(),
+                    value: Uint64(88),
+                };
+                let attr3 = Attributes{
+                    key: "attr3".as_bytes().to_vec(),
+                    value: Uint64(20),
+                };
+                OrderModule::set_token_attr(Origin::signed(bob),token_id, attr1, );
+                OrderModule::set_token_attr(Origin::signed(bob),token_id, attr2, );
+                OrderModule::set_token_attr(Origin::signed(bob),token_id, attr3, );
+                let mut stack = Vec::<FilterItem>::new();
+                let f1 = BooleanExpression {
+                    op: CompareOpcode::TokenCmpEq,
+                    key: "attr2".as_bytes().to_vec(),
+                    val : String("attr1_value1".as_bytes().to_vec()),
+                };
+                let f2 = BooleanExpression {
+                    op: CompareOpcode::TokenCmpEq,
+                    key: "attr1".as_bytes().to_vec(),
+                    val : Uint64(88),
+                };
+                let f3 = BooleanExpression {
+                    op: CompareOpcode::TokenCmpLt,
+                    key: "attr3".as_bytes().to_vec(),
+                    val : Uint64(22),
+                };
+                let f4 = LogicOpcode::TokenLogicOr;
                 stack.push(Uint8T(f4.clone()));
                 stack.push(BoolExp(f1.clone()));
                 stack.push(BoolExp(f2.clone()));
@@ -1630,6 +3455,12 @@ mod tests {
             println!("----------------- end of ask orderbook -----------------");
             
         }
+        // every reserved unit of an account is owed to one of its open bid orders
+        fn assert_bid_reservations(asset: <Test as generic_asset::Trait>::AssetId, accounts: &[u64]){
+            for who in accounts.iter(){
+                assert_eq!(AssetsModule::reserved_balance(&asset, who), OrderModule::bid_obligations(who, asset));
+            };
+        }
 
         #[test]
         fn order_create_test() {
@@ -1736,17 +3567,17 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@150", );
                 print_orderbook(asset0);
 
-                // let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, false);
+                // let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, TimeInForce::GoodTillCancel, None);
                 // assert_eq!(res, Ok(()) );
                 // println!("-----------------After create buy order form alice for asset0@250", );
                 // print_orderbook(asset0);
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@200", );
                 
@@ -1869,19 +3700,19 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
 
-                // let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, false);
+                // let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, TimeInForce::GoodTillCancel, None);
                 // assert_eq!(res, Ok(()) );
                 // println!("-----------------After create sell order form bob for asset0@200", );
                 
                 // print_orderbook(asset0);
 
                 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@150", );
                 print_orderbook(asset0);
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@250", );
                 print_orderbook(asset0);
@@ -1900,7 +3731,7 @@ mod tests {
                     nft_type: nft1,
                 };
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@200", );
                 
@@ -2013,12 +3844,12 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
                 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@150", );
                 print_orderbook(asset0);
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@250", );
                 print_orderbook(asset0);
@@ -2037,7 +3868,7 @@ mod tests {
                     nft_type: nft1,
                 };
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@200", );
                 
@@ -2151,12 +3982,12 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
                 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@150", );
                 print_orderbook(asset0);
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 250, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@250", );
                 print_orderbook(asset0);
@@ -2175,7 +4006,7 @@ mod tests {
                     nft_type: nft1,
                 };
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, true);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 200, TimeInForce::ImmediateOrCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@200", );
                 
@@ -2295,7 +4126,7 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@100", );// tk1 chosen
                 print_orderbook(asset0);
@@ -2313,7 +4144,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft1,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@100", );// tk2 chosen
                 print_orderbook(asset0);
@@ -2321,7 +4152,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft2,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form alice for asset0@150", );// tk3 chosen
                 print_orderbook(asset0);
@@ -2341,7 +4172,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft1,
                 };
-                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form jack for asset0@100", );
                 print_orderbook(asset0);
@@ -2354,7 +4185,7 @@ mod tests {
                     nft_type: nft2,
                 };
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@150", );
                 print_orderbook(asset0);
@@ -2475,7 +4306,7 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@100", );// tk1 chosen
                 print_orderbook(asset0);
@@ -2495,7 +4326,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft1,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@100", );// tk2 chosen
                 print_orderbook(asset0);
@@ -2503,7 +4334,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft2,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form alice for asset0@150", );// tk3 chosen
                 print_orderbook(asset0);
@@ -2525,7 +4356,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft1,
                 };
-                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form jack for asset0@100", );
                 print_orderbook(asset0);
@@ -2541,7 +4372,7 @@ mod tests {
                     nft_type: nft2,
                 };
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@150", );
                 print_orderbook(asset0);
@@ -2662,7 +4493,7 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@100", );// tk1 chosen
                 print_orderbook(asset0);
@@ -2682,7 +4513,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft1,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 60, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 60, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@60", );// tk2 chosen
                 print_orderbook(asset0);
@@ -2690,7 +4521,7 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft2,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, true);
+                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::ImmediateOrCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form alice for asset0@150", );// tk3 chosen
                 print_orderbook(asset0);
@@ -2712,11 +4543,11 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft1,
                 };
-                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 70, true);
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 70, TimeInForce::ImmediateOrCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form jack for asset0@70", );
                 print_orderbook(asset0);
-                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 110, true);
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), selector.clone(), asset0, 110, TimeInForce::ImmediateOrCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form jack for asset0@110", );
                 print_orderbook(asset0);
@@ -2732,7 +4563,7 @@ mod tests {
                     nft_type: nft2,
                 };
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form alice for asset0@150", );
                 print_orderbook(asset0);
@@ -2853,7 +4684,7 @@ mod tests {
                 let res = AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500);
                 assert_eq!(res, Ok(()) );
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@100", );// tk1 chosen
                 print_orderbook(asset0);
@@ -2873,14 +4704,14 @@ mod tests {
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft1,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 60, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector.clone(), asset0, 60, TimeInForce::GoodTillCancel, None);
                 println!("-----------------After create sell order form bob for asset0@60", );// tk2 chosen
                 print_orderbook(asset0);
                 let selector = TokenSelector{
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft2,
                 };
-                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, true);
+                let res = OrderModule::token_sell_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::ImmediateOrCancel, None);
                 println!("-----------------After create sell order form alice for asset0@150", );// tk3 chosen
                 print_orderbook(asset0);
 
@@ -3017,7 +4848,7 @@ mod tests {
                 
 
 
-                let res = OrderModule::token_buy_order_create(Origin::signed(bob), selector.clone(), asset0, 100, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(bob), selector.clone(), asset0, 100, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create buy order form bob for asset0@100", );// tk1 chosen
                 print_orderbook(asset0);
@@ -3038,14 +4869,14 @@ mod tests {
                     nft_type: nft1,
                 };
                 let selector_nft1 = selector.clone();
-                let res = OrderModule::token_buy_order_create(Origin::signed(bob), selector.clone(), asset0, 60, false);
+                let res = OrderModule::token_buy_order_create(Origin::signed(bob), selector.clone(), asset0, 60, TimeInForce::GoodTillCancel, None);
                 println!("-----------------After create buy order form bob for asset0@60", );// tk2 chosen
                 print_orderbook(asset0);
                 let selector = TokenSelector{
                     selector: AttrSelect(attr_selector.clone()),
                     nft_type: nft2,
                 };
-                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, true);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), selector.clone(), asset0, 150, TimeInForce::ImmediateOrCancel, None);
                 println!("-----------------After create buy order form alice for asset0@150", );// tk3 chosen
                 print_orderbook(asset0);
                 
@@ -3055,7 +4886,7 @@ mod tests {
                 let order3 = ordermap.get(&150);
                 assert_eq!(order3, None);
 
-                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector_nft1.clone(), asset0, 50, false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), selector_nft1.clone(), asset0, 50, TimeInForce::GoodTillCancel, None);
                 assert_eq!(res, Ok(()) );
                 println!("-----------------After create sell order form bob for asset0@50", );// tk1 chosen
                 print_orderbook(asset0);
@@ -3075,4 +4906,1021 @@ mod tests {
             });
         }
         
+        #[test]
+        fn english_auction_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500).unwrap();
+
+                let res = OrderModule::english_auction_create(Origin::signed(bob), tk0, asset0, 100, 10, 50);
+                assert_eq!(res, Err("auction end must be in the future"));
+                let res = OrderModule::english_auction_create(Origin::signed(bob), tk0, asset0, 100, 10, 200);
+                assert_eq!(res, Ok(()));
+                let auction_id = OrderModule::get_token_auction(tk0).unwrap();
+                assert_eq!(NftsModule::get_token_reserve(tk0), true);
+                // the seller can not take the token back out of the auction
+                assert_eq!(Erc721Module::owner_of(tk0), Some(OrderModule::account_id()));
+                let res = NftsModule::token_unreserve(Origin::signed(bob), tk0);
+                assert_eq!(res, Err("not authrized as not the issuer of this token"));
+                let res = Erc721Module::transfer_from(Origin::signed(bob), bob, jack, tk0);
+                assert_eq!(res, Err("You do not own this token"));
+
+                let res = OrderModule::english_auction_bid(Origin::signed(bob), auction_id, 100);
+                assert_eq!(res, Err("seller can not bid on own auction"));
+                let res = OrderModule::english_auction_bid(Origin::signed(alice), auction_id, 90);
+                assert_eq!(res, Err("bid lower than the minimum bid"));
+                let res = OrderModule::english_auction_bid(Origin::signed(alice), auction_id, 100);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 100);
+
+                // outbidding needs the minimum increment and releases the previous bid
+                let res = OrderModule::english_auction_bid(Origin::signed(jack), auction_id, 105);
+                assert_eq!(res, Err("bid lower than the minimum bid"));
+                let res = OrderModule::english_auction_bid(Origin::signed(jack), auction_id, 110);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &jack), 110);
+                let res = OrderModule::english_auction_cancel(Origin::signed(bob), auction_id);
+                assert_eq!(res, Err("auction with bids can not be canceled"));
+
+                Timestamp::set_timestamp(150);
+                let res = OrderModule::english_auction_settle(Origin::signed(jack), auction_id);
+                assert_eq!(res, Err("auction not ended yet"));
+
+                // a late bid pushes the end out
+                Timestamp::set_timestamp(195);
+                let res = OrderModule::english_auction_bid(Origin::signed(alice), auction_id, 130);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_english_auction(auction_id).unwrap().end, 205);
+                Timestamp::set_timestamp(200);
+                let res = OrderModule::english_auction_settle(Origin::signed(jack), auction_id);
+                assert_eq!(res, Err("auction not ended yet"));
+
+                Timestamp::set_timestamp(205);
+                let res = OrderModule::english_auction_bid(Origin::signed(jack), auction_id, 150);
+                assert_eq!(res, Err("auction already ended"));
+                let res = OrderModule::english_auction_settle(Origin::signed(jack), auction_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 130);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500 - 130);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &jack), 1500);
+                assert_eq!(OrderModule::get_english_auction(auction_id).is_none(), true);
+                assert_eq!(OrderModule::get_token_auction(tk0), None);
+            });
+        }
+
+        #[test]
+        fn english_auction_no_bid_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+
+                let res = OrderModule::english_auction_create(Origin::signed(bob), tk0, 0, 100, 10, 200);
+                assert_eq!(res, Ok(()));
+                let auction_id = OrderModule::get_token_auction(tk0).unwrap();
+                let res = OrderModule::english_auction_cancel(Origin::signed(alice), auction_id);
+                assert_eq!(res, Err("seller dismatch, seller not own this auction"));
+                let res = OrderModule::english_auction_cancel(Origin::signed(bob), auction_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(OrderModule::get_token_auction(tk0), None);
+
+                // an auction ending without bids gives the token back
+                Timestamp::set_timestamp(110);
+                let res = OrderModule::english_auction_create(Origin::signed(bob), tk0, 0, 100, 10, 200);
+                assert_eq!(res, Ok(()));
+                let auction_id = OrderModule::get_token_auction(tk0).unwrap();
+                Timestamp::set_timestamp(200);
+                let res = OrderModule::english_auction_settle(Origin::signed(alice), auction_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(bob));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(OrderModule::get_english_auction(auction_id).is_none(), true);
+            });
+        }
+
+        #[test]
+        fn english_auction_undeliverable_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec(), 250).unwrap();
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                OrderModule::english_auction_create(Origin::signed(bob), tk0, asset0, 100, 10, 200).unwrap();
+                let auction0 = OrderModule::get_token_auction(tk0).unwrap();
+                OrderModule::english_auction_create(Origin::signed(bob), tk1, asset0, 100, 10, 300).unwrap();
+                let auction1 = OrderModule::get_token_auction(tk1).unwrap();
+                OrderModule::english_auction_bid(Origin::signed(alice), auction0, 100).unwrap();
+                OrderModule::english_auction_bid(Origin::signed(alice), auction1, 100).unwrap();
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 200);
+
+                // the winner got blacklisted under the nft, the bid goes back and the token to the seller
+                let option = nfts::NonfungibleOption{
+                    permissions: vec![nfts::Permission{ perm_type: nfts::PermissionType::Black, account: alice }],
+                    max_supply: 10,
+                    description: Vec::new(),
+                    issuer_burnable: false,
+                };
+                NftsModule::nonfungible_update(Origin::signed(bob), None, Some(option), nft_id).unwrap();
+                Timestamp::set_timestamp(200);
+                let res = OrderModule::english_auction_settle(Origin::signed(alice), auction0);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(bob));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 100);
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 0);
+                assert_eq!(OrderModule::get_token_auction(tk0), None);
+
+                // a token expired during the auction is reaped, the bid goes back
+                Timestamp::set_timestamp(300);
+                let res = OrderModule::english_auction_settle(Origin::signed(alice), auction1);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk1), None);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500);
+                assert_eq!(OrderModule::get_token_auction(tk0), None);
+            });
+        }
+
+        #[test]
+        fn dutch_listing_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                let res = OrderModule::dutch_listing_create(Origin::signed(bob), tk0, asset0, 100, 200, 200, PriceCurve::Linear);
+                assert_eq!(res, Err("floor price above start price"));
+                let res = OrderModule::dutch_listing_create(Origin::signed(bob), tk0, asset0, 1000, 200, 200, PriceCurve::Linear);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::dutch_listing_create(Origin::signed(bob), tk1, asset0, 1000, 200, 200, PriceCurve::Stepwise(4));
+                assert_eq!(res, Ok(()));
+                let linear_id = OrderModule::get_token_ask_order(tk0).unwrap();
+                let stepwise_id = OrderModule::get_token_ask_order(tk1).unwrap();
+                assert_eq!(NftsModule::get_token_reserve(tk0), true);
+                // dutch listings stay out of the orderbook
+                assert_eq!(OrderModule::get_orderbook_ask(&asset0).len(), 0);
+                let res = OrderModule::token_sell_order_cancel(Origin::signed(bob), linear_id);
+                assert_eq!(res, Err("dutch listings are canceled by dutch_listing_cancel"));
+
+                let linear = OrderModule::get_dutch_listing(linear_id).unwrap();
+                let stepwise = OrderModule::get_dutch_listing(stepwise_id).unwrap();
+                assert_eq!(OrderModule::dutch_price(&linear, 100), 1000);
+                assert_eq!(OrderModule::dutch_price(&linear, 130), 760);
+                assert_eq!(OrderModule::dutch_price(&linear, 150), 600);
+                assert_eq!(OrderModule::dutch_price(&linear, 300), 200);
+                assert_eq!(OrderModule::dutch_price(&stepwise, 124), 1000);
+                assert_eq!(OrderModule::dutch_price(&stepwise, 130), 800);
+                assert_eq!(OrderModule::dutch_price(&stepwise, 175), 400);
+                assert_eq!(OrderModule::dutch_price(&stepwise, 200), 200);
+
+                // the first buyer pays the current price
+                Timestamp::set_timestamp(150);
+                let res = OrderModule::dutch_buy(Origin::signed(bob), linear_id, 1000);
+                assert_eq!(res, Err("seller can not buy own listing"));
+                let res = OrderModule::dutch_buy(Origin::signed(alice), linear_id, 500);
+                assert_eq!(res, Err("current price above max price"));
+                // a buy the fill would refuse reserves nothing
+                let jack = 2;
+                let res = OrderModule::dutch_buy(Origin::signed(jack), linear_id, 650);
+                assert_eq!(res, Err("free balance too low to pay the price"));
+                let option = |perm_type| nfts::NonfungibleOption{
+                    permissions: vec![nfts::Permission{ perm_type, account: alice }],
+                    max_supply: 10,
+                    description: Vec::new(),
+                    issuer_burnable: false,
+                };
+                NftsModule::nonfungible_update(Origin::signed(bob), None, Some(option(nfts::PermissionType::Black)), nft_id).unwrap();
+                let res = OrderModule::dutch_buy(Origin::signed(alice), linear_id, 650);
+                assert_eq!(res, Err("nft blacklist contains to-account"));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(OrderModule::get_dutch_listing(linear_id).is_some(), true);
+                NftsModule::nonfungible_update(Origin::signed(bob), None, Some(option(nfts::PermissionType::White)), nft_id).unwrap();
+                let res = OrderModule::dutch_buy(Origin::signed(alice), linear_id, 650);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500 - 600);
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 600);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(OrderModule::get_ask_token_order(linear_id).is_none(), true);
+                assert_eq!(OrderModule::get_dutch_listing(linear_id), None);
+                assert_eq!(OrderModule::get_token_ask_order(tk0), None);
+                let res = OrderModule::dutch_buy(Origin::signed(alice), linear_id, 650);
+                assert_eq!(res, Err("dutch listing not found"));
+
+                let res = OrderModule::dutch_listing_cancel(Origin::signed(alice), stepwise_id);
+                assert_eq!(res, Err("creator dismatch, creator not own this order"));
+                let res = OrderModule::dutch_listing_cancel(Origin::signed(bob), stepwise_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(tk1), false);
+                assert_eq!(OrderModule::get_token_ask_order(tk1), None);
+                assert_eq!(OrderModule::get_dutch_listing(stepwise_id), None);
+            });
+        }
+
+        #[test]
+        fn sealed_auction_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                let bobby = 3;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                for who in [alice, jack, bobby].iter() {
+                    AssetsModule::transfer(Origin::signed(asset_issuer), asset0, *who, 1500).unwrap();
+                };
+
+                let res = OrderModule::sealed_auction_create(Origin::signed(bob), tk0, asset0, 100, 50, 200, 200);
+                assert_eq!(res, Err("reveal end must be after commit end"));
+                let res = OrderModule::sealed_auction_create(Origin::signed(bob), tk0, asset0, 100, 50, 200, 300);
+                assert_eq!(res, Ok(()));
+                let auction_id = (bob, tk0, 100u64, b"sealed").using_encoded(BlakeTwo256::hash);
+                assert_eq!(OrderModule::get_sealed_auction(auction_id).is_some(), true);
+                assert_eq!(Erc721Module::owner_of(tk0), Some(OrderModule::account_id()));
+                assert_eq!(NftsModule::get_token_reserve(tk0), true);
+
+                let salt = "salt".as_bytes().to_vec();
+                let res = OrderModule::sealed_bid_commit(Origin::signed(bob), auction_id, H256::default());
+                assert_eq!(res, Err("seller can not bid on own auction"));
+                for (who, amount) in [(alice, 300), (jack, 200), (bobby, 400)].iter() {
+                    let commitment = OrderModule::sealed_bid_commitment(&auction_id, who, *amount, &salt);
+                    let res = OrderModule::sealed_bid_commit(Origin::signed(*who), auction_id, commitment);
+                    assert_eq!(res, Ok(()));
+                };
+                let res = OrderModule::sealed_bid_commit(Origin::signed(alice), auction_id, H256::default());
+                assert_eq!(res, Err("bid already committed"));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 50);
+                let res = OrderModule::sealed_bid_reveal(Origin::signed(alice), auction_id, 300, salt.clone());
+                assert_eq!(res, Err("reveal phase not started yet"));
+
+                Timestamp::set_timestamp(200);
+                let res = OrderModule::sealed_bid_reveal(Origin::signed(alice), auction_id, 300, "pepper".as_bytes().to_vec());
+                assert_eq!(res, Err("bid does not match the commitment"));
+                let res = OrderModule::sealed_bid_reveal(Origin::signed(alice), auction_id, 300, salt.clone());
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 300);
+                let res = OrderModule::sealed_bid_reveal(Origin::signed(jack), auction_id, 200, salt.clone());
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &jack), 0);
+                assert_eq!(OrderModule::get_sealed_auction(auction_id).unwrap().second_price, 200);
+
+                Timestamp::set_timestamp(250);
+                let res = OrderModule::sealed_auction_settle(Origin::signed(jack), auction_id);
+                assert_eq!(res, Err("reveal phase not ended yet"));
+
+                // bobby never reveals, the winner pays the second price
+                Timestamp::set_timestamp(300);
+                let res = OrderModule::sealed_bid_reveal(Origin::signed(bobby), auction_id, 400, salt.clone());
+                assert_eq!(res, Err("reveal phase already ended"));
+                // a settlement that can not complete writes nothing
+                OrderModule::_unreserve_asset(bobby, asset0, 50).unwrap();
+                let res = OrderModule::sealed_auction_settle(Origin::signed(jack), auction_id);
+                assert_eq!(res, Err("bid deposit no longer reserved"));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(OrderModule::account_id()));
+                assert_eq!(OrderModule::get_sealed_bidders(auction_id).len(), 3);
+                assert_eq!(OrderModule::get_sealed_commit((auction_id, bobby)).is_some(), true);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 300);
+                OrderModule::_reserve_asset(bobby, asset0, 50).unwrap();
+                let res = OrderModule::sealed_auction_settle(Origin::signed(jack), auction_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500 - 200);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &jack), 1500);
+                assert_eq!(AssetsModule::free_balance(&asset0, &bobby), 1500 - 50);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &bobby), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 200 + 50);
+                assert_eq!(OrderModule::get_sealed_auction(auction_id).is_none(), true);
+                assert_eq!(OrderModule::get_sealed_bidders(auction_id).len(), 0);
+            });
+        }
+
+        #[test]
+        fn order_expiry_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0]), asset0, 100, TimeInForce::GoodTillCancel, Some(100));
+                assert_eq!(res, Err("order expiry must be in the future"));
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0]), asset0, 100, TimeInForce::GoodTillCancel, Some(100 + MAX_ORDER_LIFETIME + 1));
+                assert_eq!(res, Err("order expiry too far in the future"));
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0]), asset0, 100, TimeInForce::GoodTillCancel, Some(150));
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk1]), asset0, 110, TimeInForce::GoodTillCancel, Some(150));
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 50, TimeInForce::GoodTillCancel, Some(1500));
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 50);
+                assert_eq!(OrderModule::get_expiry_queue(1000).len(), 2);
+                assert_eq!(OrderModule::get_expiry_queue(2000).len(), 1);
+
+                // a canceled order leaves the queue
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk1]), asset0, 40, TimeInForce::GoodTillCancel, Some(1500));
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_expiry_queue(2000).len(), 2);
+                let bid_id = *OrderModule::get_orderbook_bid(&asset0).get(&40).unwrap().get(0).unwrap();
+                OrderModule::token_buy_order_cancel(Origin::signed(alice), bid_id).unwrap();
+                assert_eq!(OrderModule::get_expiry_queue(2000).len(), 1);
+                assert_eq!(OrderModule::get_expiry_count(), 3);
+
+                // expired orders are skipped before they are reaped
+                Timestamp::set_timestamp(150);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 120, TimeInForce::ImmediateOrCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(bob));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 50);
+                // their bucket is reaped once it ends
+                <OrderModule as sr_primitives::traits::OnFinalize<u64>>::on_finalize(1);
+                assert_eq!(OrderModule::get_expiry_queue(1000).len(), 2);
+                assert_eq!(OrderModule::get_expiry_cursor(), 1000);
+
+                // reaping is bounded, the rest waits for the next block
+                Timestamp::set_timestamp(1000);
+                OrderModule::_reap_expired_orders(1);
+                assert_eq!(OrderModule::get_expiry_queue(1000).len(), 1);
+                <OrderModule as sr_primitives::traits::OnFinalize<u64>>::on_finalize(2);
+                assert_eq!(OrderModule::get_expiry_queue(1000).len(), 0);
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(NftsModule::get_token_reserve(tk1), false);
+                assert_eq!(OrderModule::get_token_ask_order(tk0), None);
+                assert_eq!(OrderModule::get_orderbook_ask(&asset0).get(&100).unwrap().len(), 0);
+                assert_eq!(OrderModule::get_orderbook_ask(&asset0).get(&110).unwrap().len(), 0);
+                assert_eq!(OrderModule::get_orderbook_bid(&asset0).get(&50).unwrap().len(), 1);
+
+                Timestamp::set_timestamp(2000);
+                <OrderModule as sr_primitives::traits::OnFinalize<u64>>::on_finalize(3);
+                assert_eq!(OrderModule::get_orderbook_bid(&asset0).get(&50).unwrap().len(), 0);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500);
+                assert_eq!(OrderModule::get_expiry_queue(2000).len(), 0);
+                assert_eq!(OrderModule::get_expiry_count(), 0);
+            });
+        }
+
+        #[test]
+        fn time_in_force_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+
+                // rejected orders leave nothing behind
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0, tk1]), asset0, 150, TimeInForce::FillOrKill, None);
+                assert_eq!(res, Err("fill-or-kill order can not be filled completely"));
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 150, TimeInForce::PostOnly, None);
+                assert_eq!(res, Err("post-only order would match on entry"));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(bob));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0, tk1]), asset0, 90, TimeInForce::PostOnly, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 180);
+                assert_eq!(OrderModule::get_orderbook_bid(&asset0).get(&90).unwrap().len(), 1);
+
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk1]), asset0, 80, TimeInForce::PostOnly, None);
+                assert_eq!(res, Err("post-only order would match on entry"));
+                assert_eq!(NftsModule::get_token_reserve(tk1), false);
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk1]), asset0, 80, TimeInForce::FillOrKill, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk1), Some(alice));
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 90);
+
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 150, TimeInForce::FillOrKill, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 190);
+            });
+        }
+
+        #[test]
+        fn fill_or_kill_fill_check_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                let fee_account = 3;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 100).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+
+                // the buyer can not pay the taker fee on top of the price
+                OrderModule::set_fee_account(Origin::ROOT, Some(fee_account)).unwrap();
+                OrderModule::set_fee_schedule(Origin::ROOT, asset0, 50, 50).unwrap();
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), id_selector(vec![tk0]), asset0, 100, TimeInForce::FillOrKill, None);
+                assert_eq!(res, Err("fill-or-kill order can not be filled completely"));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &jack), 0);
+
+                // a blacklisted buyer can not receive the token
+                let option = nfts::NonfungibleOption{
+                    permissions: vec![nfts::Permission{ perm_type: nfts::PermissionType::Black, account: alice }],
+                    max_supply: 10,
+                    description: Vec::new(),
+                    issuer_burnable: false,
+                };
+                NftsModule::nonfungible_update(Origin::signed(bob), None, Some(option), nft_id).unwrap();
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 100, TimeInForce::FillOrKill, None);
+                assert_eq!(res, Err("fill-or-kill order can not be filled completely"));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(bob));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+
+                // the seller side checks every resting buyer the same way
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), id_selector(vec![tk1]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk1]), asset0, 100, TimeInForce::FillOrKill, None);
+                assert_eq!(res, Err("fill-or-kill order can not be filled completely"));
+                assert_eq!(NftsModule::get_token_reserve(tk1), false);
+
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 10).unwrap();
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk1]), asset0, 100, TimeInForce::FillOrKill, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk1), Some(jack));
+            });
+        }
+
+        #[test]
+        fn failed_fill_keeps_match_position_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                NftsModule::nonfungible_create(Origin::signed(bob), "kitty".as_bytes().to_vec(), 10);
+                let nft_x = NftsModule::get_nft_by_index(0);
+                let nft_y = NftsModule::get_nft_by_index(1);
+                // bob's tokens are visited newest first, so the x token of each pair is matched first
+                NftsModule::issue_token(Origin::signed(bob), nft_y, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_x, "token_1".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_y, "token_2".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_x, "token_3".as_bytes().to_vec());
+                let y0 = Erc721Module::token_by_index(0);
+                let x0 = Erc721Module::token_by_index(1);
+                let y1 = Erc721Module::token_by_index(2);
+                let x1 = Erc721Module::token_by_index(3);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                // alice can not receive tokens of the x nft
+                let option = nfts::NonfungibleOption{
+                    permissions: vec![nfts::Permission{ perm_type: nfts::PermissionType::Black, account: alice }],
+                    max_supply: 10,
+                    description: Vec::new(),
+                    issuer_burnable: false,
+                };
+                NftsModule::nonfungible_update(Origin::signed(bob), None, Some(option), nft_x).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_x,
+                };
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![x0, y0, x1, y1]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+
+                // the fill of x1 fails, an immediate-or-cancel ask still sells y1 and hands x1 back unlocked
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![x1, y1]), asset0, 100, TimeInForce::ImmediateOrCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(y1), Some(alice));
+                assert_eq!(Erc721Module::owner_of(x1), Some(bob));
+                assert_eq!(NftsModule::get_token_reserve(x1), false);
+                assert_eq!(NftsModule::get_token_lock(x1), None);
+
+                // a resting ask sells y0 the same way and only x0 rests in the ask
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![x0, y0]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(y0), Some(alice));
+                assert_eq!(OrderModule::get_token_ask_order(y0), None);
+                assert_eq!(Erc721Module::owner_of(x0), Some(bob));
+                assert_eq!(OrderModule::get_token_ask_order(x0).is_some(), true);
+                assert_eq!(NftsModule::get_token_lock(x0), Some(ORDER_LOCK_ID));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 200);
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 200);
+            });
+        }
+
+        #[test]
+        fn order_cancel_release_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_2".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                let tk2 = Erc721Module::token_by_index(2);
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0, tk1]), 0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(tk0), true);
+                let order_id = OrderModule::get_token_ask_order(tk0).unwrap();
+                let res = OrderModule::token_sell_order_cancel(Origin::signed(bob), order_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(tk0), false);
+                assert_eq!(NftsModule::get_token_reserve(tk1), false);
+                let res = Erc721Module::transfer_from(Origin::signed(bob), bob, alice, tk0);
+                assert_eq!(res, Ok(()));
+
+                // a token stranded by an old cancel, next to a token still on sale
+                NftsModule::_token_lock(bob, tk1, ORDER_LOCK_ID).unwrap();
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk2]), 0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::unlock_stranded_tokens(Origin::signed(bob), vec![tk1]);
+                assert_eq!(res, Err("RequireRootOrigin"));
+                let res = OrderModule::unlock_stranded_tokens(Origin::ROOT, vec![tk1, tk2]);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_reserve(tk1), false);
+                assert_eq!(NftsModule::get_token_lock(tk1), None);
+                assert_eq!(NftsModule::get_token_reserve(tk2), true);
+                assert!(OrderModule::get_token_ask_order(tk2).is_some());
+
+                // tokens locked by another module or reserved by their owner are left alone
+                NftsModule::_token_lock(bob, tk1, *b"nft/lend").unwrap();
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_3".as_bytes().to_vec());
+                let tk3 = Erc721Module::token_by_index(3);
+                NftsModule::token_reserve(Origin::signed(bob), tk3);
+                let res = OrderModule::unlock_stranded_tokens(Origin::ROOT, vec![tk1, tk3]);
+                assert_eq!(res, Ok(()));
+                assert_eq!(NftsModule::get_token_lock(tk1), Some(*b"nft/lend"));
+                assert_eq!(NftsModule::get_token_reserve(tk1), true);
+                assert_eq!(NftsModule::get_token_reserve(tk3), true);
+            });
+        }
+
+        #[test]
+        fn bid_reservation_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_2".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                let tk2 = Erc721Module::token_by_index(2);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 1500).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0]), asset0, 60, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+
+                // a bid filled below its price keeps only what the rest of the order needs
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0, tk1, tk2]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 200);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500 - 260);
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), id_selector(vec![tk1]), asset0, 80, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_bid_reservations(asset0, &[bob, alice, jack]);
+
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk1]), asset0, 90, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk1), Some(alice));
+                assert_bid_reservations(asset0, &[bob, alice, jack]);
+
+                // cancels give back everything still reserved
+                let alice_order = *OrderModule::get_orderbook_bid(&asset0).get(&100).unwrap().get(0).unwrap();
+                let jack_order = *OrderModule::get_orderbook_bid(&asset0).get(&80).unwrap().get(0).unwrap();
+                let res = OrderModule::token_buy_order_cancel(Origin::signed(alice), alice_order);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_buy_order_cancel(Origin::signed(jack), jack_order);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500 - 160);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &jack), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &jack), 1500);
+                assert_bid_reservations(asset0, &[bob, alice, jack]);
+            });
+        }
+
+        #[test]
+        fn account_orders_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_2".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+                let tk2 = Erc721Module::token_by_index(2);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0, tk1]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::dutch_listing_create(Origin::signed(bob), tk2, asset0, 300, 100, 1000, PriceCurve::Linear);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk1, tk2]), asset0, 50, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_owned_ask_orders(bob).len(), 2);
+                assert_eq!(OrderModule::get_owned_bid_orders(alice).len(), 1);
+
+                let orders = OrderModule::account_orders(&bob);
+                assert_eq!(orders.len(), 2);
+                assert_eq!(orders[0].is_bid, false);
+                assert_eq!(orders[0].price, 100);
+                assert_eq!(orders[0].remaining, 2);
+                assert_eq!(orders[0].bind_tokens, vec![tk0, tk1]);
+                let orders = OrderModule::account_orders(&alice);
+                assert_eq!(orders.len(), 1);
+                assert_eq!(orders[0].is_bid, true);
+                assert_eq!(orders[0].remaining, 2);
+                assert_eq!(orders[0].status, OrderStatus::Open);
+
+                // partial fills update the details, complete fills and cancels drop the order
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 100, TimeInForce::ImmediateOrCancel, None);
+                assert_eq!(res, Ok(()));
+                let orders = OrderModule::account_orders(&bob);
+                assert_eq!(orders[0].remaining, 1);
+                assert_eq!(orders[0].status, OrderStatus::PartialFilled);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk1]), asset0, 100, TimeInForce::ImmediateOrCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::account_orders(&bob).len(), 1);
+                let listing_id = OrderModule::get_token_ask_order(tk2).unwrap();
+                let res = OrderModule::dutch_listing_cancel(Origin::signed(bob), listing_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::account_orders(&bob).len(), 0);
+
+                let bid_id = *OrderModule::get_owned_bid_orders(alice).get(0).unwrap();
+                let res = OrderModule::token_buy_order_cancel(Origin::signed(alice), bid_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_owned_bid_orders(alice).len(), 0);
+                assert_eq!(OrderModule::account_orders(&alice).len(), 0);
+            });
+        }
+
+        #[test]
+        fn trade_history_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                type Timestamp = timestamp::Module<Test>;
+                Timestamp::set_timestamp(100);
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0, tk1]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let ask_id = OrderModule::get_token_ask_order(tk0).unwrap();
+                Timestamp::set_timestamp(120);
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 120, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+
+                // the taker closes filled at once, the maker keeps its last token on sale
+                let closed = OrderModule::get_closed_orders(alice);
+                assert_eq!(closed.len(), 1);
+                assert_eq!(closed[0].is_bid, true);
+                assert_eq!(closed[0].remaining, 0);
+                assert_eq!(closed[0].status, OrderStatus::Filled);
+                let bid_id = closed[0].order_id;
+                assert_eq!(OrderModule::get_closed_orders(bob).len(), 0);
+
+                assert_eq!(OrderModule::get_trade_count(), 1);
+                let trades = OrderModule::trade_records(0, 10);
+                assert_eq!(trades.len(), 1);
+                assert_eq!(trades[0].seq, 0);
+                assert_eq!(trades[0].ask_order_id, ask_id);
+                assert_eq!(trades[0].bid_order_id, bid_id);
+                assert_eq!(trades[0].seller, bob);
+                assert_eq!(trades[0].buyer, alice);
+                assert_eq!(trades[0].token_id, tk0);
+                assert_eq!(trades[0].price, 100);
+                assert_eq!(trades[0].timepoint, 120);
+                assert_eq!(OrderModule::trade_records(1, 10).len(), 0);
+
+                Timestamp::set_timestamp(150);
+                let res = OrderModule::token_sell_order_cancel(Origin::signed(bob), ask_id);
+                assert_eq!(res, Ok(()));
+                let closed = OrderModule::get_closed_orders(bob);
+                assert_eq!(closed.len(), 1);
+                assert_eq!(closed[0].order_id, ask_id);
+                assert_eq!(closed[0].remaining, 1);
+                assert_eq!(closed[0].status, OrderStatus::Canceled);
+                assert_eq!(closed[0].closed_at, 150);
+            });
+        }
+
+        #[test]
+        fn trading_fee_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let fee_account = 9;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+
+                let res = OrderModule::set_fee_schedule(Origin::signed(bob), asset0, 10, 20);
+                assert!(res.is_err());
+                let res = OrderModule::set_fee_account(Origin::signed(bob), Some(bob));
+                assert!(res.is_err());
+                let res = OrderModule::set_fee_schedule(Origin::ROOT, asset0, 10, 200);
+                assert_eq!(res, Err("fee above the highest fee"));
+                let res = OrderModule::set_fee_schedule(Origin::ROOT, asset0, 10, 20);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::set_fee_account(Origin::ROOT, Some(fee_account));
+                assert_eq!(res, Ok(()));
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                // the resting ask makes bob the maker
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 100, TimeInForce::ImmediateOrCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 100 - 1);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500 - 100 - 2);
+                assert_eq!(AssetsModule::free_balance(&asset0, &fee_account), 3);
+
+                // the resting bid makes alice the maker
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk1]), asset0, 200, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk1]), asset0, 200, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk1), Some(alice));
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 99 + 200 - 4);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1398 - 200 - 2);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &fee_account), 3 + 6);
+
+                // clearing the fee account stops charging fees
+                let res = OrderModule::set_fee_account(Origin::ROOT, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(OrderModule::get_fee_account(), None);
+                assert_eq!(OrderModule::_fill_fees(asset0, 200, false), (0, 0));
+            });
+        }
+
+        #[test]
+        fn trading_fee_failed_fill_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                let fee_account = 9;
+                NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+                let nft_id = NftsModule::get_nft_by_index(0);
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+                NftsModule::issue_token(Origin::signed(bob), nft_id, "token_1".as_bytes().to_vec());
+                let tk0 = Erc721Module::token_by_index(0);
+                let tk1 = Erc721Module::token_by_index(1);
+
+                let asset_issuer = 100;
+                use generic_asset::{AssetOptions, PermissionsV1};
+                let asset_option = AssetOptions{
+                    initial_issuance: 1000000,
+                    permissions: PermissionsV1::default(),
+                };
+                let asset0 = AssetsModule::next_asset_id();
+                AssetsModule::create_asset(None, Some(asset_issuer), asset_option.clone()).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, alice, 1500).unwrap();
+                AssetsModule::transfer(Origin::signed(asset_issuer), asset0, jack, 200).unwrap();
+                OrderModule::set_fee_account(Origin::ROOT, Some(fee_account)).unwrap();
+                OrderModule::set_fee_schedule(Origin::ROOT, asset0, 50, 50).unwrap();
+
+                let id_selector = |tokens: Vec<H256>| TokenSelector{
+                    selector: IdSelect(TokenIdSelector{ id_set: tokens }),
+                    nft_type: nft_id,
+                };
+                // jack reserves all it has and can not pay the maker fee
+                let res = OrderModule::token_buy_order_create(Origin::signed(jack), id_selector(vec![tk1]), asset0, 200, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                let res = OrderModule::token_buy_order_create(Origin::signed(alice), id_selector(vec![tk0]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+
+                // tk1 is matched first against jack and fails, tk0 still fills against alice
+                let res = OrderModule::token_sell_order_create(Origin::signed(bob), id_selector(vec![tk0, tk1]), asset0, 100, TimeInForce::GoodTillCancel, None);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(tk0), Some(alice));
+                assert_eq!(OrderModule::get_token_ask_order(tk0), None);
+                assert_eq!(Erc721Module::owner_of(tk1), Some(bob));
+                assert_eq!(OrderModule::get_token_ask_order(tk1).is_some(), true);
+                assert_eq!(NftsModule::get_token_lock(tk1), Some(ORDER_LOCK_ID));
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &jack), 200);
+                assert_eq!(AssetsModule::reserved_balance(&asset0, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&asset0, &alice), 1500 - 100 - 5);
+                assert_eq!(AssetsModule::free_balance(&asset0, &bob), 100 - 5);
+                assert_eq!(AssetsModule::free_balance(&asset0, &fee_account), 10);
+            });
+        }
 }
\ No newline at end of file
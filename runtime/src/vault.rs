@@ -0,0 +1,638 @@
+// Fractionalization vault: lock a token and split it into fungible shares of a new generic asset.
+// Whoever holds every share can take the token out again, or the token can be sold through a
+// buyout auction whose proceeds are shared among the share holders pro rata.
+
+use codec::{Encode, Decode};
+use system::ensure_signed;
+use sr_primitives::traits::{Hash, SaturatedConversion, Zero, AccountIdConversion};
+use sr_primitives::ModuleId;
+use rstd::prelude::*;
+use support::{
+    decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue,
+    traits::Get,
+};
+use generic_asset::{AssetOptions, PermissionsV1};
+use crate::erc721;
+use crate::nfts;
+
+// account holding the vaulted tokens and the buyout proceeds
+const VAULT_MODULE_ID: ModuleId = ModuleId(*b"nft/vlt ");
+
+pub trait Trait: nfts::Trait + timestamp::Trait + generic_asset::Trait {
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    // how long a buyout auction runs after its first bid
+    type BuyoutPeriod: Get<Self::Moment>;
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub enum VaultStatus<AccountId, Balance, Moment> {
+    // token locked, shares trading freely
+    Locked,
+    // buyout running, `bid` of the buyout asset is reserved from `bidder`
+    Auction{ bidder: AccountId, bid: Balance, end: Moment },
+    // token sold, proceeds wait in the vault account for the remaining shares
+    Sold{ proceeds_left: Balance, shares_left: Balance },
+}
+
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct Vault<T> where T: Trait {
+    vault_id: T::Hash,
+    curator: T::AccountId, // depositor of the token
+    token_id: T::Hash,
+    share_asset: T::AssetId,
+    share_supply: T::Balance,
+    buyout_asset: T::AssetId, // asset buyout bids are paid in
+    reserve_price: T::Balance, // lowest acceptable buyout bid
+    status: VaultStatus<T::AccountId, T::Balance, T::Moment>,
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        <T as system::Trait>::AccountId,
+        <T as system::Trait>::Hash,
+        <T as generic_asset::Trait>::Balance,
+        <T as generic_asset::Trait>::AssetId
+    {
+        // curator, vault_id, token_id, share_asset, share_supply
+        VaultCreated(AccountId, Hash, Hash, AssetId, Balance),
+        // redeemer, vault_id, token_id
+        VaultRedeemed(AccountId, Hash, Hash),
+        // bidder, vault_id, bid
+        BuyoutBid(AccountId, Hash, Balance),
+        // buyer, vault_id, price
+        BuyoutSettled(AccountId, Hash, Balance),
+        // share holder, vault_id, shares burnt, proceeds paid
+        ProceedsClaimed(AccountId, Hash, Balance, Balance),
+        // vault_id, token_id reaped on expiry
+        VaultClosed(Hash, Hash),
+    }
+);
+
+decl_storage! {
+    trait Store for Module<T: Trait> as VaultStorage {
+        // vault id -> vault
+        Vaults get(get_vault): map T::Hash => Option<Vault<T>>;
+        // token id -> vault the token is locked in
+        TokenVault get(get_token_vault): map T::Hash => Option<T::Hash>;
+
+        Nonce: u64;
+    }
+}
+
+decl_module! {
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        fn deposit_event() = default;
+
+        const BuyoutPeriod: T::Moment = T::BuyoutPeriod::get();
+
+        // lock a token and mint `share_supply` shares of a new asset to the sender
+        pub fn vault_create(origin, token_id: T::Hash, share_supply: T::Balance, buyout_asset: T::AssetId, reserve_price: T::Balance) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_vault_create(sender, token_id, share_supply, buyout_asset, reserve_price)
+        }
+
+        // burn every share and take the token out
+        pub fn vault_redeem(origin, vault_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_vault_redeem(sender, vault_id)
+        }
+
+        // open or raise the buyout auction, the bid stays reserved until outbid
+        pub fn buyout_bid(origin, vault_id: T::Hash, bid: T::Balance) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_buyout_bid(sender, vault_id, bid)
+        }
+
+        // anyone may settle an auction once it is over
+        pub fn buyout_settle(origin, vault_id: T::Hash) -> Result {
+            let _ = ensure_signed(origin)?;
+            Self::_buyout_settle(vault_id)
+        }
+
+        // burn the shares of the sender for its part of the buyout proceeds
+        pub fn claim_proceeds(origin, vault_id: T::Hash) -> Result {
+            let sender = ensure_signed(origin)?;
+            Self::_claim_proceeds(sender, vault_id)
+        }
+
+        // anyone may close a vault whose token expired, the token is reaped and a running bid refunded
+        pub fn vault_close(origin, vault_id: T::Hash) -> Result {
+            let _ = ensure_signed(origin)?;
+            Self::_vault_close(vault_id)
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    pub fn account_id() -> T::AccountId {
+        VAULT_MODULE_ID.into_account()
+    }
+
+    fn _vault_create(curator: T::AccountId, token_id: T::Hash, share_supply: T::Balance, buyout_asset: T::AssetId, reserve_price: T::Balance) -> Result {
+        ensure!(!share_supply.is_zero(), "share supply can not be zero");
+        let token = match <nfts::Module<T>>::get_token(&token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        ensure!(Self::get_token_vault(&token_id).is_none(), "token already vaulted");
+        // everything the token move checks, so it can not fail once the share asset exists
+        let pot = Self::account_id();
+        ensure!(!<nfts::Module<T>>::_is_expired(&token_id), "token expired, reserve now is forbidden");
+        ensure!(<erc721::Module<T>>::owner_of(&token_id) == Some(curator.clone()), "You do not own this token");
+        ensure!(!<nfts::Module<T>>::get_token_reserve(&token_id), "token reserved, transfer now is forbidden");
+        ensure!(<nfts::Module<T>>::nft_perm((token.nft_id, pot.clone())) != Some(false), "nft blacklist contains to-account");
+
+        // Writing to storage begins here
+        let share_asset = <generic_asset::Module<T>>::next_asset_id();
+        let options = AssetOptions{
+            initial_issuance: share_supply,
+            permissions: PermissionsV1::default(),
+        };
+        <generic_asset::Module<T>>::create_asset(None, Some(curator.clone()), options)?;
+
+        // move the token into the vault account and lock it there
+        <nfts::Module<T>>::_escrow_token(curator.clone(), pot, token_id)?;
+
+        let nonce = Nonce::get();
+        let vault_id = (b"vault", &curator, token_id, nonce).using_encoded(<T as system::Trait>::Hashing::hash);
+        Nonce::mutate(|n| *n += 1);
+        let vault = Vault{
+            vault_id,
+            curator: curator.clone(),
+            token_id,
+            share_asset,
+            share_supply,
+            buyout_asset,
+            reserve_price,
+            status: VaultStatus::Locked,
+        };
+        <Vaults<T>>::insert(vault_id, vault);
+        <TokenVault<T>>::insert(token_id, vault_id);
+        Self::deposit_event(RawEvent::VaultCreated(curator, vault_id, token_id, share_asset, share_supply));
+        Ok(())
+    }
+
+    fn _vault_redeem(sender: T::AccountId, vault_id: T::Hash) -> Result {
+        let vault = match Self::get_vault(&vault_id){
+            Some(t) => t,
+            None => return Err("vault not found"),
+        };
+        ensure!(vault.status == VaultStatus::Locked, "vault is in a buyout, redeem now is forbidden");
+        let shares = <generic_asset::Module<T>>::free_balance(&vault.share_asset, &sender);
+        ensure!(shares == vault.share_supply, "every share is needed to redeem the token");
+
+        // Writing to storage begins here
+        // an expired token is reaped instead, the shares are burnt all the same
+        let delivered = <nfts::Module<T>>::_release_escrow(Self::account_id(), sender.clone(), vault.token_id)?;
+        <generic_asset::Module<T>>::slash(&vault.share_asset, &sender, shares);
+        <Vaults<T>>::remove(vault_id);
+        <TokenVault<T>>::remove(vault.token_id);
+        if delivered {
+            Self::deposit_event(RawEvent::VaultRedeemed(sender, vault_id, vault.token_id));
+        } else {
+            Self::deposit_event(RawEvent::VaultClosed(vault_id, vault.token_id));
+        };
+        Ok(())
+    }
+
+    fn _buyout_bid(bidder: T::AccountId, vault_id: T::Hash, bid: T::Balance) -> Result {
+        let mut vault = match Self::get_vault(&vault_id){
+            Some(t) => t,
+            None => return Err("vault not found"),
+        };
+        ensure!(!<nfts::Module<T>>::_is_expired(&vault.token_id), "token expired, buyout now is forbidden");
+        let now = <timestamp::Module<T>>::get();
+        let (end, prev) = match vault.status {
+            VaultStatus::Locked => {
+                ensure!(bid >= vault.reserve_price, "bid lower than the reserve price");
+                (now + T::BuyoutPeriod::get(), None)
+            },
+            VaultStatus::Auction{ bidder: ref prev_bidder, bid: best, end } => {
+                ensure!(now < end, "buyout auction is over");
+                ensure!(bid > best, "bid must be higher than the current one");
+                (end, Some((prev_bidder.clone(), best)))
+            },
+            VaultStatus::Sold{ .. } => return Err("vault already sold"),
+        };
+        // the new bid is reserved before the previous one goes back, a raised bid only reserves the difference
+        let to_reserve = match prev {
+            Some((ref prev_bidder, best)) if *prev_bidder == bidder => bid - best,
+            _ => bid,
+        };
+        <generic_asset::Module<T>>::reserve(&vault.buyout_asset, &bidder, to_reserve)?;
+        if let Some((prev_bidder, best)) = prev {
+            if prev_bidder != bidder {
+                let _ = <generic_asset::Module<T>>::unreserve(&vault.buyout_asset, &prev_bidder, best);
+            };
+        };
+        vault.status = VaultStatus::Auction{ bidder: bidder.clone(), bid, end };
+        <Vaults<T>>::insert(vault_id, vault);
+        Self::deposit_event(RawEvent::BuyoutBid(bidder, vault_id, bid));
+        Ok(())
+    }
+
+    fn _buyout_settle(vault_id: T::Hash) -> Result {
+        let mut vault = match Self::get_vault(&vault_id){
+            Some(t) => t,
+            None => return Err("vault not found"),
+        };
+        let (buyer, price) = match vault.status {
+            VaultStatus::Auction{ ref bidder, bid, end } => {
+                ensure!(<timestamp::Module<T>>::get() >= end, "buyout auction still running");
+                (bidder.clone(), bid)
+            },
+            _ => return Err("no buyout auction to settle"),
+        };
+        // an expired token can not be sold anymore
+        if <nfts::Module<T>>::_is_expired(&vault.token_id) {
+            return Self::_vault_close(vault_id)
+        };
+        let pot = Self::account_id();
+        let token = match <nfts::Module<T>>::get_token(&vault.token_id){
+            Some(t) => t,
+            None => return Err("token not found"),
+        };
+        if <nfts::Module<T>>::nft_perm((token.nft_id, buyer.clone())) == Some(false) {
+            // the buyer can not receive the token, the bid goes back and the vault stays open
+            let _ = <generic_asset::Module<T>>::unreserve(&vault.buyout_asset, &buyer, price);
+            vault.status = VaultStatus::Locked;
+            <Vaults<T>>::insert(vault_id, vault);
+            return Ok(())
+        };
+        ensure!(<generic_asset::Module<T>>::reserved_balance(&vault.buyout_asset, &buyer) >= price, "buyout bid no longer reserved");
+
+        // Writing to storage begins here
+        // the token goes to the buyer, the bid into the vault account
+        <nfts::Module<T>>::_release_escrow(pot.clone(), buyer.clone(), vault.token_id)?;
+        let _ = <generic_asset::Module<T>>::unreserve(&vault.buyout_asset, &buyer, price);
+        <generic_asset::Module<T>>::make_transfer(&vault.buyout_asset, &buyer, &pot, price)?;
+        <TokenVault<T>>::remove(vault.token_id);
+
+        vault.status = VaultStatus::Sold{ proceeds_left: price, shares_left: vault.share_supply };
+        <Vaults<T>>::insert(vault_id, vault);
+        Self::deposit_event(RawEvent::BuyoutSettled(buyer, vault_id, price));
+        Ok(())
+    }
+
+    fn _claim_proceeds(holder: T::AccountId, vault_id: T::Hash) -> Result {
+        let mut vault = match Self::get_vault(&vault_id){
+            Some(t) => t,
+            None => return Err("vault not found"),
+        };
+        let (proceeds_left, shares_left) = match vault.status {
+            VaultStatus::Sold{ proceeds_left, shares_left } => (proceeds_left, shares_left),
+            _ => return Err("vault not sold yet"),
+        };
+        let shares = <generic_asset::Module<T>>::free_balance(&vault.share_asset, &holder);
+        ensure!(!shares.is_zero(), "no share to claim with");
+        ensure!(shares <= shares_left, "more shares than outstanding");
+
+        // the last claimant takes whatever rounding left behind
+        let payout = if shares == shares_left {
+            proceeds_left
+        } else {
+            let payout = proceeds_left.saturated_into::<u128>()
+                .saturating_mul(shares.saturated_into::<u128>())
+                / shares_left.saturated_into::<u128>();
+            payout.saturated_into::<T::Balance>()
+        };
+        <generic_asset::Module<T>>::slash(&vault.share_asset, &holder, shares);
+        <generic_asset::Module<T>>::make_transfer(&vault.buyout_asset, &Self::account_id(), &holder, payout)?;
+
+        let shares_left = shares_left - shares;
+        if shares_left.is_zero() {
+            <Vaults<T>>::remove(vault_id);
+        } else {
+            vault.status = VaultStatus::Sold{ proceeds_left: proceeds_left - payout, shares_left };
+            <Vaults<T>>::insert(vault_id, vault);
+        };
+        Self::deposit_event(RawEvent::ProceedsClaimed(holder, vault_id, shares, payout));
+        Ok(())
+    }
+
+    fn _vault_close(vault_id: T::Hash) -> Result {
+        let vault = match Self::get_vault(&vault_id){
+            Some(t) => t,
+            None => return Err("vault not found"),
+        };
+        let refund = match vault.status {
+            VaultStatus::Locked => None,
+            VaultStatus::Auction{ ref bidder, bid, .. } => Some((bidder.clone(), bid)),
+            VaultStatus::Sold{ .. } => return Err("vault already sold"),
+        };
+        ensure!(<nfts::Module<T>>::_is_expired(&vault.token_id), "token not expired yet");
+
+        // Writing to storage begins here
+        // the expired token is reaped in the vault account
+        <nfts::Module<T>>::_release_escrow(Self::account_id(), vault.curator.clone(), vault.token_id)?;
+        if let Some((bidder, bid)) = refund {
+            let _ = <generic_asset::Module<T>>::unreserve(&vault.buyout_asset, &bidder, bid);
+        };
+        <Vaults<T>>::remove(vault_id);
+        <TokenVault<T>>::remove(vault.token_id);
+        Self::deposit_event(RawEvent::VaultClosed(vault_id, vault.token_id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        use primitives::{H256};
+        use support::{impl_outer_origin, parameter_types};
+        use sr_primitives::{traits::{BlakeTwo256, IdentityLookup}, testing::Header};
+        use sr_primitives::weights::Weight;
+        use sr_primitives::Perbill;
+
+        impl_outer_origin! {
+            pub enum Origin for Test {}
+        }
+
+        // For testing the module, we construct most of a mock runtime. This means
+        // first constructing a configuration type (`Test`) which `impl`s each of the
+        // configuration traits of modules we want to use.
+        #[derive(Clone, Eq, PartialEq, Debug)]
+        pub struct Test;
+        parameter_types! {
+            pub const BlockHashCount: u64 = 250;
+            pub const MaximumBlockWeight: Weight = 1024;
+            pub const MaximumBlockLength: u32 = 2 * 1024;
+            pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+        }
+        impl system::Trait for Test {
+            type Origin = Origin;
+            type Call = ();
+            type Index = u64;
+            type BlockNumber = u64;
+            type Hash = H256;
+            type Hashing = BlakeTwo256;
+            type AccountId = u64;
+            type Lookup = IdentityLookup<Self::AccountId>;
+            type Header = Header;
+            type Event = ();
+            type BlockHashCount = BlockHashCount;
+            type MaximumBlockWeight = MaximumBlockWeight;
+            type MaximumBlockLength = MaximumBlockLength;
+            type AvailableBlockRatio = AvailableBlockRatio;
+            type Version = ();
+        }
+        type Balance = u64;
+        parameter_types! {
+            pub const TransferFee: Balance = 0;
+            pub const CreationFee: Balance = 0;
+        }
+        impl balances::Trait for Test {
+            type Balance = Balance;
+            type OnFreeBalanceZero = ();
+            type OnNewAccount = ();
+            type Event = ();
+            type TransferPayment = ();
+            type DustRemoval = ();
+            type ExistentialDeposit = ();
+            type TransferFee = TransferFee;
+            type CreationFee = CreationFee;
+        }
+        impl erc721::Trait for Test{
+            type Event = ();
+            type Hooks = nfts::Module<Test>;
+        }
+        parameter_types! {
+            pub const ClassDeposit: Balance = 10;
+            pub const TokenDeposit: Balance = 2;
+            pub const DepositPerByte: Balance = 1;
+        }
+        impl nfts::Trait for Test{
+            type Event = ();
+            type Currency = balances::Module<Self>;
+            type ClassDeposit = ClassDeposit;
+            type TokenDeposit = TokenDeposit;
+            type DepositPerByte = DepositPerByte;
+        }
+        impl generic_asset::Trait for Test {
+                type Event = ();
+                type Balance = u64;
+                type AssetId = u32;
+        }
+        parameter_types! {
+            pub const MinimumPeriod: u64 = 5;
+        }
+        impl timestamp::Trait for Test {
+                type Moment = u64;
+                type OnTimestampSet = ();
+                type MinimumPeriod = MinimumPeriod;
+        }
+        parameter_types! {
+            pub const BuyoutPeriod: u64 = 100;
+        }
+        impl Trait for Test {
+            type Event = ();
+            type BuyoutPeriod = BuyoutPeriod;
+        }
+
+        // This function basically just builds a genesis storage key/value store according to
+        // our desired mockup.
+        fn new_test_ext() -> runtime_io::TestExternalities {
+            let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+            balances::GenesisConfig::<Test>{
+                balances: vec![(0, 1000), (1, 1000), (2, 1000), (3, 1000)],
+                vesting: vec![],
+            }.assimilate_storage(&mut t).unwrap();
+            t.into()
+        }
+
+        pub type NftsModule = nfts::Module<Test>;
+        pub type AssetsModule = generic_asset::Module<Test>;
+        pub type Erc721Module = erc721::Module<Test>;
+        pub type VaultModule = Module<Test>;
+        type Timestamp = timestamp::Module<Test>;
+
+        // bob vaults a fresh token into 100 shares, returns (vault_id, token_id, share_asset, pay_asset)
+        fn setup_vault() -> (H256, H256, u32, u32) {
+            let bob = 0;
+            let asset_issuer = 100;
+            let pay_asset = AssetsModule::next_asset_id();
+            let options = AssetOptions{
+                initial_issuance: 1000000,
+                permissions: PermissionsV1::default(),
+            };
+            AssetsModule::create_asset(None, Some(asset_issuer), options).unwrap();
+            for who in [1, 2].iter() {
+                AssetsModule::transfer(Origin::signed(asset_issuer), pay_asset, *who, 5000).unwrap();
+            };
+
+            NftsModule::nonfungible_create(Origin::signed(bob), "doggy".as_bytes().to_vec(), 10);
+            let nft_id = NftsModule::get_nft_by_index(0);
+            NftsModule::issue_token(Origin::signed(bob), nft_id, "token_0".as_bytes().to_vec());
+            let token_id = Erc721Module::token_by_index(0);
+            let share_asset = AssetsModule::next_asset_id();
+            let res = VaultModule::vault_create(Origin::signed(bob), token_id, 100, pay_asset, 1000);
+            assert_eq!(res, Ok(()));
+            let vault_id = VaultModule::get_token_vault(token_id).unwrap();
+            (vault_id, token_id, share_asset, pay_asset)
+        }
+
+        #[test]
+        fn vault_redeem_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let (vault_id, token_id, share_asset, _) = setup_vault();
+                let pot = VaultModule::account_id();
+                assert_eq!(Erc721Module::owner_of(token_id), Some(pot));
+                assert_eq!(NftsModule::get_token_reserve(token_id), true);
+                assert_eq!(AssetsModule::free_balance(&share_asset, &bob), 100);
+
+                // the token can not be vaulted twice nor leave the vault account
+                let res = VaultModule::vault_create(Origin::signed(bob), token_id, 100, 0, 0);
+                assert_eq!(res, Err("token already vaulted"));
+                let res = NftsModule::burn_token(Origin::signed(pot), token_id, 0);
+                assert_eq!(res, Err("token locked by a module, burn now is forbidden"));
+
+                AssetsModule::transfer(Origin::signed(bob), share_asset, alice, 1).unwrap();
+                let res = VaultModule::vault_redeem(Origin::signed(bob), vault_id);
+                assert_eq!(res, Err("every share is needed to redeem the token"));
+                AssetsModule::transfer(Origin::signed(alice), share_asset, bob, 1).unwrap();
+
+                let res = VaultModule::vault_redeem(Origin::signed(bob), vault_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(token_id), Some(bob));
+                assert_eq!(NftsModule::get_token_reserve(token_id), false);
+                assert_eq!(AssetsModule::free_balance(&share_asset, &bob), 0);
+                assert_eq!(VaultModule::get_vault(vault_id).is_none(), true);
+                assert_eq!(VaultModule::get_token_vault(token_id), None);
+            });
+        }
+
+        #[test]
+        fn vault_buyout_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                Timestamp::set_timestamp(10);
+                let (vault_id, token_id, share_asset, pay_asset) = setup_vault();
+                let pot = VaultModule::account_id();
+                // bob keeps 70 shares, alice gets 30
+                AssetsModule::transfer(Origin::signed(bob), share_asset, alice, 30).unwrap();
+
+                let res = VaultModule::buyout_bid(Origin::signed(jack), vault_id, 999);
+                assert_eq!(res, Err("bid lower than the reserve price"));
+                let res = VaultModule::buyout_bid(Origin::signed(jack), vault_id, 1000);
+                assert_eq!(res, Ok(()));
+                let res = VaultModule::buyout_bid(Origin::signed(alice), vault_id, 1000);
+                assert_eq!(res, Err("bid must be higher than the current one"));
+                let res = VaultModule::buyout_bid(Origin::signed(alice), vault_id, 1501);
+                assert_eq!(res, Ok(()));
+                // the outbid bidder gets its funds back
+                assert_eq!(AssetsModule::reserved_balance(&pay_asset, &jack), 0);
+                assert_eq!(AssetsModule::reserved_balance(&pay_asset, &alice), 1501);
+                // a bid that can not be reserved leaves the current one in place
+                let res = VaultModule::buyout_bid(Origin::signed(jack), vault_id, 6000);
+                assert!(res.is_err());
+                assert_eq!(AssetsModule::reserved_balance(&pay_asset, &alice), 1501);
+                assert_eq!(AssetsModule::reserved_balance(&pay_asset, &jack), 0);
+
+                let res = VaultModule::vault_redeem(Origin::signed(bob), vault_id);
+                assert_eq!(res, Err("vault is in a buyout, redeem now is forbidden"));
+                let res = VaultModule::buyout_settle(Origin::signed(jack), vault_id);
+                assert_eq!(res, Err("buyout auction still running"));
+
+                Timestamp::set_timestamp(110);
+                let res = VaultModule::buyout_bid(Origin::signed(jack), vault_id, 2000);
+                assert_eq!(res, Err("buyout auction is over"));
+                let res = VaultModule::buyout_settle(Origin::signed(jack), vault_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(token_id), Some(alice));
+                assert_eq!(NftsModule::get_token_reserve(token_id), false);
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &pot), 1501);
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &alice), 5000 - 1501);
+
+                // proceeds are paid pro rata, the last claimant takes the remainder
+                let res = VaultModule::claim_proceeds(Origin::signed(alice), vault_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &alice), 5000 - 1501 + 450);
+                assert_eq!(AssetsModule::free_balance(&share_asset, &alice), 0);
+                let res = VaultModule::claim_proceeds(Origin::signed(alice), vault_id);
+                assert_eq!(res, Err("no share to claim with"));
+                let res = VaultModule::claim_proceeds(Origin::signed(bob), vault_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &bob), 1051);
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &pot), 0);
+                assert_eq!(VaultModule::get_vault(vault_id).is_none(), true);
+            });
+        }
+
+        #[test]
+        fn vault_expired_token_test() {
+            new_test_ext().execute_with(|| {
+                let bob = 0;
+                let alice = 1;
+                let jack = 2;
+                Timestamp::set_timestamp(10);
+                let (vault_id, _, share_asset, pay_asset) = setup_vault();
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), NftsModule::get_nft_by_index(0), "token_1".as_bytes().to_vec(), 50).unwrap();
+                let expiring = Erc721Module::token_by_index(1);
+                let res = VaultModule::vault_create(Origin::signed(bob), expiring, 100, pay_asset, 1000);
+                assert_eq!(res, Ok(()));
+                let expiring_vault = VaultModule::get_token_vault(expiring).unwrap();
+
+                let res = VaultModule::vault_close(Origin::signed(jack), expiring_vault);
+                assert_eq!(res, Err("token not expired yet"));
+                let res = VaultModule::buyout_bid(Origin::signed(alice), expiring_vault, 1000);
+                assert_eq!(res, Ok(()));
+                let res = VaultModule::buyout_bid(Origin::signed(alice), vault_id, 1200);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&pay_asset, &alice), 2200);
+
+                // the token expired during the buyout, settling refunds the bid and closes the vault
+                Timestamp::set_timestamp(110);
+                let res = VaultModule::buyout_settle(Origin::signed(jack), expiring_vault);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(expiring), None);
+                assert_eq!(VaultModule::get_vault(expiring_vault).is_none(), true);
+                assert_eq!(VaultModule::get_token_vault(expiring), None);
+                assert_eq!(AssetsModule::reserved_balance(&pay_asset, &alice), 1200);
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &alice), 5000 - 1200);
+                let res = VaultModule::buyout_settle(Origin::signed(jack), expiring_vault);
+                assert_eq!(res, Err("vault not found"));
+
+                // the live token is sold as usual
+                let res = VaultModule::buyout_settle(Origin::signed(jack), vault_id);
+                assert_eq!(res, Ok(()));
+                assert_eq!(AssetsModule::reserved_balance(&pay_asset, &alice), 0);
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &alice), 5000 - 1200);
+                assert_eq!(AssetsModule::free_balance(&pay_asset, &VaultModule::account_id()), 1200);
+                let res = VaultModule::buyout_settle(Origin::signed(jack), vault_id);
+                assert_eq!(res, Err("no buyout auction to settle"));
+
+                // a locked vault whose token expired can be redeemed or closed
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), NftsModule::get_nft_by_index(0), "token_2".as_bytes().to_vec(), 150).unwrap();
+                let redeemed = Erc721Module::token_by_index(1);
+                let res = VaultModule::vault_create(Origin::signed(bob), redeemed, 100, pay_asset, 1000);
+                assert_eq!(res, Ok(()));
+                let redeemed_vault = VaultModule::get_token_vault(redeemed).unwrap();
+                NftsModule::issue_token_with_expiry(Origin::signed(bob), NftsModule::get_nft_by_index(0), "token_3".as_bytes().to_vec(), 150).unwrap();
+                let closed = Erc721Module::token_by_index(2);
+                let res = VaultModule::vault_create(Origin::signed(bob), closed, 100, pay_asset, 1000);
+                assert_eq!(res, Ok(()));
+                let closed_vault = VaultModule::get_token_vault(closed).unwrap();
+
+                Timestamp::set_timestamp(200);
+                let res = VaultModule::buyout_bid(Origin::signed(alice), closed_vault, 1000);
+                assert_eq!(res, Err("token expired, buyout now is forbidden"));
+                let redeemed_shares = share_asset + 2;
+                let res = VaultModule::vault_redeem(Origin::signed(bob), redeemed_vault);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(redeemed), None);
+                assert_eq!(AssetsModule::free_balance(&redeemed_shares, &bob), 0);
+                assert_eq!(VaultModule::get_vault(redeemed_vault).is_none(), true);
+                let res = VaultModule::vault_close(Origin::signed(jack), closed_vault);
+                assert_eq!(res, Ok(()));
+                assert_eq!(Erc721Module::owner_of(closed), None);
+                assert_eq!(VaultModule::get_vault(closed_vault).is_none(), true);
+                assert_eq!(VaultModule::get_token_vault(closed), None);
+            });
+        }
+}